@@ -0,0 +1,9 @@
+# metal in the bands of a height ramp, paint in between
+position = object_position
+height = component position y
+bands = ramp height -100 0 0 0  -40 0 0 0  -30 1 1 1  30 1 1 1  40 0 0 0
+output metallic bands
+gold = color 0.95 0.75 0.35
+paint = color 0.1 0.4 0.2
+color = mix paint gold bands
+output base_color color
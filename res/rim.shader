@@ -0,0 +1,8 @@
+# deep blue facing the camera, glowing orange rim
+facing = facing_ratio
+color = ramp facing 0 1 0.5 0.1  0.35 0.6 0.1 0.3  1 0.05 0.1 0.5
+output base_color color
+rim = fresnel 1.8
+glow = multiply rim color
+# the fixed emission of the material is black, so the glow doesn't light the scene
+output emission glow
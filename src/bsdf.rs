@@ -0,0 +1,669 @@
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::material::{ChannelValues, Material, TextureChannel, ThinFilm};
+use crate::math::Vector;
+use crate::spectrum::{stratified_wavelengths, wavelength_to_rgb, Wavelengths};
+
+// principled bsdf after "Physically Based Shading at Disney" (Burley 2012). the lobes are layered
+// so no light is created: the clearcoat takes its fresnel share first, the specular layer the
+// next and the diffuse base (with sheen and subsurface) only gets what is left of the rest.
+// transmission takes its share of the base away, the refracted ray itself is traced by the camera.
+#[derive(Debug, Clone, Copy)]
+pub struct Principled {
+    pub base_color: Color,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub ior: f32,
+    pub anisotropy: f32,
+    // radians, turns the direction of anisotropic highlights around the normal
+    pub anisotropy_rotation: f32,
+    // tangent of the surface at the hit, anisotropic highlights follow it
+    pub tangent: Option<Vector>,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub transmission: f32,
+    pub subsurface: f32,
+    pub thin_film: Option<ThinFilm>,
+    // the base scatters below the surface in a random walk the camera traces, instead of
+    // being diffuse
+    pub random_walk: bool,
+    // wavelengths of a spectral ray, the thin film is evaluated at them
+    pub wavelengths: Option<Wavelengths>,
+}
+
+impl Principled {
+    // base color, metallic and roughness come from the hit, they may be read from textures
+    pub fn new(material: &Material, base_color: Color, metallic: f32, roughness: f32) -> Principled {
+        Principled {
+            base_color,
+            metallic,
+            roughness,
+            ior: material.ior,
+            anisotropy: material.anisotropy,
+            anisotropy_rotation: material.anisotropy_rotation,
+            tangent: None,
+            specular_tint: material.specular_tint,
+            sheen: material.sheen,
+            sheen_tint: material.sheen_tint,
+            clearcoat: material.clearcoat,
+            clearcoat_roughness: material.clearcoat_roughness,
+            transmission: material.transmission,
+            subsurface: material.subsurface,
+            thin_film: material.thin_film,
+            random_walk: material.subsurface_scattering.is_some(),
+            wavelengths: None,
+        }
+    }
+
+    pub fn with_wavelengths(mut self, spectral: Option<&Wavelengths>) -> Principled {
+        self.wavelengths = spectral.copied();
+        self
+    }
+
+    pub fn with_tangent(mut self, tangent: Option<Vector>) -> Principled {
+        self.tangent = tangent;
+        self
+    }
+
+    // lobes driven by textures or the shader graph at the hit
+    pub fn with_channels(mut self, channels: &ChannelValues) -> Principled {
+        self.specular_tint = channels.scalar(TextureChannel::SpecularTint, self.specular_tint);
+        self.sheen = channels.scalar(TextureChannel::Sheen, self.sheen);
+        self.sheen_tint = channels.scalar(TextureChannel::SheenTint, self.sheen_tint);
+        self.clearcoat = channels.scalar(TextureChannel::Clearcoat, self.clearcoat);
+        self.clearcoat_roughness = channels.scalar(TextureChannel::ClearcoatRoughness, self.clearcoat_roughness);
+        self.transmission = channels.scalar(TextureChannel::Transmission, self.transmission);
+        self.subsurface = channels.scalar(TextureChannel::Subsurface, self.subsurface);
+        self.anisotropy = channels.scalar(TextureChannel::Anisotropy, self.anisotropy);
+        if let Some(film) = self.thin_film.as_mut() {
+            film.thickness *= channels.scalar(TextureChannel::FilmThickness, 1.0);
+        }
+        self
+    }
+
+    // share of the light refracted into the surface, metals don't transmit
+    pub fn transmission_weight(&self) -> f32 {
+        self.transmission * (1.0 - self.metallic)
+    }
+
+    // share of the light seen from v that comes from the base, below the specular layer and
+    // the clearcoat
+    pub fn base_weight(&self, n: &Vector, v: &Vector) -> Color {
+        let n_dot_v = n.dot(v).max(0.0);
+        let below_coat = 1.0 - self.clearcoat * lerp(0.04, 1.0, schlick_weight(n_dot_v));
+        (Color::white() - self.specular_fresnel(&self.specular_f0(), n_dot_v)) * ((1.0 - self.metallic) * (1.0 - self.transmission) * below_coat)
+    }
+
+    // base color with its luminance divided out
+    fn tint(&self) -> Color {
+        let luminance = 0.3 * self.base_color.r + 0.6 * self.base_color.g + 0.1 * self.base_color.b;
+        if luminance > 0.0 {
+            self.base_color / luminance
+        } else {
+            Color::white()
+        }
+    }
+
+    // tangent and bitangent of the anisotropic highlight. the tangent of the primitive is made
+    // perpendicular to n, any basis is only taken when the primitive has none
+    fn tangent_frame(&self, n: &Vector) -> (Vector, Vector) {
+        let surface_tangent = self.tangent.map(|tangent| tangent - *n * n.dot(&tangent)).filter(|tangent| tangent.length() > 1e-6);
+        let (t, b) = match surface_tangent {
+            Some(tangent) => {
+                let t = tangent._normalize();
+                (t, n.cross(&t))
+            }
+            None => n.orthonormal_basis(),
+        };
+        let (sin, cos) = self.anisotropy_rotation.sin_cos();
+        (t * cos + b * sin, b * cos - t * sin)
+    }
+
+    // reflectance at normal incidence of the specular layer
+    fn specular_f0(&self) -> Color {
+        let dielectric = ((self.ior - 1.0) / (self.ior + 1.0)).powi(2);
+        let dielectric_color = lerp_color(&Color::white(), &self.tint(), self.specular_tint) * dielectric;
+        lerp_color(&dielectric_color, &self.base_color, self.metallic)
+    }
+
+    // fresnel of the specular layer, cos_theta is between the light and the half vector. a thin
+    // film lies on a substrate with the ior that reflects f0 head on
+    fn specular_fresnel(&self, f0: &Color, cos_theta: f32) -> Color {
+        match &self.thin_film {
+            Some(film) => {
+                let substrate = Color::new(ior_from_f0(f0.r), ior_from_f0(f0.g), ior_from_f0(f0.b));
+                thin_film_fresnel(cos_theta, 1.0, film, &substrate, self.wavelengths.as_ref())
+            }
+            None => lerp_color(f0, &Color::white(), schlick_weight(cos_theta)),
+        }
+    }
+
+    // ggx roughness along the tangent and the bitangent
+    fn alphas(&self) -> (f32, f32) {
+        let aspect = (1.0 - 0.9 * self.anisotropy).sqrt();
+        let alpha = self.roughness * self.roughness;
+        ((alpha / aspect).max(0.001), (alpha * aspect).max(0.001))
+    }
+
+    // glossy reflection of the specular layer for the camera to trace. the direction is
+    // importance sampled from the visible normals, so the weight is just F * G2 / G1(v),
+    // scaled up by the energy the single scattering ggx misses. u1 and u2 are in 0..1
+    pub fn sample_reflection(&self, n: &Vector, v: &Vector, u1: f32, u2: f32) -> Option<(Vector, Color)> {
+        let n_dot_v = n.dot(v);
+        if n_dot_v <= 0.0 {
+            return None;
+        }
+        let (ax, ay) = self.alphas();
+        let (x, y) = self.tangent_frame(n);
+        let v_local = to_local(v, &x, &y, n);
+        let h_local = sample_vndf(&v_local, ax, ay, u1, u2);
+        let l_local = h_local * (2.0 * v_local.dot(&h_local)) - v_local;
+        if l_local.z <= 0.0 {
+            return None;
+        }
+        let f0 = self.specular_f0();
+        let fresnel = self.specular_fresnel(&f0, v_local.dot(&h_local));
+        let weight = smith_g2(&l_local, &v_local, ax, ay) / smith_g1(&v_local, ax, ay);
+        let single_albedo = ggx_albedo(n_dot_v, (ax * ay).sqrt());
+        let compensation = Color::white() + f0 * (1.0 / single_albedo - 1.0);
+        let below_coat = 1.0 - self.clearcoat * lerp(0.04, 1.0, schlick_weight(n_dot_v));
+        let l = x * l_local.x + y * l_local.y + *n * l_local.z;
+        Some((l, fresnel * compensation * (weight * below_coat)))
+    }
+
+    // density over solid angle of sample_reflection picking l, the visible normal density of
+    // the half vector over the jacobian of the reflection
+    pub fn reflection_pdf(&self, n: &Vector, v: &Vector, l: &Vector) -> f32 {
+        let (n_dot_l, n_dot_v) = (n.dot(l), n.dot(v));
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return 0.0;
+        }
+        let (ax, ay) = self.alphas();
+        let (x, y) = self.tangent_frame(n);
+        let h = to_local(&(*l + *v)._normalize(), &x, &y, n);
+        smith_g1(&to_local(v, &x, &y, n), ax, ay) * ggx_d(&h, ax, ay) / (4.0 * n_dot_v)
+    }
+
+    // bsdf times the cosine of the light direction. l points to the light, v to the viewer
+    pub fn evaluate(&self, n: &Vector, v: &Vector, l: &Vector) -> Color {
+        let (rest, reflection) = self.evaluate_lobes(n, v, l);
+        rest + reflection
+    }
+
+    // the part of evaluate that sample_reflection samples, the specular layer
+    pub fn evaluate_reflection(&self, n: &Vector, v: &Vector, l: &Vector) -> Color {
+        self.evaluate_lobes(n, v, l).1
+    }
+
+    // evaluate split into the lobes the camera only gets from lights and the specular layer
+    fn evaluate_lobes(&self, n: &Vector, v: &Vector, l: &Vector) -> (Color, Color) {
+        let n_dot_l = n.dot(l);
+        let n_dot_v = n.dot(v);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return (Color::black(), Color::black());
+        }
+        let h = (*l + *v)._normalize();
+        let n_dot_h = n.dot(&h);
+        let l_dot_h = l.dot(&h);
+        let (fl, fv, fh) = (schlick_weight(n_dot_l), schlick_weight(n_dot_v), schlick_weight(l_dot_h));
+
+        // diffuse, renormalized as in frostbite so it stays below an albedo of 1 when rough
+        let energy_bias = 0.5 * self.roughness;
+        let energy_factor = lerp(1.0, 1.0 / 1.51, self.roughness);
+        let fd90 = energy_bias + 2.0 * l_dot_h * l_dot_h * self.roughness;
+        let fd = lerp(1.0, fd90, fl) * lerp(1.0, fd90, fv) * energy_factor;
+        // hanrahan-krueger like flattening of the diffuse lobe for subsurface scattering
+        let fss90 = l_dot_h * l_dot_h * self.roughness;
+        let fss = lerp(1.0, fss90, fl) * lerp(1.0, fss90, fv);
+        let ss = 1.25 * (fss * (1.0 / (n_dot_l + n_dot_v) - 0.5) + 0.5);
+        // sheen lies on the diffuse like a layer of fibers, the diffuse gets what it lets through
+        let sheen = lerp_color(&Color::white(), &self.tint(), self.sheen_tint) * (self.sheen * fh);
+        let diffuse = if self.random_walk { Color::black() } else { self.base_color * (lerp(fd, ss, self.subsurface) / PI * (1.0 - self.sheen * fv)) };
+        let base = (diffuse + sheen) * ((1.0 - self.metallic) * (1.0 - self.transmission));
+
+        // anisotropic ggx specular with height correlated masking-shadowing, plus the light
+        // that bounces between the microfacets more than once (kulla-conty)
+        let f0 = self.specular_f0();
+        let (ax, ay) = self.alphas();
+        let (x, y) = self.tangent_frame(n);
+        let (l_local, v_local) = (to_local(l, &x, &y, n), to_local(v, &x, &y, n));
+        let ds = ggx_d(&to_local(&h, &x, &y, n), ax, ay);
+        let g2 = smith_g2(&l_local, &v_local, ax, ay);
+        let fs = self.specular_fresnel(&f0, l_dot_h);
+        let specular = fs * (ds * g2 / (4.0 * n_dot_l * n_dot_v)) + multiple_scattering(&f0, n_dot_l, n_dot_v, (ax * ay).sqrt());
+        // the base only sees the light the specular layer doesn't reflect
+        let base = base * (Color::white() - self.specular_fresnel(&f0, n_dot_v));
+
+        // isotropic clearcoat on top of everything
+        let coat_alpha = (self.clearcoat_roughness * self.clearcoat_roughness).max(0.001);
+        let fr = lerp(0.04, 1.0, fh);
+        let dr = gtr1(n_dot_h, coat_alpha);
+        let gr = smith_g(n_dot_l, coat_alpha) * smith_g(n_dot_v, coat_alpha);
+        let coat = self.clearcoat * fr * dr * gr;
+        let below_coat = 1.0 - self.clearcoat * lerp(0.04, 1.0, fv);
+
+        ((base * below_coat + Color::white() * coat) * n_dot_l, specular * (below_coat * n_dot_l))
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_color(a: &Color, b: &Color, t: f32) -> Color {
+    *a * (1.0 - t) + *b * t
+}
+
+// (1 - cos)^5 of schlick's fresnel approximation
+pub fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+// exact fresnel reflectance of a dielectric for unpolarized light. eta is the ratio of the
+// indices of refraction on the far and the near side
+pub fn dielectric_fresnel(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin_t2 = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin_t2 >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t2).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// hemispherical average of the fresnel reflectance for diffuse light, 2 * integral of F(mu) mu
+pub fn diffuse_fresnel(eta: f32) -> f32 {
+    let steps = 64;
+    (0..steps)
+        .map(|i| {
+            let mu = (i as f32 + 0.5) / steps as f32;
+            2.0 * dielectric_fresnel(mu, eta) * mu / steps as f32
+        })
+        .sum()
+}
+
+// reflectance of the boundary between two dielectrics for light coming from the outside ior,
+// with or without a thin film on it
+pub fn interface_fresnel(cos_i: f32, outside: f32, inside: f32, film: Option<&ThinFilm>, spectral: Option<&Wavelengths>) -> Color {
+    match film {
+        Some(film) => thin_film_fresnel(cos_i, outside, film, &Color::new(inside, inside, inside), spectral),
+        None => {
+            let reflectance = dielectric_fresnel(cos_i, inside / outside);
+            Color::new(reflectance, reflectance, reflectance)
+        }
+    }
+}
+
+// ---- thin film ----
+// wavelengths the film is averaged over for rgb rays
+const FILM_WAVELENGTHS: usize = 16;
+
+// reflectance of a thin film on a substrate, lit from a medium with the ior outside, at one
+// wavelength in nanometers. the light bouncing back and forth inside the film is summed up
+// with the airy formula for both polarizations, as in "A Practical Extension to Microfacet
+// Theory for the Modeling of Varying Iridescence" (Belcour, Barla 2017)
+pub fn thin_film_reflectance(cos_i: f32, outside: f32, film: &ThinFilm, substrate: f32, wavelength: f32) -> f32 {
+    let cos_1 = cos_i.clamp(0.0, 1.0);
+    let sin_1_2 = 1.0 - cos_1 * cos_1;
+    // snell's law, none for total internal reflection
+    let cos_in = |ior: f32| {
+        let sin2 = sin_1_2 * (outside / ior).powi(2);
+        (sin2 < 1.0).then(|| (1.0 - sin2).sqrt())
+    };
+    let (cos_2, cos_3) = match (cos_in(film.ior), cos_in(substrate)) {
+        (Some(cos_2), Some(cos_3)) => (cos_2, cos_3),
+        _ => return 1.0,
+    };
+    // phase between the light reflected at the bottom and at the top of the film
+    let phase = 4.0 * PI * film.ior * film.thickness * cos_2 / wavelength;
+    let airy = |r12: f32, r23: f32| {
+        let interference = 2.0 * r12 * r23 * phase.cos();
+        (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+    };
+    let s = |n1: f32, cos1: f32, n2: f32, cos2: f32| (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let p = |n1: f32, cos1: f32, n2: f32, cos2: f32| (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    0.5 * (airy(s(outside, cos_1, film.ior, cos_2), s(film.ior, cos_2, substrate, cos_3))
+        + airy(p(outside, cos_1, film.ior, cos_2), p(film.ior, cos_2, substrate, cos_3)))
+}
+
+// thin film reflectance per channel, substrate holds the ior below the film for every
+// channel. spectral rays take it at their wavelengths, rgb rays average it over the visible
+// range weighted by how much each wavelength adds to the channel
+pub fn thin_film_fresnel(cos_i: f32, outside: f32, film: &ThinFilm, substrate: &Color, spectral: Option<&Wavelengths>) -> Color {
+    if let Some(wavelengths) = spectral {
+        return Color::new(
+            thin_film_reflectance(cos_i, outside, film, substrate.r, wavelengths[0]),
+            thin_film_reflectance(cos_i, outside, film, substrate.g, wavelengths[1]),
+            thin_film_reflectance(cos_i, outside, film, substrate.b, wavelengths[2]),
+        );
+    }
+    let mut sum = Color::black();
+    let mut weights = Color::black();
+    for wavelength in stratified_wavelengths(FILM_WAVELENGTHS, 0.5) {
+        let weight = wavelength_to_rgb(wavelength);
+        sum += Color::new(
+            weight.r * thin_film_reflectance(cos_i, outside, film, substrate.r, wavelength),
+            weight.g * thin_film_reflectance(cos_i, outside, film, substrate.g, wavelength),
+            weight.b * thin_film_reflectance(cos_i, outside, film, substrate.b, wavelength),
+        );
+        weights += weight;
+    }
+    sum / weights
+}
+
+// ior of a dielectric reflecting f0 head on
+fn ior_from_f0(f0: f32) -> f32 {
+    let root = f0.clamp(0.0, 0.98).sqrt();
+    (1.0 + root) / (1.0 - root)
+}
+
+// generalized trowbridge-reitz with gamma 1, the long tailed distribution of the clearcoat
+fn gtr1(n_dot_h: f32, alpha: f32) -> f32 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * n_dot_h * n_dot_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+// "Taming the Shadow Terminator" (Chiang, Li, Burley 2019). a shading normal tilted towards
+// the light lights the surface right up to where the geometry shadows itself, this fades the
+// light out smoothly before that hard edge. 1 when both normals agree
+pub fn shadow_terminator(geometric: &Vector, shading: &Vector, l: &Vector) -> f32 {
+    let (geometric_l, shading_l) = (geometric.dot(l), shading.dot(l));
+    if geometric_l <= 0.0 || shading_l <= 0.0 {
+        return 0.0;
+    }
+    let g = (geometric_l / (shading_l * geometric.dot(shading).max(1e-4))).min(1.0);
+    -g * g * g + g * g + g
+}
+
+// ---- ggx ----
+// directions in the tangent frame have the normal along z
+fn to_local(w: &Vector, x: &Vector, y: &Vector, n: &Vector) -> Vector {
+    Vector::new(w.dot(x), w.dot(y), w.dot(n))
+}
+
+// anisotropic ggx distribution of the half vector h
+fn ggx_d(h: &Vector, ax: f32, ay: f32) -> f32 {
+    let d = (h.x / ax).powi(2) + (h.y / ay).powi(2) + h.z * h.z;
+    1.0 / (PI * ax * ay * d * d)
+}
+
+// smith lambda of anisotropic ggx
+fn smith_lambda(w: &Vector, ax: f32, ay: f32) -> f32 {
+    if w.z <= 0.0 {
+        return 0.0;
+    }
+    let tan2 = ((ax * w.x).powi(2) + (ay * w.y).powi(2)) / (w.z * w.z);
+    (-1.0 + (1.0 + tan2).sqrt()) * 0.5
+}
+
+fn smith_g1(w: &Vector, ax: f32, ay: f32) -> f32 {
+    1.0 / (1.0 + smith_lambda(w, ax, ay))
+}
+
+// height correlated masking-shadowing, microfacets hidden from the light and the viewer
+// at the same time are only counted once
+fn smith_g2(l: &Vector, v: &Vector, ax: f32, ay: f32) -> f32 {
+    1.0 / (1.0 + smith_lambda(l, ax, ay) + smith_lambda(v, ax, ay))
+}
+
+// "Sampling the GGX Distribution of Visible Normals" (Heitz 2018). returns a half vector in
+// the tangent frame, distributed like the normals visible from v
+pub fn sample_vndf(v: &Vector, ax: f32, ay: f32, u1: f32, u2: f32) -> Vector {
+    // stretch the view direction to the hemisphere configuration
+    let vh = Vector::new(ax * v.x, ay * v.y, v.z)._normalize();
+    let len2 = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if len2 > 0.0 { Vector::new(-vh.y, vh.x, 0.0) / len2.sqrt() } else { Vector::new(1.0, 0.0, 0.0) };
+    let t2 = vh.cross(&t1);
+    // point on the disk, squeezed to the visible half
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    // and back to the ellipsoid
+    Vector::new(ax * nh.x, ay * nh.y, nh.z.max(0.0))._normalize()
+}
+
+// ---- multiple scattering ----
+// "Revisiting Physically Based Shading at Imageworks" (Kulla, Conty 2017). tables of the
+// albedo of single scattering ggx with a perfect mirror fresnel, by cos(theta) and alpha
+const ALBEDO_TABLE_SIZE: usize = 32;
+
+struct AlbedoTables {
+    // [alpha][cos theta]
+    albedo: Vec<[f32; ALBEDO_TABLE_SIZE]>,
+    // cosine weighted hemispherical average of albedo per alpha
+    average: [f32; ALBEDO_TABLE_SIZE],
+}
+
+fn albedo_tables() -> &'static AlbedoTables {
+    static TABLES: OnceLock<AlbedoTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let samples = 24;
+        let mut albedo = Vec::with_capacity(ALBEDO_TABLE_SIZE);
+        let mut average = [0.0; ALBEDO_TABLE_SIZE];
+        for (i, average) in average.iter_mut().enumerate() {
+            let alpha = table_coordinate(i).max(0.001);
+            let mut row = [0.0; ALBEDO_TABLE_SIZE];
+            for (j, entry) in row.iter_mut().enumerate() {
+                let cos = table_coordinate(j).max(0.001);
+                let v = Vector::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+                let mut sum = 0.0;
+                // vndf sampling makes every sample worth G2 / G1
+                for a in 0..samples {
+                    for b in 0..samples {
+                        let u1 = (a as f32 + 0.5) / samples as f32;
+                        let u2 = (b as f32 + 0.5) / samples as f32;
+                        let h = sample_vndf(&v, alpha, alpha, u1, u2);
+                        let l = h * (2.0 * v.dot(&h)) - v;
+                        if l.z > 0.0 {
+                            sum += smith_g2(&l, &v, alpha, alpha) / smith_g1(&v, alpha, alpha);
+                        }
+                    }
+                }
+                *entry = sum / (samples * samples) as f32;
+            }
+            // 2 * integral of E(mu) mu dmu
+            *average = row.iter().enumerate().map(|(j, e)| 2.0 * e * table_coordinate(j).max(0.001)).sum::<f32>() / ALBEDO_TABLE_SIZE as f32;
+            albedo.push(row);
+        }
+        AlbedoTables { albedo, average }
+    })
+}
+
+// cell centers from 0 to 1
+fn table_coordinate(i: usize) -> f32 {
+    (i as f32 + 0.5) / ALBEDO_TABLE_SIZE as f32
+}
+
+// linear lookup of a table indexed by cell centers
+fn lookup(table: &[f32; ALBEDO_TABLE_SIZE], x: f32) -> f32 {
+    let x = (x * ALBEDO_TABLE_SIZE as f32 - 0.5).clamp(0.0, (ALBEDO_TABLE_SIZE - 1) as f32);
+    let i = (x as usize).min(ALBEDO_TABLE_SIZE - 2);
+    lerp(table[i], table[i + 1], x - i as f32)
+}
+
+// albedo of single scattering ggx, between the two closest alpha rows
+pub fn ggx_albedo(cos_theta: f32, alpha: f32) -> f32 {
+    let tables = albedo_tables();
+    let a = (alpha * ALBEDO_TABLE_SIZE as f32 - 0.5).clamp(0.0, (ALBEDO_TABLE_SIZE - 1) as f32);
+    let i = (a as usize).min(ALBEDO_TABLE_SIZE - 2);
+    lerp(lookup(&tables.albedo[i], cos_theta), lookup(&tables.albedo[i + 1], cos_theta), a - i as f32)
+}
+
+fn ggx_average_albedo(alpha: f32) -> f32 {
+    lookup(&albedo_tables().average, alpha)
+}
+
+// the energy single scattering loses, brought back as a diffuse-like lobe colored by the
+// average fresnel over all the bounces
+fn multiple_scattering(f0: &Color, n_dot_l: f32, n_dot_v: f32, alpha: f32) -> Color {
+    let e_avg = ggx_average_albedo(alpha);
+    if e_avg >= 1.0 {
+        return Color::black();
+    }
+    let lobe = (1.0 - ggx_albedo(n_dot_l, alpha)) * (1.0 - ggx_albedo(n_dot_v, alpha)) / (PI * (1.0 - e_avg));
+    // average of schlick's fresnel over the hemisphere
+    let f_avg = *f0 + (Color::white() - *f0) / 21.0;
+    let f_ms = f_avg * f_avg * e_avg / (Color::white() - f_avg * (1.0 - e_avg));
+    f_ms * lobe
+}
+
+fn smith_g(n_dot_v: f32, alpha: f32) -> f32 {
+    let a = alpha * alpha;
+    let b = n_dot_v * n_dot_v;
+    1.0 / (n_dot_v + (a + b - a * b).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // light reflected into v from uniform light of 1 over the hemisphere
+    fn albedo(bsdf: &Principled, v: &Vector) -> Color {
+        let n = Vector::new(0.0, 0.0, 1.0);
+        let steps = 200;
+        let mut sum = Color::black();
+        for i in 0..steps {
+            for j in 0..steps {
+                // uniform over the hemisphere, pdf 1 / 2pi
+                let z = (i as f32 + 0.5) / steps as f32;
+                let phi = 2.0 * PI * (j as f32 + 0.5) / steps as f32;
+                let r = (1.0 - z * z).sqrt();
+                let l = Vector::new(r * phi.cos(), r * phi.sin(), z);
+                sum += bsdf.evaluate(&n, v, &l) * (2.0 * PI);
+            }
+        }
+        sum / (steps * steps) as f32
+    }
+
+    #[test]
+    fn energy_conservation_test() {
+        let material = Material::new_pbr(Color::white(), 0.0, 0.5, 1.5, 0.0, 0.0)
+            .with_sheen(1.0, 0.0)
+            .with_clearcoat(1.0, 0.1);
+        for (metallic, roughness, subsurface) in [(0.0, 0.05, 0.0), (0.0, 1.0, 0.0), (1.0, 0.3, 0.0), (0.0, 0.6, 1.0), (0.5, 0.9, 0.5)] {
+            let mut bsdf = Principled::new(&material, Color::white(), metallic, roughness);
+            bsdf.subsurface = subsurface;
+            for cos in [1.0, 0.7, 0.3] {
+                let v = Vector::new((1.0_f32 - cos * cos).sqrt(), 0.0, cos);
+                let a = albedo(&bsdf, &v);
+                assert!(a.r <= 1.02 && a.g <= 1.02 && a.b <= 1.02, "{} at {} for {:?}", a, cos, (metallic, roughness, subsurface));
+            }
+        }
+        // a white diffuse surface doesn't lose much either
+        let bsdf = Principled::new(&Material::new_pbr(Color::white(), 0.0, 0.5, 1.0, 0.0, 0.0), Color::white(), 0.0, 0.5);
+        assert!(albedo(&bsdf, &Vector::new(0.0, 0.0, 1.0)).g > 0.75);
+    }
+
+    #[test]
+    fn furnace_test() {
+        // a white rough metal reflects everything once multiple scattering is added
+        let material = Material::new_pbr(Color::white(), 1.0, 1.0, 1.5, 0.0, 0.0);
+        for roughness in [0.3, 0.7, 1.0] {
+            let bsdf = Principled::new(&material, Color::white(), 1.0, roughness);
+            let a = albedo(&bsdf, &Vector::new(0.6, 0.0, 0.8));
+            assert!((a.g - 1.0).abs() < 0.03, "{} for roughness {}", a, roughness);
+        }
+        // single scattering alone loses energy when rough
+        assert!(ggx_albedo(0.8, 1.0) < 0.9);
+        assert!(ggx_albedo(0.8, 0.05) > 0.98);
+    }
+
+    #[test]
+    fn vndf_test() {
+        // sampled half vectors face the viewer and lie in the upper hemisphere
+        let v = Vector::new(0.6, 0.0, 0.8);
+        for (u1, u2) in [(0.1, 0.2), (0.5, 0.9), (0.99, 0.5)] {
+            let h = sample_vndf(&v, 0.3, 0.1, u1, u2);
+            assert!((h.length() - 1.0).abs() < 1e-4);
+            assert!(h.z >= 0.0 && h.dot(&v) > 0.0);
+        }
+    }
+
+    #[test]
+    fn reflection_pdf_test() {
+        let material = Material::new_pbr(Color::white(), 1.0, 0.3, 1.5, 0.0, 0.0);
+        let bsdf = Principled::new(&material, Color::new(0.9, 0.6, 0.3), 1.0, 0.3);
+        let (n, v) = (Vector::new(0.0, 0.0, 1.0), Vector::new(0.6, 0.0, 0.8));
+        // the density integrates to the share of reflections above the surface
+        let steps = 400;
+        let mut total = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let z = (i as f32 + 0.5) / steps as f32;
+                let phi = 2.0 * PI * (j as f32 + 0.5) / steps as f32;
+                let r = (1.0 - z * z).sqrt();
+                total += bsdf.reflection_pdf(&n, &v, &Vector::new(r * phi.cos(), r * phi.sin(), z)) * 2.0 * PI / (steps * steps) as f32;
+            }
+        }
+        assert!(total > 0.95 && total < 1.01, "{}", total);
+        // sampled weights are the reflection lobe over its density
+        for (u1, u2) in [(0.1, 0.2), (0.5, 0.9), (0.8, 0.4)] {
+            let (l, weight) = bsdf.sample_reflection(&n, &v, u1, u2).unwrap();
+            let expected = bsdf.evaluate_reflection(&n, &v, &l) / bsdf.reflection_pdf(&n, &v, &l);
+            assert!((weight.g - expected.g).abs() < 0.05 * expected.g, "{} {}", weight, expected);
+        }
+    }
+
+    #[test]
+    fn tangent_frame_test() {
+        let material = Material::new_pbr(Color::white(), 1.0, 0.3, 1.5, 0.0, 0.0);
+        let n = Vector::new(0.0, 0.0, 1.0);
+        // the tangent of the primitive is made perpendicular to n and then turned by the rotation
+        let bsdf = Principled::new(&material, Color::white(), 1.0, 0.3).with_tangent(Some(Vector::new(1.0, 0.0, 1.0)));
+        let (t, b) = bsdf.tangent_frame(&n);
+        assert!((t.x - 1.0).abs() < 1e-5 && (b.y - 1.0).abs() < 1e-5, "{:?} {:?}", t, b);
+        let mut rotated = bsdf;
+        rotated.anisotropy_rotation = PI / 2.0;
+        let (t, _) = rotated.tangent_frame(&n);
+        assert!((t.y - 1.0).abs() < 1e-5, "{:?}", t);
+        // a tangent along n gives none, any basis is taken
+        let (t, b) = bsdf.with_tangent(Some(n)).tangent_frame(&n);
+        assert!(t.dot(&n).abs() < 1e-5 && b.dot(&n).abs() < 1e-5 && t.dot(&b).abs() < 1e-5);
+    }
+
+    #[test]
+    fn dielectric_fresnel_test() {
+        assert!((dielectric_fresnel(1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert_eq!(dielectric_fresnel(0.1, 1.0 / 1.5), 1.0);
+        assert!(dielectric_fresnel(0.05, 1.5) > 0.7);
+        // about 9% for glass from the outside, total internal reflection raises it inside
+        assert!((diffuse_fresnel(1.5) - 0.092).abs() < 0.005);
+        assert!(diffuse_fresnel(1.0 / 1.5) > 0.55);
+    }
+
+    #[test]
+    fn thin_film_test() {
+        // without thickness or with the ior of the outside the film is invisible
+        for cos in [1.0, 0.7, 0.2] {
+            let plain = dielectric_fresnel(cos, 1.5);
+            let vanished = thin_film_reflectance(cos, 1.0, &ThinFilm { thickness: 0.0, ior: 1.33 }, 1.5, 550.0);
+            let air = thin_film_reflectance(cos, 1.0, &ThinFilm { thickness: 300.0, ior: 1.0 }, 1.5, 550.0);
+            assert!((vanished - plain).abs() < 1e-4 && (air - plain).abs() < 1e-4, "{} {} {}", plain, vanished, air);
+        }
+        // a quarter wave anti reflection coating cancels green, but not violet
+        let coating = ThinFilm { thickness: 550.0 / (4.0 * 1.5f32.sqrt()), ior: 1.5f32.sqrt() };
+        assert!(thin_film_reflectance(1.0, 1.0, &coating, 1.5, 550.0) < 1e-4);
+        assert!(thin_film_reflectance(1.0, 1.0, &coating, 1.5, 400.0) > 0.005);
+        // a soap bubble is colored, and stays in 0..1
+        let soap = ThinFilm { thickness: 400.0, ior: 1.33 };
+        let color = thin_film_fresnel(0.8, 1.0, &soap, &Color::white(), None);
+        assert!((color.r - color.b).abs() > 0.01, "{}", color);
+        for channel in [color.r, color.g, color.b] {
+            assert!((0.0..=1.0).contains(&channel));
+        }
+    }
+}
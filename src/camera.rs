@@ -1,19 +1,44 @@
 use std::collections::HashMap;
 use std::f32::consts::PI;
 use std::path;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread::Thread;
+use std::time::{Duration, Instant};
 
 use float_cmp::F32Margin;
 use image::Pixel;
 
+use crate::bsdf::{interface_fresnel, shadow_terminator, Principled};
 use crate::buffer::Buffer;
 use crate::color::Color;
-use crate::geometry::Line;
-use crate::light::{LightCalculationData, LightType};
-use crate::material::{self, Material, MaterialType};
-use crate::math::{Quaternion, RayCastHit, Vector};
+use crate::geometry::{Line, RayDifferentials};
+use crate::light::{Light, LightCalculationData, LightType};
+use crate::material::{self, resolve_composite, Material, MaterialType, TextureChannel};
+use crate::math::{RayCastHit, Vector};
+use crate::medium::{Medium, MediumSegment, Volume};
+use crate::mipmap::Footprint;
 use crate::scene::Scene;
+use crate::spectrum::{emission_for, hero_wavelengths, reflectance_for, spectral_to_xyz, stratified_wavelengths, wavelength_to_rgb, ColorSpace};
+use crate::stats::{print_progress, ProgressCallback, ProgressCounters, RenderProgress, RenderReport, RenderStats};
+use crate::shader::ShaderInput;
+use crate::texture::TextureInput;
+
+// number of pixel rows rendered by a thread in one go. threads pick up tiles
+// from a shared counter, so faster threads simply render more of them.
+const TILE_ROWS: i32 = 8;
+
+// number of wavelengths a ray is split into when it hits a dispersive material
+const DISPERSION_SAMPLES: usize = 8;
+
+// hero wavelength samples per camera ray in spectral mode, stratified over the visible range
+const SPECTRAL_SAMPLES: usize = 4;
+
+// random walks per hit on a subsurface scattering material
+const SUBSURFACE_WALKS: usize = 16;
+
+// upper limit of samples along one ray through participating media
+const MAX_MARCH_STEPS: f32 = 512.0;
 
 #[derive(Clone, PartialEq, Eq, Copy, Debug)]
 pub enum AntiAliasingType {
@@ -50,6 +75,14 @@ pub struct Camera {
     pub antialias_debug_buffer: Buffer,
     pub aa_type: AntiAliasingType,
     pub max_bounces: i32,
+    pub thread_count: usize,
+    // traces SPECTRAL_SAMPLES sets of three hero wavelengths per camera ray instead of rgb and
+    // converts to color_space per pixel. tonemapping happens once per pixel then, for all materials
+    pub spectral: bool,
+    pub color_space: ColorSpace,
+    // called periodically during multithreaded rendering. None prints progress to the terminal.
+    pub progress_callback: Option<ProgressCallback>,
+    pub last_report: Option<RenderReport>,
 }
 
 impl Camera {
@@ -71,6 +104,11 @@ impl Camera {
             antialias_debug_buffer: Buffer::new(width as u32, height as u32),
             aa_type: AntiAliasingType::None,
             max_bounces: 4,
+            thread_count: 16,
+            spectral: false,
+            color_space: ColorSpace::Srgb,
+            progress_callback: None,
+            last_report: None,
         }
     }
 
@@ -79,7 +117,28 @@ impl Camera {
         self.materials.len() - 1
     }
 
-    pub fn render_scene(&mut self, scene: &Scene, name: &str) {
+    pub fn set_progress_callback(&mut self, callback: impl Fn(&RenderProgress) + 'static) {
+        self.progress_callback = Some(Box::new(callback));
+    }
+
+    fn report_progress(&self, counters: &ProgressCounters, tiles_total: usize, start: Instant) {
+        let progress = counters.snapshot(tiles_total, start);
+        match &self.progress_callback {
+            Some(callback) => callback(&progress),
+            None => print_progress(&progress),
+        }
+    }
+
+    // everything the scene needs from the materials before rendering: the emitters, the light
+    // tree over the lights and the caustic photons. returns how many photons were stored
+    fn prepare_scene(&self, scene: &mut Scene, stats: &mut RenderStats) -> usize {
+        scene.register_emitters(&self.materials);
+        scene.build_light_tree();
+        scene.trace_caustics(&self.materials, stats);
+        scene.caustics.as_ref().map_or(0, |caustics| caustics.stored_photons())
+    }
+
+    pub fn render_scene(&mut self, scene: &mut Scene, name: &str) {
         let mut path_specs = String::from(name);
         if self.perspective {
             path_specs += "_perspective_";
@@ -94,6 +153,8 @@ impl Camera {
         self.buffer.clear_color(Color::black());
 
         let time = std::time::Instant::now();
+        let mut stats = RenderStats::new();
+        let caustic_photons = self.prepare_scene(scene, &mut stats);
 
         if self.aa_type == AntiAliasingType::Supersampling4x {
             // Supersampling means: Render at twice the resolution and then shrink by two, interpolating the colors
@@ -109,7 +170,8 @@ impl Camera {
                     if self.aa_type == AntiAliasingType::Supersampling4x {
                         ray.point /= 2.0;
                     }
-                    let color = self.shoot_ray(&ray, scene);
+                    stats.camera_rays += 1;
+                    let color = drop_nan(self.shoot_ray(&ray, scene, &mut stats), &mut stats);
                     if color.is_some() {
                         self.set_pixel_ji(j, i, color.unwrap());
                     }
@@ -127,7 +189,8 @@ impl Camera {
 
                     ray.direction = Vector::from_points(pinhole_position, ray.point);
                     
-                    let color = self.shoot_ray(&ray, scene);
+                    stats.camera_rays += 1;
+                    let color = drop_nan(self.shoot_ray(&ray, scene, &mut stats), &mut stats);
                     if color.is_some() {
                         self.set_pixel_ji(j, i, color.unwrap());
                     }
@@ -187,7 +250,8 @@ impl Camera {
                         if self.perspective {
                             ray.direction = Vector::from_points(pinhole_position, ray.point);
                         }
-                        let color = self.shoot_ray(&ray, scene);
+                        stats.camera_rays += 1;
+                        let color = drop_nan(self.shoot_ray(&ray, scene, &mut stats), &mut stats);
                         if color.is_some() {
                             hit_colors.push(color.unwrap());
                         }
@@ -218,10 +282,18 @@ impl Camera {
 
         println!("Rendering took: {}ms", time.elapsed().as_millis());
 
+        let mut report = RenderReport::new(1, 1);
+        report.stats = stats;
+        report.caustic_photons = caustic_photons;
+        report.add_phase("render", time.elapsed());
+
         if self.aa_type == AntiAliasingType::Supersampling4x {
             self.buffer.shrink_by_two();
         }
         self.buffer.save(path_specs.as_str());
+        report.print();
+        report.save_json(&format!("{}_stats.json", path_specs.trim_end_matches(".png")));
+        self.last_report = Some(report);
     }
 
     pub fn add_pixel_ji(&mut self, j: i32, i: i32, color: Color) {
@@ -256,10 +328,11 @@ impl Camera {
         self.debug.clone()
     }
 
-    pub fn shoot_ray(&mut self, ray: &Line, scene: &Scene) -> Option<Color> {
+    pub fn shoot_ray(&mut self, ray: &Line, scene: &Scene, stats: &mut RenderStats) -> Option<Color> {
         let mut closest_intersection = RayCastHit::new(None);
         let mut closest_distance = 0.0;
         let mut closest_material_idx = 0;
+        stats.intersection_tests += scene.primitives.len() as u64;
         for (i, primitive) in scene.primitives.iter().enumerate() {
             let hit = primitive.intersect(&ray);
             if hit.is_some() {
@@ -295,6 +368,7 @@ impl Camera {
                 base_color: material.base_color,
                 shininess: material.shininess,
                 specular_amount: material.specular_amount,
+                spectral: None,
             };
 
             for light in scene.lights.iter() {
@@ -308,7 +382,7 @@ impl Camera {
                     let line_pos = intersection + light_dir * 0.01;
                     let light_ray = Line::new(line_pos, light_dir);
                     let distance = intersection.distance(&light.position);
-                    let shadowed = shoot_ray_into_light(&light_ray, scene, distance);
+                    let shadowed = shoot_ray_into_light(&light_ray, scene, distance, stats);
 
                     if !shadowed {
                         let light_color = light.calculate_lighting(&lighting_data);
@@ -338,6 +412,7 @@ impl Camera {
         self.buffer.clear_color(Color::black());
 
         let time = std::time::Instant::now();
+        let mut phase_start = Instant::now();
 
         if self.aa_type == AntiAliasingType::Supersampling4x {
             // Supersampling means: Render at twice the resolution and then shrink by two, interpolating the colors
@@ -345,29 +420,40 @@ impl Camera {
             self.render_height *= 2;
             self.buffer = Buffer::new(self.render_width as u32, self.render_height as u32);
         }
-        // create two threads
         let mut handles = vec![];
 
+        let mut scene = scene;
+        let mut stats = RenderStats::new();
+        let caustic_photons = self.prepare_scene(&mut scene, &mut stats);
         // Arc is Rust's read-only shared pointer
         let scene_arc = Arc::new(scene);
 
+        // the image is split into strips of TILE_ROWS rows, from the bottom row up
+        let mut tiles = Vec::new();
+        let mut min_i = -self.render_height / 2;
+        while min_i < self.render_height / 2 {
+            let max_i = (min_i + TILE_ROWS).min(self.render_height / 2);
+            tiles.push((min_i, max_i));
+            min_i = max_i;
+        }
+        let tiles = Arc::new(tiles);
+        let counters = Arc::new(ProgressCounters::new());
+
         let mut thread_data_vec: Vec<ThreadRenderDara> = Vec::new();
         // for 2 threads, time decreases by about half, for 4 threads, time decreases by about 1/4
         // but futher the time doesn't decrease linearly. 10x decrease is seen for 16 thread.
         // after than the time only increases
-        let thread_nums = 16;
+        let thread_nums = self.thread_count.max(1);
         println!("rendering with {} threads", thread_nums);
+        let mut report = RenderReport::new(thread_nums, tiles.len());
+        report.caustic_photons = caustic_photons;
 
-        for i in 0..thread_nums {
-            let min_i = -self.render_height / 2 + (self.render_height / thread_nums) * i;
-            let max_i = -self.render_height / 2 + (self.render_height / thread_nums) * (i + 1);
-            let min_j = -self.render_width / 2;
-            let max_j = self.render_width / 2;
+        for _ in 0..thread_nums {
             let thread_data = ThreadRenderDara {
-                min_i,
-                max_i,
-                min_j,
-                max_j,
+                tiles: tiles.clone(),
+                progress: counters.clone(),
+                min_j: -self.render_width / 2,
+                max_j: self.render_width / 2,
                 position: self.position,
                 up: new_up,
                 right: self.right,
@@ -379,9 +465,13 @@ impl Camera {
                 scene: scene_arc.clone(),
                 sky_color: self.buffer.clear_color,
                 max_bounces: self.max_bounces,
+                spectral: self.spectral,
+                color_space: self.color_space,
             };
             thread_data_vec.push(thread_data);
         }
+        report.add_phase("setup", phase_start.elapsed());
+        phase_start = Instant::now();
 
         for thread_data in thread_data_vec {
             let handle = std::thread::spawn(move || {
@@ -390,22 +480,31 @@ impl Camera {
             handles.push(handle);
         }
 
-        let mut output_pixels: Vec<Option<Color>> = Vec::new();
-
-        for handle in handles {
-            let output = handle.join().unwrap();
-            for  color in output {
-                output_pixels.push(color);
+        loop {
+            let finished = handles.iter().all(|handle| handle.is_finished());
+            self.report_progress(&counters, tiles.len(), phase_start);
+            if finished {
+                break;
             }
+            std::thread::sleep(Duration::from_millis(250));
         }
+        println!();
 
-        for (i, color) in output_pixels.iter().enumerate() {
-            if color.is_some() {
-                self.buffer.write_pixel_by_idx(i, color.unwrap());
-            } else {
-                //println!("no color for pixel {}", i);
+        let row_width = (self.render_width / 2 - (-self.render_width / 2)) as usize;
+        for handle in handles {
+            let (rendered_tiles, thread_stats) = handle.join().unwrap();
+            stats.merge(&thread_stats);
+            for (tile_idx, output) in rendered_tiles {
+                let offset = tile_idx * TILE_ROWS as usize * row_width;
+                for (i, color) in output.iter().enumerate() {
+                    if color.is_some() {
+                        self.buffer.write_pixel_by_idx(offset + i, color.unwrap());
+                    }
+                }
             }
         }
+        report.add_phase("render", phase_start.elapsed());
+        phase_start = Instant::now();
 
         if self.aa_type == AntiAliasingType::AdaptiveX || self.aa_type == AntiAliasingType::AdaptiveO {
             // pixels (x, y) marked for additional rays.
@@ -459,7 +558,8 @@ impl Camera {
                         if self.perspective {
                             ray.direction = Vector::from_points(pinhole_position, ray.point);
                         }
-                        let color = self.shoot_ray(&ray, &scene_arc);
+                        stats.camera_rays += 1;
+                        let color = drop_nan(self.shoot_ray(&ray, &scene_arc, &mut stats), &mut stats);
                         if color.is_some() {
                             hit_colors.push(color.unwrap());
                         }
@@ -485,18 +585,29 @@ impl Camera {
             }
         }
 
+        report.add_phase("antialiasing", phase_start.elapsed());
+        phase_start = Instant::now();
+
         println!("Rendering took: {}ms", time.elapsed().as_millis());
 
         if self.aa_type == AntiAliasingType::Supersampling4x {
             self.buffer.shrink_by_two();
         }
+        report.add_phase("post-process", phase_start.elapsed());
+        phase_start = Instant::now();
+
         self.buffer.save(path_specs.as_str());
+        report.add_phase("save", phase_start.elapsed());
+        report.stats = stats;
+        report.print();
+        report.save_json(&format!("{}_stats.json", path_specs.trim_end_matches(".png")));
+        self.last_report = Some(report);
     }
 }
 
 pub struct ThreadRenderDara {
-    pub min_i: i32,
-    pub max_i: i32,
+    pub tiles: Arc<Vec<(i32, i32)>>,
+    pub progress: Arc<ProgressCounters>,
     pub min_j: i32,
     pub max_j: i32,
     pub position: Vector,
@@ -510,52 +621,121 @@ pub struct ThreadRenderDara {
     pub scene: Arc<Scene>,
     pub sky_color: Color,
     pub max_bounces: i32,
+    pub spectral: bool,
+    pub color_space: ColorSpace,
+}
+
+// tile index and the colors of its pixels, row by row
+pub type RenderedTile = (usize, Vec<Option<Color>>);
+
+// renders tiles until there are none left. returns every rendered tile together with its index
+pub fn render_thread(data: ThreadRenderDara) -> (Vec<RenderedTile>, RenderStats) {
+    let mut rendered = Vec::new();
+    let mut stats = RenderStats::new();
+    loop {
+        let tile_idx = data.progress.next_tile.fetch_add(1, Ordering::Relaxed);
+        if tile_idx >= data.tiles.len() {
+            break;
+        }
+        let (min_i, max_i) = data.tiles[tile_idx];
+        let rays_before = stats.total_rays();
+        let output = render_tile(&data, min_i, max_i, &mut stats);
+        rendered.push((tile_idx, output));
+
+        data.progress.rays_traced.fetch_add(stats.total_rays() - rays_before, Ordering::Relaxed);
+        data.progress.tiles_done.fetch_add(1, Ordering::Relaxed);
+    }
+    (rendered, stats)
 }
 
-pub fn render_thread(data: ThreadRenderDara) -> Vec<Option<Color>>{
+pub fn render_tile(data: &ThreadRenderDara, min_i: i32, max_i: i32, stats: &mut RenderStats) -> Vec<Option<Color>> {
     let mut output: Vec<Option<Color>> = Vec::new();
-    if !data.perspective {
-        let pinhole_position = data.position - data.forward * data.pinhole_distance;
-        let mut ray = Line::new(data.position, data.forward);
-        for i in data.min_i..data.max_i {
-            for j in data.min_j..data.max_j {
-                ray.point = data.position + data.up * i as f32 + data.right * j as f32;
-                if data.aa_type == AntiAliasingType::Supersampling4x {
-                    ray.point /= 2.0;
-                }
-                let color = p_shoot_ray(&ray, pinhole_position, &data.scene, &data.materials, data.max_bounces, data.sky_color);
-                output.push(color);
+    let pinhole_position = data.position - data.forward * data.pinhole_distance;
+    let mut ray = Line::new(data.position, data.forward);
+    for i in min_i..max_i {
+        for j in data.min_j..data.max_j {
+            ray.point = data.position + data.up * i as f32 + data.right * j as f32;
+            if data.aa_type == AntiAliasingType::Supersampling4x {
+                ray.point /= 2.0;
             }
-        }
-    } else {
-        let pinhole_position = data.position - data.forward * data.pinhole_distance;
-        let mut ray = Line::new(data.position, data.forward);
-        for (ci, i) in (data.min_i..data.max_i).enumerate() {
-            for (cj, j) in (data.min_j..data.max_j).enumerate() {
+            if data.perspective {
                 //'pinhole' camera rendering
-                ray.point = data.position + data.up * i as f32 + data.right * j as f32;
-                if data.aa_type == AntiAliasingType::Supersampling4x {
-                    ray.point /= 2.0;
-                }
-
                 ray.direction = Vector::from_points(pinhole_position, ray.point)._normalize();
-
-                let mut color = p_shoot_ray(&ray, pinhole_position, &data.scene, &data.materials, data.max_bounces, data.sky_color);
-                output.push(color);
             }
+            // rays through the next pixel to the right and below, for texture filtering
+            let step = if data.aa_type == AntiAliasingType::Supersampling4x { 0.5 } else { 1.0 };
+            let (x_point, y_point) = (ray.point + data.right * step, ray.point + data.up * step);
+            let direction_through = |point: Vector| if data.perspective { Vector::from_points(pinhole_position, point)._normalize() } else { ray.direction };
+            ray.differentials = Some(RayDifferentials {
+                x_point,
+                x_direction: direction_through(x_point),
+                y_point,
+                y_direction: direction_through(y_point),
+            });
+
+            stats.camera_rays += 1;
+            let color = if data.spectral {
+                shoot_spectral_ray(&ray, pinhole_position, data, stats)
+            } else {
+                let color = p_shoot_ray(&ray, pinhole_position, &data.scene, &data.materials, data.max_bounces, data.sky_color, stats);
+                if data.scene.glossy_reflections { color.map(tonemap) } else { color }
+            };
+            stats.finish_path(data.max_bounces);
+            output.push(drop_nan(color, stats));
         }
     }
-
     output
 }
 
-pub fn p_shoot_ray(ray: &Line, pinhole_position: Vector, scene: &Scene, materials: &Vec<Material>, max_bounces: i32, sky_color: Color) -> Option<Color> {
+// samples that came out NaN are counted and left out, the pixel keeps the background
+fn drop_nan(color: Option<Color>, stats: &mut RenderStats) -> Option<Color> {
+    if color.is_some_and(|c| c.is_nan()) {
+        stats.nan_samples += 1;
+        return None;
+    }
+    color
+}
+
+// traces the camera ray for SPECTRAL_SAMPLES sets of hero wavelengths and returns the
+// tonemapped color in the output color space. None if the ray hits nothing
+pub fn shoot_spectral_ray(ray: &Line, pinhole_position: Vector, data: &ThreadRenderDara, stats: &mut RenderStats) -> Option<Color> {
+    let jitter = rand::random::<f32>();
+    let mut xyz = Vector::new(0.0, 0.0, 0.0);
+    let mut hit = false;
+    for i in 0..SPECTRAL_SAMPLES {
+        let mut spectral_ray = *ray;
+        let wavelengths = hero_wavelengths((i as f32 + jitter) / (3 * SPECTRAL_SAMPLES) as f32);
+        spectral_ray.spectral = Some(wavelengths);
+        let samples = p_shoot_ray(&spectral_ray, pinhole_position, &data.scene, &data.materials, data.max_bounces, data.sky_color, stats);
+        if let Some(samples) = samples {
+            xyz += spectral_to_xyz(&samples, &wavelengths);
+            hit = true;
+        }
+    }
+    hit.then(|| resolve_spectral(&(xyz / SPECTRAL_SAMPLES as f32), data.color_space))
+}
+
+// tonemapped color of the output color space for the XYZ of a pixel
+pub fn resolve_spectral(xyz: &Vector, color_space: ColorSpace) -> Color {
+    let rgb = color_space.xyz_to_rgb(xyz);
+    // colors outside of the gamut come out negative
+    tonemap(Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0)))
+}
+
+pub fn p_shoot_ray(ray: &Line, pinhole_position: Vector, scene: &Scene, materials: &Vec<Material>, max_bounces: i32, sky_color: Color, stats: &mut RenderStats) -> Option<Color> {
+    let spectral = ray.spectral.as_ref();
+    // multiple importance sampling weights have to add up in linear radiance, without glossy
+    // reflections rgb rendering tonemaps every hit
+    let linear = keeps_linear(ray, scene);
     if max_bounces == -1 {
-        return Some(sky_color);
+        return Some(background(ray, scene, sky_color));
     }
+    stats.record_bounce(max_bounces);
     let mut closest_intersection = RayCastHit::new(None);
     let mut closest_distance = 0.0;
     let mut closest_material_idx = 0;
+    let mut closest_primitive_idx = 0;
+    stats.intersection_tests += scene.primitives.len() as u64;
     for (i, primitive) in scene.primitives.iter().enumerate() {
         let hit = primitive.intersect(&ray);
         if hit.is_some() {
@@ -565,186 +745,445 @@ pub fn p_shoot_ray(ray: &Line, pinhole_position: Vector, scene: &Scene, material
                 let intersection = hit.unwrap();
                 let distance = ray.point.distance(&intersection.0);
 
-                if closest_intersection.is_none() {
-                    closest_intersection = hit.clone();
-                    closest_material_idx = scene.material_index[i];
-                    closest_distance = distance;
-                } else if distance < closest_distance {
+                if closest_intersection.is_none() || distance < closest_distance {
                     closest_intersection = hit.clone();
                     closest_material_idx = scene.material_index[i];
+                    closest_primitive_idx = i;
                     closest_distance = distance;
                 }
             }
         }
     }
 
-    if closest_intersection.is_some() {
-        let mut color = Color::black();
+    let surface_color = if closest_intersection.is_some() {
         let intersection = closest_intersection.unwrap().0;
         let normal = closest_intersection.normal.unwrap();
-        let material = &materials[closest_material_idx];
-
-        let lighting_data = LightCalculationData {
-            point: intersection,
-            normal,
-            view_dir: ray.direction,
-            base_color: material.base_color,
-            shininess: material.shininess,
-            specular_amount: material.specular_amount,
+        // where the rays through the neighbouring pixels pass the surface, textures only need
+        // their footprint when the material has any
+        let offset_hits = ray.differentials.and_then(|differentials| offset_hits(&differentials, &closest_intersection));
+        let (footprint, position_footprint) = match offset_hits.as_ref() {
+            Some(offset_hits) if materials[closest_material_idx].has_textures() => footprints(&closest_intersection, offset_hits),
+            _ => (None, None),
         };
+        let texture_input = TextureInput::new(closest_intersection.uv, intersection, closest_intersection.object_position).with_footprints(footprint, position_footprint);
+        // blended materials are all shaded and mixed by their share of the hit
+        let mut blended = Color::black();
+        for (material_idx, share, coatings) in resolve_composite(materials, closest_material_idx, &texture_input) {
+            let mut color = Color::black();
+            let material = &materials[material_idx];
+            // refraction keeps the geometric normal, everything else is shaded with the detail maps
+            let shading_normal = material.shading_normal(&normal, closest_intersection.tangent, closest_intersection.uv);
+            let shader_input = ShaderInput::new(texture_input, shading_normal, -ray.direction, closest_distance);
+            let channels = material.channels_at(&shader_input);
+            let (base_color, metalic, roughness) = material.surface_at(&texture_input, &channels);
+            let terminator = |l: &Vector| if material.has_detail_maps() { shadow_terminator(&normal, &shading_normal, l) } else { 1.0 };
 
-        match material.material_type {
-            MaterialType::Phong => {
-                for light in scene.lights.iter() {
-                    if light.light_type == LightType::Ambient {
-                        let light_color = light.calculate_lighting(&lighting_data);
-                        color += light_color;
-                        continue;
-                    } else {
-                        // shot ray into the light
-                        let light_dir = (light.position - intersection)._normalize();
-                        let line_pos = intersection + light_dir * 0.01;
-                        let light_ray = Line::new(line_pos, light_dir);
-                        let distance = intersection.distance(&light.position);
-                        let shadowed = shoot_ray_into_light(&light_ray, scene, distance);
-        
-                        if !shadowed {
+            let lighting_data = LightCalculationData {
+                point: intersection,
+                normal: shading_normal,
+                view_dir: ray.direction,
+                base_color: reflectance_for(&base_color, spectral),
+                shininess: channels.scalar(TextureChannel::Shininess, material.shininess),
+                specular_amount: channels.scalar(TextureChannel::Specular, material.specular_amount),
+                spectral: ray.spectral,
+            };
+            // emission hit by a bsdf sampled ray shares its light with the samples of the emitter
+            let emission_weight = match (ray.bsdf_pdf, scene.emitter_pdf(closest_primitive_idx, &ray.point, &intersection, &normal)) {
+                (Some(bsdf_pdf), Some(light_pdf)) => power_heuristic(bsdf_pdf, light_pdf),
+                _ => 1.0,
+            };
+            let emitted = emission_for(&material.emitted_radiance_at(&channels), spectral) * emission_weight;
+
+            match material.material_type {
+                MaterialType::Phong => {
+                    color = emitted;
+                    // lights picked for this point and light of emissive primitives, sampled anew for every shaded point
+                    for light in scene.shading_lights(&intersection) {
+                        if light.light_type == LightType::Ambient {
                             let light_color = light.calculate_lighting(&lighting_data);
                             color += light_color;
-                            color._clamp01();
+                            continue;
+                        } else {
+                            // shot ray into the light
+                            let light_dir = (light.position - intersection)._normalize();
+                            let line_pos = intersection + light_dir * 0.01;
+                            let light_ray = ray.spawn(line_pos, light_dir);
+                            let distance = light.shadow_distance(&intersection);
+                            let transmittance = shadow_transmittance(&light_ray, scene, distance, stats);
+        
+                            if !transmittance.is_black() {
+                                let light_color = light.calculate_lighting(&lighting_data) * transmittance * terminator(&light_dir);
+                                color += light_color;
+                                color._clamp01();
+                            }
                         }
                     }
-                }
-            },
-            MaterialType::Reflective => {
-                let reflected_dir = ray.direction.reflect(&normal);
-                let reflected_ray_start = intersection + reflected_dir * 0.1;
-                let reflected_ray = Line::new(reflected_ray_start, reflected_dir);
-                let reflected_color = p_shoot_ray(&reflected_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color);
-                if reflected_color.is_some() {
-                    color = reflected_color.unwrap();
-                }
-            },
-            MaterialType::Refractive => {
-                let refracted_dir = ray.direction.refract(&normal, material.refractive_index);
-                let refracted_ray_start = intersection + refracted_dir * 0.1;
-                let refracted_ray = Line::new(refracted_ray_start, refracted_dir);
-                let refracted_color = p_shoot_ray(&refracted_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color);
-                if refracted_color.is_some() {
-                    color = refracted_color.unwrap();
-                }
-            },
-            MaterialType::PBR => {
-                let albedo = material.base_color;
-                let roughness = material.roughness;
-                let metalic = material.metallic;
-                let anisotropy = material.anisotropy;
-                let f0 = Vector::new(0.04, 0.04, 0.04);
-                let albedo_vec = albedo.to_vector();
-                let f0 = Vector::lerp(&f0, &albedo_vec, metalic);
-                let anisotropy_rotation = material.anisotropy_rotation;
-
-                let mut lo = Color::black();
-                for light in scene.lights.iter() {
-                    if light.light_type == LightType::Point {
-                        let l = (light.position - intersection)._normalize();
-
-                        let light_ray = Line::new(intersection + l * 0.01, l);
-                        let distance = intersection.distance(&light.position);
-                        let shadowed = shoot_ray_into_light(&light_ray, scene, distance);
-                        if shadowed {
-                            continue;
+                    // light focused onto the surface by mirrors and glass, reflected diffusely
+                    if let Some(caustics) = &scene.caustics {
+                        let facing = if shading_normal.dot(&ray.direction) > 0.0 { -shading_normal } else { shading_normal };
+                        caustics.gather(&intersection, &facing, |_, irradiance| color += lighting_data.base_color * emission_for(&irradiance, spectral) / PI);
+                    }
+                    // emission alone may be brighter than 1
+                    color._clamp01();
+                },
+                MaterialType::Reflective => {
+                    let reflected_dir = ray.direction.reflect(&shading_normal);
+                    let reflected_ray_start = intersection + reflected_dir * 0.1;
+                    let mut reflected_ray = ray.spawn(reflected_ray_start, reflected_dir);
+                    reflected_ray.differentials = bent_differentials(ray, offset_hits.as_ref(), |direction, normal| direction.reflect(normal));
+                    stats.secondary_rays += 1;
+                    let reflected_color = p_shoot_ray(&reflected_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats);
+                    if reflected_color.is_some() {
+                        color = reflected_color.unwrap();
+                    }
+                    color += emitted;
+                    if !linear {
+                        color._clamp01();
+                    }
+                },
+                MaterialType::Refractive => {
+                    // white light hitting a dispersive material is split up into wavelengths, each refracting
+                    // at its own angle. the split rays keep their wavelength for all following interfaces.
+                    // spectral rays split into their three wavelengths, each one filling its own channel
+                    let split = material.is_dispersive() && ray.wavelength.is_none();
+                    let wavelengths: Vec<Option<f32>> = match (split, spectral) {
+                        (true, Some(spectral)) => spectral.iter().map(|wavelength| Some(*wavelength)).collect(),
+                        (true, None) => stratified_wavelengths(DISPERSION_SAMPLES, rand::random::<f32>()).into_iter().map(Some).collect(),
+                        (false, _) => vec![ray.wavelength],
+                    };
+                    for (channel, wavelength) in wavelengths.into_iter().enumerate() {
+                        let mut incoming = *ray;
+                        incoming.wavelength = wavelength;
+                        if split && spectral.is_some() {
+                            incoming.spectral = wavelength.map(|wavelength| [wavelength; 3]);
                         }
-
-                        let v = (pinhole_position - intersection)._normalize();
-                        let h = (v + l)._normalize();
-                        let ndotl = normal.dot(&l).max(0.0);
-
-                        let distance = intersection.distance(&light.position);
-                        let attenuation = 1.0 / (light.attenuation.0 + light.attenuation.1 * distance + light.attenuation.2 * distance * distance);
-                        let radiance = light.color * attenuation;
-
-                        let mut D = normal_distribution(&normal, &h, roughness);
-                        if anisotropy > 0.001 {
-                            let tangent;
-                            if normal == Vector::new(0.0, 0.0, 1.0) {
-                                tangent = (Vector::new(0.0, 1.0, 0.0).cross(&normal))._normalize();
-                            } else {
-                                tangent = (Vector::new(0.0, 0.0, 1.0).cross(&normal))._normalize();
+                        let refractive_index = material.refractive_index_for(&incoming);
+                        let refract = |direction: &Vector, normal: &Vector| {
+                            let refracted = direction.refract(normal, refractive_index);
+                            // total internal reflection
+                            if refracted.length_squared() == 0.0 { direction.reflect(normal) } else { refracted }
+                        };
+                        let refracted_dir = refract(&ray.direction, &normal);
+                        let refracted_ray_start = intersection + refracted_dir * 0.1;
+                        let mut refracted_ray = incoming.spawn(refracted_ray_start, refracted_dir);
+                        refracted_ray.differentials = bent_differentials(ray, offset_hits.as_ref(), refract);
+                        stats.secondary_rays += 1;
+                        let refracted_color = p_shoot_ray(&refracted_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats);
+                        if let Some(refracted_color) = refracted_color {
+                            match (wavelength, spectral) {
+                                (Some(_), Some(_)) if split => match channel {
+                                    0 => color.r = refracted_color.r,
+                                    1 => color.g = refracted_color.g,
+                                    _ => color.b = refracted_color.b,
+                                },
+                                (Some(wavelength), None) if split => color += refracted_color * wavelength_to_rgb(wavelength) / DISPERSION_SAMPLES as f32,
+                                _ => color += refracted_color,
                             }
-                            
-                            let binormal = normal.cross(&tangent);
-                            // calculate ax and ay based on anisotropy rotation
-                            let mut v = Vector::new(1.0, 0.0, 0.0);
-                            let mut q = Quaternion::identity();
-                            q.rotate(anisotropy_rotation, Vector::new(0.0, 0.0, 1.0));
-                            q.rotate_vec(&mut v);
-                            let ax = v.x * roughness;
-                            let ay = v.y * roughness;
-
-                            let an = ggx_anisotropic(&h, &normal, &tangent, &binormal, ax, ay);
-                            D = anisotropy * an + ((1.0 - anisotropy) * D);
                         }
+                    }
+                    if split && !linear {
+                        // the wavelength weights only add up to white on average
+                        color._clamp01();
+                    }
+                    // a thin film reflects part of the light, in colors depending on the angle
+                    if let Some(film) = material.thin_film.as_ref() {
+                        let entering = ray.direction.dot(&normal) < 0.0;
+                        let refractive_index = material.refractive_index_for(ray);
+                        let (outside, inside) = if entering { (1.0, refractive_index) } else { (refractive_index, 1.0) };
+                        let reflectance = interface_fresnel(ray.direction.dot(&normal).abs(), outside, inside, Some(film), spectral);
+                        color *= Color::white() - reflectance;
+                        if max_bounces > 0 {
+                            let reflected_dir = ray.direction.reflect(&normal);
+                            let mut reflected_ray = ray.spawn(intersection + reflected_dir * 0.1, reflected_dir);
+                            reflected_ray.differentials = bent_differentials(ray, offset_hits.as_ref(), |direction, normal| direction.reflect(normal));
+                            stats.secondary_rays += 1;
+                            if let Some(reflected_color) = p_shoot_ray(&reflected_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats) {
+                                color += reflected_color * reflectance;
+                            }
+                        }
+                    }
+                    // leaving the object, the ray travelled closest_distance through the material
+                    if ray.direction.dot(&normal) > 0.0 {
+                        color *= material.absorption(closest_distance, spectral);
+                    }
+                    // glowing glass, the emission leaves the surface without passing through it
+                    color += emitted;
+                    if !linear {
+                        color._clamp01();
+                    }
+                },
+                MaterialType::PBR => {
+                    let albedo = reflectance_for(&base_color, spectral);
+                    let bsdf = Principled::new(material, albedo, metalic, roughness).with_channels(&channels).with_wavelengths(spectral).with_tangent(closest_intersection.tangent);
+                    let v = -ray.direction;
+
+                    // direction to a light and the radiance arriving from it at a point, none in shadow
+                    let incident = |point: &Vector, light: &Light, stats: &mut RenderStats| {
+                        let (l, radiance) = light.sample(point, spectral)?;
+                        let light_ray = ray.spawn(*point + l * 0.01, l);
+                        let distance = light.shadow_distance(point);
+                        let transmittance = shadow_transmittance(&light_ray, scene, distance, stats);
+                        if transmittance.is_black() {
+                            return None;
+                        }
+                        Some((l, radiance * transmittance))
+                    };
+
+                    let mut lo = Color::black();
+                    for light in scene.shading_lights(&intersection) {
+                        if let Some((l, radiance)) = incident(&intersection, &light, stats) {
+                            // lights with an area can also be hit by the glossy reflection traced
+                            // below, the reflection lobe is weighted between both
+                            let reflection = bsdf.evaluate_reflection(&shading_normal, &v, &l);
+                            let weight = match light.pdf(&intersection) {
+                                Some(light_pdf) if linear && max_bounces > 0 => power_heuristic(light_pdf, bsdf.reflection_pdf(&shading_normal, &v, &l)),
+                                _ => 1.0,
+                            };
+                            // light colors are what a white diffuse surface facing the light reflects,
+                            // the bsdf of that surface is 1 / pi
+                            let f = bsdf.evaluate(&shading_normal, &v, &l) - reflection + reflection * weight;
+                            lo += f * radiance * (PI * terminator(&l));
+                        }
+                    }
+                    // photons carry irradiance on the surface, the cosine is already in their density
+                    if let Some(caustics) = &scene.caustics {
+                        caustics.gather(&intersection, &shading_normal, |l, irradiance| {
+                            lo += bsdf.evaluate(&shading_normal, &v, l) / shading_normal.dot(l).max(1e-3) * emission_for(&irradiance, spectral);
+                        });
+                    }
+                    // the base of subsurface materials is lit where the random walk comes out again,
+                    // like a diffuse surface
+                    if let Some(scattering) = material.subsurface_scattering.as_ref() {
+                        let outward = if normal.dot(&v) < 0.0 { -normal } else { normal };
+                        let mut scattered = Color::black();
+                        for _ in 0..SUBSURFACE_WALKS {
+                            stats.secondary_rays += 1;
+                            if let Some(exit) = scattering.random_walk(&intersection, &outward, &albedo, scene, spectral, stats) {
+                                for light in scene.shading_lights(&exit.point) {
+                                    if let Some((l, radiance)) = incident(&exit.point, &light, stats) {
+                                        scattered += exit.throughput * radiance * exit.normal.dot(&l).max(0.0);
+                                    }
+                                }
+                            }
+                        }
+                        lo += scattered * bsdf.base_weight(&shading_normal, &v) / SUBSURFACE_WALKS as f32;
+                    }
+                    let ambient = match &scene.sky {
+                        Some(sky) => albedo * emission_for(&sky.ambient, spectral),
+                        None => albedo * 0.001,
+                    };
+                    let mut pixel_color = lo + ambient + emitted;
+                    // linear radiance is tonemapped once per pixel
+                    if !linear {
+                        pixel_color = tonemap(pixel_color);
+                    }
+                    color += pixel_color;
+
+                    let transmission = bsdf.transmission_weight();
+
+                    // glossy reflection of the surroundings, one direction picked from the visible normals.
+                    // it spreads wider than differentials could tell, so it has none. the transmitted
+                    // share reflects at the interface below
+                    if scene.glossy_reflections && max_bounces > 0 && transmission < 1.0 {
+                        if let Some((reflected_dir, weight)) = bsdf.sample_reflection(&shading_normal, &v, rand::random::<f32>(), rand::random::<f32>()) {
+                            let mut reflected_ray = ray.spawn(intersection + reflected_dir * 0.1, reflected_dir);
+                            reflected_ray.bsdf_pdf = linear.then(|| bsdf.reflection_pdf(&shading_normal, &v, &reflected_dir));
+                            stats.secondary_rays += 1;
+                            let reflected_color = p_shoot_ray(&reflected_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats);
+                            if let Some(reflected_color) = reflected_color {
+                                color += reflected_color * weight * (1.0 - transmission);
+                            }
+                        }
+                    }
 
-                        let G = geometry_smith(normal, v, l, roughness);
-
-                        let hdotv = h.dot(&v).max(0.0);
-                        let F = fresnel_schlick(hdotv, Color::from(f0));
-
-                        let numerator = F * D * G;
-                        let denom = 4.0 * normal.dot(&v).max(0.0) * normal.dot(&l).max(0.0) + 0.0001;
-                        let specular = numerator / denom;
-                        let ks = F;
-                        let kd = Color::white() - ks;
-                        let kd = kd * (1.0 - metalic);
+                    if transmission > 0.0 && max_bounces > 0 {
+                        let entering = ray.direction.dot(&normal) < 0.0;
+                        let (outside, inside) = if entering { (1.0, material.ior) } else { (material.ior, 1.0) };
+                        let mut reflectance = interface_fresnel(ray.direction.dot(&normal).abs(), outside, inside, bsdf.thin_film.as_ref(), spectral);
+                        let refracted_dir = ray.direction.refract(&normal, material.ior);
+                        // what the interface doesn't let through is reflected, all of it under total
+                        // internal reflection
+                        if refracted_dir.length_squared() == 0.0 {
+                            reflectance = Color::white();
+                        }
+                        let reflected_dir = ray.direction.reflect(&normal);
+                        let mut reflected_ray = ray.spawn(intersection + reflected_dir * 0.1, reflected_dir);
+                        reflected_ray.differentials = bent_differentials(ray, offset_hits.as_ref(), |direction, normal| direction.reflect(normal));
+                        stats.secondary_rays += 1;
+                        if let Some(reflected_color) = p_shoot_ray(&reflected_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats) {
+                            color += reflected_color * reflectance * transmission;
+                            if !linear {
+                                color._clamp01();
+                            }
+                        }
+                        if refracted_dir.length_squared() > 0.0 {
+                            let mut refracted_ray = ray.spawn(intersection + refracted_dir * 0.1, refracted_dir);
+                            refracted_ray.differentials = bent_differentials(ray, offset_hits.as_ref(), |direction, normal| direction.refract(normal, material.ior));
+                            stats.secondary_rays += 1;
+                            let refracted_color = p_shoot_ray(&refracted_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats);
+                            if let Some(refracted_color) = refracted_color {
+                                color += refracted_color * albedo * (Color::white() - reflectance) * transmission;
+                                if !linear {
+                                    color._clamp01();
+                                }
+                            }
+                        }
+                    }
+                }
+            }
 
-                        lo += (kd * albedo + specular) * radiance * ndotl;
+            // coatings from the innermost out, each one reflecting the surroundings and letting
+            // through what comes back up from below
+            for coating in coatings.iter().rev() {
+                let cos_view = ray.direction.dot(&normal).abs();
+                let base_albedo = base_color.brightness();
+                let (reflected, through) = coating.transfer(cos_view, base_albedo, spectral);
+                color *= through;
+                if max_bounces > 0 {
+                    let reflected_dir = ray.direction.reflect(&normal);
+                    let mut reflected_ray = ray.spawn(intersection + reflected_dir * 0.1, reflected_dir);
+                    reflected_ray.differentials = bent_differentials(ray, offset_hits.as_ref(), |direction, normal| direction.reflect(normal));
+                    stats.secondary_rays += 1;
+                    if let Some(reflected_color) = p_shoot_ray(&reflected_ray, pinhole_position, scene, materials, max_bounces - 1, sky_color, stats) {
+                        color += reflected_color * reflected;
                     }
                 }
-                let ambient = albedo * 0.001;
-                let mut pixel_color = lo + ambient;
-                pixel_color = pixel_color / (pixel_color + Color::white());
-                pixel_color.gamma_correction(2.2);
-                color += pixel_color;
+                if !linear {
+                    color._clamp01();
+                }
             }
+            blended += color * share;
         }
 
-        Some(color)
+        Some(blended)
     } else {
-        None
+        scene.sky.as_ref().map(|_| background(ray, scene, sky_color))
+    };
+
+    if !scene.has_media() {
+        return surface_color;
     }
+    // light lost and scattered in on the way from the hit (or the sky) to the ray origin
+    let distance = if closest_intersection.is_some() { closest_distance } else { f32::INFINITY };
+    let (transmittance, inscattered) = march_media(ray, distance, scene, stats);
+    let color = surface_color.unwrap_or_else(|| background(ray, scene, sky_color)) * transmittance + inscattered;
+    if linear {
+        return Some(color);
+    }
+    // surface colors are already tonemapped, bright fog could push them over 1
+    Some(color.clamp01())
+}
+
+// what rays leaving the scene see, tonemapped like surfaces unless radiance stays linear
+fn background(ray: &Line, scene: &Scene, sky_color: Color) -> Color {
+    let spectral = ray.spectral.as_ref();
+    let sky = match &scene.sky {
+        Some(sky) => sky,
+        None => return emission_for(&sky_color, spectral),
+    };
+    // the sun disk seen by bsdf sampled rays shares its light with the samples of the sun
+    let sun_weight = match (ray.bsdf_pdf, sky.sun_pdf()) {
+        (Some(bsdf_pdf), Some(sun_pdf)) => power_heuristic(bsdf_pdf, sun_pdf),
+        (Some(_), None) => 0.0,
+        _ => 1.0,
+    };
+    let mut color = emission_for(&(sky.radiance(&ray.direction) + sky.sun_disk(&ray.direction) * sun_weight), spectral);
+    if !keeps_linear(ray, scene) {
+        color = tonemap(color);
+    }
+    color
+}
+
+// spectral radiance and the radiance of scenes with glossy reflections stay linear until the
+// pixel is resolved, everything else is tonemapped per hit
+fn keeps_linear(ray: &Line, scene: &Scene) -> bool {
+    ray.spectral.is_some() || scene.glossy_reflections
+}
+
+// reinhard and gamma, from linear radiance to the color of a pixel
+fn tonemap(color: Color) -> Color {
+    let mut color = color / (color + Color::white());
+    color.gamma_correction(2.2);
+    color
+}
+
+// multiple importance sampling weight of a sample with density pdf against one with
+// other_pdf, "Optimally Combining Sampling Techniques for Monte Carlo Rendering" (Veach,
+// Guibas 1995). the densities are of all samples of a strategy together
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 && (a + b).is_finite() { a / (a + b) } else if pdf > other_pdf { 1.0 } else { 0.0 }
+}
+
+// where the rays through the neighbouring pixels cross the tangent plane of a hit, how far
+// along u and v of the surface that is and the normal there, "Tracing Ray Differentials"
+// (Igehy 1999)
+#[derive(Debug, Clone, Copy)]
+struct OffsetHits {
+    x_point: Vector,
+    y_point: Vector,
+    x_uv: Option<(f32, f32)>,
+    y_uv: Option<(f32, f32)>,
+    x_normal: Vector,
+    y_normal: Vector,
+}
+
+fn offset_hits(differentials: &RayDifferentials, hit: &RayCastHit) -> Option<OffsetHits> {
+    let (point, normal) = (hit.unwrap().0, hit.normal?);
+    let cross = |origin: &Vector, direction: &Vector| {
+        let denom = direction.dot(&normal);
+        (denom.abs() > 1e-8).then(|| *origin + *direction * ((point - *origin).dot(&normal) / denom))
+    };
+    let x_point = cross(&differentials.x_point, &differentials.x_direction)?;
+    let y_point = cross(&differentials.y_point, &differentials.y_direction)?;
+    // the step on the plane in u and v, least squares as it lies only roughly in the span
+    // of the derivatives
+    let uv_step = |step: Vector| {
+        let derivatives = hit.derivatives?;
+        let (a, b) = (derivatives.dpdu, derivatives.dpdv);
+        let (aa, ab, bb) = (a.dot(&a), a.dot(&b), b.dot(&b));
+        let det = aa * bb - ab * ab;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let (a_step, b_step) = (a.dot(&step), b.dot(&step));
+        Some(((bb * a_step - ab * b_step) / det, (aa * b_step - ab * a_step) / det))
+    };
+    let (x_uv, y_uv) = (uv_step(x_point - point), uv_step(y_point - point));
+    let normal_at = |uv: Option<(f32, f32)>| match (uv, hit.derivatives) {
+        (Some((du, dv)), Some(derivatives)) => (normal + derivatives.dndu * du + derivatives.dndv * dv)._normalize(),
+        _ => normal,
+    };
+    Some(OffsetHits { x_point, y_point, x_uv, y_uv, x_normal: normal_at(x_uv), y_normal: normal_at(y_uv) })
 }
 
-pub fn normal_distribution(n: &Vector, h: &Vector, roughness: f32) -> f32 {
-    let a2 = (roughness * roughness).powi(2);
-    let ndoth: f32 = (n.dot(h)).max(0.0);
-    let ndoth2 = ndoth * ndoth;
-    let num = a2;
-    let mut denom = ndoth2 * (a2 - 1.0) + 1.0;
-    denom = PI * denom * denom;
-    num / denom
+// how much uv and the hit point change towards the neighbouring pixels
+fn footprints(hit: &RayCastHit, offset_hits: &OffsetHits) -> (Option<Footprint>, Option<(Vector, Vector)>) {
+    let point = hit.unwrap().0;
+    let position_footprint = Some((offset_hits.x_point - point, offset_hits.y_point - point));
+    let footprint = match (hit.uv, offset_hits.x_uv, offset_hits.y_uv) {
+        (Some(_), Some(dx), Some(dy)) => Some(Footprint { dx, dy }),
+        _ => None,
+    };
+    (footprint, position_footprint)
 }
 
-// GGX Anisotropic [5] from: https://graphicrants.blogspot.com/2013/08/specular-brdf-reference.html
-pub fn ggx_anisotropic(h: &Vector, n: &Vector, x: &Vector, y: &Vector, ax: f32, ay: f32, ) -> f32 {
-    let first = 1.0 / (PI * ax * ay);
-    let ndoth2 = n.dot(h) * n.dot(h);
-    let xh = (x.dot(h)).powi(2) / ax.powi(2);
-    let yh = (y.dot(h)).powi(2) / ay.powi(2);
-    let second = 1.0 / (xh + yh + ndoth2).powi(2);
-    first * second
-
-    // let ax = ax * ax;
-    // let ay = ay * ay;
-    // let xoh = x.dot(h);
-    // let yoh = y.dot(h);
-    // let noh = n.dot(h);
-    // let d = xoh * xoh / (ax * ax) + yoh * yoh / (ay * ay) + noh*noh;
-    // 1.0 / (PI * ax * ay * d * d)
+// differentials of a reflected or refracted ray. the neighbouring rays are bent the same way
+// where they cross the surface
+fn bent_differentials(ray: &Line, offset_hits: Option<&OffsetHits>, bend: impl Fn(&Vector, &Vector) -> Vector) -> Option<RayDifferentials> {
+    let (differentials, offset_hits) = (ray.differentials?, offset_hits?);
+    Some(RayDifferentials {
+        x_point: offset_hits.x_point,
+        x_direction: bend(&differentials.x_direction, &offset_hits.x_normal),
+        y_point: offset_hits.y_point,
+        y_direction: bend(&differentials.y_direction, &offset_hits.y_normal),
+    })
 }
 
-pub fn shoot_ray_into_light(ray: &Line, scene: &Scene, max_distance: f32) -> bool {
+pub fn shoot_ray_into_light(ray: &Line, scene: &Scene, max_distance: f32, stats: &mut RenderStats) -> bool {
+    stats.shadow_rays += 1;
     for primitive in scene.primitives.iter() {
+        stats.intersection_tests += 1;
         let hit = primitive.intersect(&ray);
         if hit.is_some() {
             let intersection = hit.unwrap().0;
@@ -757,23 +1196,190 @@ pub fn shoot_ray_into_light(ray: &Line, scene: &Scene, max_distance: f32) -> boo
     return false
 }
 
+// fraction of a light reaching the ray origin, black when something is in the way
+pub fn shadow_transmittance(ray: &Line, scene: &Scene, max_distance: f32, stats: &mut RenderStats) -> Color {
+    if shoot_ray_into_light(ray, scene, max_distance, stats) {
+        return Color::black();
+    }
+    medium_transmittance(ray, max_distance, scene, stats)
+}
+
+// how far along the ray there is any medium
+fn media_range(ray: &Line, scene: &Scene, stats: &mut RenderStats) -> f32 {
+    stats.intersection_tests += scene.volumes.len() as u64;
+    let mut range: f32 = 0.0;
+    if let Some(medium) = &scene.global_medium {
+        range = medium.visibility_distance();
+    }
+    for volume in scene.volumes.iter() {
+        if let Some((_, far)) = scene.volume_intervals(volume, ray).last() {
+            range = range.max(*far);
+        }
+    }
+    range
+}
+
+// splits the ray up to distance at every volume boundary, so every piece lies in a single
+// medium. pieces in vacuum are left out
+pub fn medium_segments<'a>(ray: &Line, distance: f32, scene: &'a Scene, stats: &mut RenderStats) -> Vec<MediumSegment<'a>> {
+    let distance = distance.min(media_range(ray, scene, stats));
+    if distance <= 0.0 {
+        return Vec::new();
+    }
+    stats.intersection_tests += scene.volumes.len() as u64;
+    let intervals: Vec<Vec<(f32, f32)>> = scene.volumes.iter().map(|volume| scene.volume_intervals(volume, ray)).collect();
+    let mut bounds = vec![0.0, distance];
+    for (near, far) in intervals.iter().flatten() {
+        bounds.extend([*near, *far].iter().filter(|t| **t > 0.0 && **t < distance));
+    }
+    bounds.sort_by(|a, b| a.total_cmp(b));
+
+    let mut segments = Vec::new();
+    for pair in bounds.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        if end - start <= 0.0 {
+            continue;
+        }
+        // the first volume the middle of the piece is in
+        let middle = (start + end) / 2.0;
+        let volume = scene.volumes.iter().zip(intervals.iter())
+            .find(|(_, intervals)| intervals.iter().any(|(near, far)| *near <= middle && middle <= *far))
+            .map(|(volume, _)| volume);
+        let segment = match volume {
+            Some(volume) => Some(MediumSegment { start, end, medium: volume.medium.for_ray(ray.spectral.as_ref()), volume: Some(volume) }),
+            None => scene.global_medium.map(|medium| MediumSegment { start, end, medium: medium.for_ray(ray.spectral.as_ref()), volume: None }),
+        };
+        segments.extend(segment);
+    }
+    segments
+}
+
+// transmittance along the ray up to distance. exact beer-lambert in homogeneous media,
+// an unbiased estimate by ratio tracking in heterogeneous volumes
+pub fn medium_transmittance(ray: &Line, distance: f32, scene: &Scene, stats: &mut RenderStats) -> Color {
+    if !scene.has_media() {
+        return Color::white();
+    }
+    let mut transmittance = Color::white();
+    for segment in medium_segments(ray, distance, scene, stats) {
+        transmittance *= match segment.volume {
+            Some(volume) if !volume.is_homogeneous() => ratio_tracking(ray, &segment, volume),
+            _ => segment.medium.transmittance(segment.length()),
+        };
+        if transmittance.is_black() {
+            break;
+        }
+    }
+    transmittance
+}
+
+// steps through the volume with tentative collisions at the majorant rate and
+// multiplies in the chance of each one being a null collision
+fn ratio_tracking(ray: &Line, segment: &MediumSegment, volume: &Volume) -> Color {
+    let majorant = segment.majorant();
+    if majorant <= 0.0 {
+        return Color::white();
+    }
+    let extinction = segment.medium.extinction();
+    let mut transmittance = Color::white();
+    let mut t = segment.start;
+    loop {
+        t -= (1.0 - rand::random::<f32>()).ln() / majorant;
+        if t >= segment.end {
+            return transmittance;
+        }
+        let density = volume.density_at(&ray.point_on_line(&t));
+        transmittance *= Color::white() - extinction * (density / majorant);
+        // russian roulette once hardly any light is left
+        let max = transmittance.r.max(transmittance.g).max(transmittance.b);
+        if max < 0.1 {
+            if rand::random::<f32>() < 0.5 {
+                return Color::black();
+            }
+            transmittance = transmittance * 2.0;
+        }
+    }
+}
 
-pub fn geometry_schlick_ggx(NdotV: f32, roughness: f32) -> f32 {
-    let r = roughness + 1.0;
-    let k = (r * r) / 8.0;
-    NdotV / (NdotV * (1.0 - k) + k)
+// delta tracking: samples where the ray collides with a particle of the volume. returns the
+// light leaving the collision towards the ray origin, None if the ray passes the volume.
+// collisions are sampled with the largest extinction channel and the albedo takes care of the others
+fn delta_tracking(ray: &Line, segment: &MediumSegment, volume: &Volume, scene: &Scene, stats: &mut RenderStats) -> Option<Color> {
+    let majorant = segment.majorant();
+    if majorant <= 0.0 {
+        return None;
+    }
+    let max_extinction = segment.medium.max_extinction();
+    let mut t = segment.start;
+    loop {
+        t -= (1.0 - rand::random::<f32>()).ln() / majorant;
+        if t >= segment.end {
+            return None;
+        }
+        let point = ray.point_on_line(&t);
+        let extinction = max_extinction * volume.density_at(&point);
+        if rand::random::<f32>() * majorant < extinction {
+            let albedo = segment.medium.scattering * (volume.density_at(&point) / extinction);
+            let emitted = segment.emission_at(&point) / extinction;
+            return Some(albedo * light_scattered_at(&point, ray, &segment.medium, scene, stats) + emitted);
+        }
+    }
 }
 
-pub fn geometry_smith(N: Vector, V: Vector, L: Vector, roughness: f32) -> f32 {
-    let ndotv = N.dot(&V).max(0.0);
-    let ndotl = N.dot(&L).max(0.0);
-    let ggx2 = geometry_schlick_ggx(ndotv, roughness);
-    let ggx1 = geometry_schlick_ggx(ndotl, roughness);
-    ggx1 * ggx2
+// light from the lights arriving at a point in a medium, weighted by the phase function for
+// scattering back along the ray
+fn light_scattered_at(point: &Vector, ray: &Line, medium: &Medium, scene: &Scene, stats: &mut RenderStats) -> Color {
+    let mut light_in = Color::black();
+    for light in scene.shading_lights(point) {
+        if light.light_type == LightType::Ambient {
+            // isotropic light, the phase function integrates to one
+            light_in += light.radiance_at(point, ray.spectral.as_ref());
+            continue;
+        }
+        let light_radiance = light.radiance_at(point, ray.spectral.as_ref());
+        if light_radiance.is_black() {
+            continue;
+        }
+        let l = (light.position - *point)._normalize();
+        let light_ray = ray.spawn(*point, l);
+        let light_distance = light.shadow_distance(point);
+        let light_transmittance = shadow_transmittance(&light_ray, scene, light_distance, stats);
+        light_in += light_radiance * light_transmittance * medium.phase(l.dot(&ray.direction));
+    }
+    light_in
 }
 
-pub fn fresnel_schlick(cos_theta: f32, f0: Color) -> Color {
-    f0 + (Color::white() - f0) * ((1.0 - cos_theta).clamp(0.0, 1.0)).powi(5)
+// integrates the media in front of the ray up to distance. returns the transmittance to that
+// point and the light scattered and emitted towards the ray origin on the way. homogeneous
+// media are ray marched, heterogeneous volumes use delta tracking
+pub fn march_media(ray: &Line, distance: f32, scene: &Scene, stats: &mut RenderStats) -> (Color, Color) {
+    let mut transmittance = Color::white();
+    let mut inscattered = Color::black();
+    // random offset of the samples, turns banding into noise
+    let jitter = rand::random::<f32>();
+
+    for segment in medium_segments(ray, distance, scene, stats) {
+        if let Some(volume) = segment.volume.filter(|volume| !volume.is_homogeneous()) {
+            if let Some(radiance) = delta_tracking(ray, &segment, volume, scene, stats) {
+                // the collision hides everything behind it
+                inscattered += transmittance * radiance;
+                return (Color::black(), inscattered);
+            }
+            continue;
+        }
+
+        let medium = &segment.medium;
+        let steps = (segment.length() / scene.march_step).ceil().clamp(1.0, MAX_MARCH_STEPS) as usize;
+        let step = segment.length() / steps as f32;
+        let step_transmittance = medium.transmittance(step);
+        for i in 0..steps {
+            let point = ray.point_on_line(&(segment.start + (i as f32 + jitter) * step));
+            let light_in = light_scattered_at(&point, ray, medium, scene, stats);
+            inscattered += transmittance * (medium.scattering * light_in + segment.emission_at(&point)) * step;
+            transmittance *= step_transmittance;
+        }
+    }
+    (transmittance, inscattered)
 }
 
 //return None;
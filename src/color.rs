@@ -50,6 +50,15 @@ impl Color {
         Color::new(0.0, 0.0, 1.0)
     }
 
+    pub fn grey(value: f32) -> Color {
+        Color::new(value, value, value)
+    }
+
+    // average of the channels, for colors standing in for a single number
+    pub fn brightness(&self) -> f32 {
+        (self.r + self.g + self.b) / 3.0
+    }
+
     pub fn to_vector(&self) -> Vector {
         Vector::new(self.r, self.g, self.b)
     }
@@ -60,6 +69,11 @@ impl Color {
         self.b = self.b.powf(1.0 / gamma);
     }
 
+    // exact check, unlike == which compares the 8 bit values
+    pub fn is_black(&self) -> bool {
+        self.r == 0.0 && self.g == 0.0 && self.b == 0.0
+    }
+
     pub fn is_nan(&self) -> bool {
         self.r.is_nan() || self.g.is_nan() || self.b.is_nan()
     }
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::math::noise::fbm;
+use crate::math::Vector;
+
+// scalar voxel grid filling the unit cube, x varies fastest in data.
+// used for smoke densities and fire temperatures of volumes.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub resolution: (usize, usize, usize),
+    pub data: Vec<f32>,
+    max_value: f32,
+}
+
+impl DensityGrid {
+    pub fn new(resolution: (usize, usize, usize), data: Vec<f32>) -> DensityGrid {
+        assert_eq!(data.len(), resolution.0 * resolution.1 * resolution.2, "grid data doesn't match its resolution");
+        let max_value = data.iter().cloned().fold(0.0, f32::max);
+        DensityGrid { resolution, data, max_value }
+    }
+
+    // evaluates f at the center of every voxel, positions are in 0..1
+    pub fn from_fn(resolution: (usize, usize, usize), f: impl Fn(Vector) -> f32) -> DensityGrid {
+        let mut data = Vec::with_capacity(resolution.0 * resolution.1 * resolution.2);
+        for z in 0..resolution.2 {
+            for y in 0..resolution.1 {
+                for x in 0..resolution.0 {
+                    let p = Vector::new(
+                        (x as f32 + 0.5) / resolution.0 as f32,
+                        (y as f32 + 0.5) / resolution.1 as f32,
+                        (z as f32 + 0.5) / resolution.2 as f32,
+                    );
+                    data.push(f(p));
+                }
+            }
+        }
+        DensityGrid::new(resolution, data)
+    }
+
+    // puffy cloud, fbm noise carved by a sphere so it fades out before the grid border
+    pub fn cloud(resolution: usize, frequency: f32, octaves: u32) -> DensityGrid {
+        DensityGrid::from_fn((resolution, resolution, resolution), |p| {
+            let centered = (p - Vector::from_num(0.5)) * 2.0;
+            let falloff = 1.0 - centered.length();
+            let noise = fbm(&(p * frequency), octaves, 2.0, 0.5);
+            (falloff + noise * 0.8).max(0.0)
+        })
+    }
+
+    // headerless little endian 32 bit floats, the resolution has to be known
+    pub fn load_raw(path: &str, resolution: (usize, usize, usize)) -> Result<DensityGrid> {
+        let bytes = fs::read(path)?;
+        let count = resolution.0 * resolution.1 * resolution.2;
+        if bytes.len() < count * 4 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} is too small for a {:?} grid", path, resolution)));
+        }
+        Ok(DensityGrid::new(resolution, read_f32s(&bytes, count)))
+    }
+
+    // mitsuba .vol grid: "VOL", version 3, encoding 1 (float32), x/y/z resolution, channel count,
+    // bounding box as 6 floats, then the data. only the first channel is kept
+    pub fn load_vol(path: &str) -> Result<DensityGrid> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} is no version 3 .vol file", path)));
+        }
+        let header = |i: usize| i32::from_le_bytes([bytes[4 + i * 4], bytes[5 + i * 4], bytes[6 + i * 4], bytes[7 + i * 4]]);
+        if header(0) != 1 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} doesn't store 32 bit floats", path)));
+        }
+        let resolution = (header(1) as usize, header(2) as usize, header(3) as usize);
+        let channels = header(4).max(1) as usize;
+        let count = resolution.0 * resolution.1 * resolution.2;
+        let data = &bytes[48..];
+        if data.len() < count * channels * 4 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} ends before its data does", path)));
+        }
+        let values = read_f32s(data, count * channels);
+        let first_channel = values.iter().step_by(channels).cloned().collect();
+        Ok(DensityGrid::new(resolution, first_channel))
+    }
+
+    pub fn max_value(&self) -> f32 {
+        self.max_value
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.data[(z * self.resolution.1 + y) * self.resolution.0 + x]
+    }
+
+    // trilinear lookup, p in 0..1 on every axis. zero outside of the grid
+    pub fn sample(&self, p: &Vector) -> f32 {
+        if p.x < 0.0 || p.y < 0.0 || p.z < 0.0 || p.x > 1.0 || p.y > 1.0 || p.z > 1.0 {
+            return 0.0;
+        }
+        let (rx, ry, rz) = self.resolution;
+        // voxel values sit at the voxel centers
+        let gx = (p.x * rx as f32 - 0.5).clamp(0.0, (rx - 1) as f32);
+        let gy = (p.y * ry as f32 - 0.5).clamp(0.0, (ry - 1) as f32);
+        let gz = (p.z * rz as f32 - 0.5).clamp(0.0, (rz - 1) as f32);
+        let (x0, y0, z0) = (gx as usize, gy as usize, gz as usize);
+        let (x1, y1, z1) = ((x0 + 1).min(rx - 1), (y0 + 1).min(ry - 1), (z0 + 1).min(rz - 1));
+        let (fx, fy, fz) = (gx - x0 as f32, gy - y0 as f32, gz - z0 as f32);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+fn read_f32s(bytes: &[u8], count: usize) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .take(count)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_test() {
+        let grid = DensityGrid::new((2, 1, 1), vec![0.0, 1.0]);
+        assert_eq!(grid.sample(&Vector::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.sample(&Vector::new(0.75, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.sample(&Vector::new(0.5, 0.5, 0.5)), 0.5);
+        assert_eq!(grid.sample(&Vector::new(1.5, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.max_value(), 1.0);
+    }
+
+    #[test]
+    fn load_vol_test() {
+        let mut bytes = b"VOL".to_vec();
+        bytes.push(3);
+        for value in [1, 2, 1, 1, 1] {
+            bytes.extend(i32::to_le_bytes(value));
+        }
+        for value in [0.0_f32, 0.0, 0.0, 1.0, 1.0, 1.0, 0.25, 0.75] {
+            bytes.extend(f32::to_le_bytes(value));
+        }
+        let path = std::env::temp_dir().join("density_grid_test.vol");
+        fs::write(&path, bytes).unwrap();
+        let grid = DensityGrid::load_vol(path.to_str().unwrap()).unwrap();
+        assert_eq!(grid.resolution, (2, 1, 1));
+        assert_eq!(grid.data, vec![0.25, 0.75]);
+        fs::remove_file(path).unwrap();
+    }
+}
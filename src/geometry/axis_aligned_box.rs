@@ -0,0 +1,135 @@
+use crate::math::{SurfaceCrossing, SurfaceDerivatives, Vector};
+
+use super::Line;
+
+// solid box with faces parallel to the axes. unlike create_box_surfaces it is a single
+// primitive, so it can be placed with an Instance. every face has its own 0..1 uv.
+#[derive(Debug, Clone, Copy)]
+pub struct AxisAlignedBox {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl AxisAlignedBox {
+    pub fn new(min: Vector, max: Vector) -> AxisAlignedBox {
+        AxisAlignedBox {
+            min: Vector::new(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)),
+            max: Vector::new(min.x.max(max.x), min.y.max(max.y), min.z.max(max.z)),
+        }
+    }
+
+    pub fn from_center_size(center: Vector, size: Vector) -> AxisAlignedBox {
+        AxisAlignedBox::new(center - size / 2.0, center + size / 2.0)
+    }
+
+    pub fn center(&self) -> Vector {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Vector {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: &Vector) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x &&
+        point.y >= self.min.y && point.y <= self.max.y &&
+        point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    // slab method. returns (t_near, t_far) of the infinite ray, None if it misses the box
+    pub fn slab(&self, ray: &Line) -> Option<(f32, f32)> {
+        let (near, far) = self.slab_axes(ray)?;
+        Some((near.0, far.0))
+    }
+
+    // like slab, but also returns which axis (0, 1, 2) was crossed at each end
+    fn slab_axes(&self, ray: &Line) -> Option<((f32, usize), (f32, usize))> {
+        let origin = [ray.point.x, ray.point.y, ray.point.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+
+        let mut near = (f32::NEG_INFINITY, 0);
+        let mut far = (f32::INFINITY, 0);
+        for axis in 0..3 {
+            if direction[axis].abs() < 1e-9 {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let inv = 1.0 / direction[axis];
+            let mut t0 = (min[axis] - origin[axis]) * inv;
+            let mut t1 = (max[axis] - origin[axis]) * inv;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > near.0 {
+                near = (t0, axis);
+            }
+            if t1 < far.0 {
+                far = (t1, axis);
+            }
+            if near.0 > far.0 {
+                return None;
+            }
+        }
+        Some((near, far))
+    }
+
+    pub fn area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // uniformly distributed point on the faces for u, v in 0..1 and the outward normal there.
+    // u picks a pair of opposite faces by their area, what is left of it one face of the pair
+    // and the position across it
+    pub fn sample_surface(&self, u: f32, v: f32) -> (Vector, Vector) {
+        let size = self.size();
+        let pairs = [size.y * size.z, size.z * size.x, size.x * size.y];
+        let mut pick = u * (pairs[0] + pairs[1] + pairs[2]);
+        let mut axis = 2;
+        for (i, pair) in pairs.iter().enumerate() {
+            if pick < *pair {
+                axis = i;
+                break;
+            }
+            pick -= pair;
+        }
+        let s = (2.0 * pick / pairs[axis].max(f32::MIN_POSITIVE)).clamp(0.0, 2.0);
+        let (side, s) = if s >= 1.0 { (1.0, s - 1.0) } else { (0.0, s) };
+        let sign = 2.0 * side - 1.0;
+        let (offset, normal) = match axis {
+            0 => (Vector::new(side, s, v), Vector::new(sign, 0.0, 0.0)),
+            1 => (Vector::new(v, side, s), Vector::new(0.0, sign, 0.0)),
+            _ => (Vector::new(s, v, side), Vector::new(0.0, 0.0, sign)),
+        };
+        (self.min + Vector::new(size.x * offset.x, size.y * offset.y, size.z * offset.z), normal)
+    }
+
+    // entry and exit crossing of the infinite ray, sorted by t
+    pub fn crossings(&self, ray: &Line) -> Vec<SurfaceCrossing> {
+        let Some((near, far)) = self.slab_axes(ray) else {
+            return Vec::new();
+        };
+        if !near.0.is_finite() || !far.0.is_finite() {
+            return Vec::new();
+        }
+        vec![self.face_crossing(ray, near.0, near.1), self.face_crossing(ray, far.0, far.1)]
+    }
+
+    fn face_crossing(&self, ray: &Line, t: f32, axis: usize) -> SurfaceCrossing {
+        let point = ray.point_on_line(&t);
+        let center = self.center();
+        let size = self.size();
+        let local = point - self.min;
+        let (x, y, z) = (Vector::new(size.x, 0.0, 0.0), Vector::new(0.0, size.y, 0.0), Vector::new(0.0, 0.0, size.z));
+        let (normal, uv, derivatives) = match axis {
+            0 => (Vector::new((point.x - center.x).signum(), 0.0, 0.0), (local.z / size.z, local.y / size.y), SurfaceDerivatives::flat(z, y)),
+            1 => (Vector::new(0.0, (point.y - center.y).signum(), 0.0), (local.x / size.x, local.z / size.z), SurfaceDerivatives::flat(x, z)),
+            _ => (Vector::new(0.0, 0.0, (point.z - center.z).signum()), (local.x / size.x, local.y / size.y), SurfaceDerivatives::flat(x, y)),
+        };
+        SurfaceCrossing { t, normal, uv, derivatives: Some(derivatives) }
+    }
+}
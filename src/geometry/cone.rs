@@ -0,0 +1,88 @@
+use crate::math::{solve_quadratic, SurfaceCrossing, SurfaceDerivatives, Vector};
+
+use super::cylinder::cap_crossing;
+use super::Line;
+
+// cone standing on a round base. the axis points from the base to the apex.
+// on the side u goes around the axis and v goes from the base to the apex, the base uses polar uv.
+#[derive(Debug, Clone, Copy)]
+pub struct Cone {
+    pub base: Vector,
+    pub axis: Vector,
+    pub radius: f32,
+    pub height: f32,
+    tangent: Vector,
+    bitangent: Vector,
+}
+
+impl Cone {
+    pub fn new(base: Vector, axis: Vector, radius: f32, height: f32) -> Cone {
+        let axis = axis._normalize();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        Cone {
+            base,
+            axis,
+            radius,
+            height,
+            tangent,
+            bitangent,
+        }
+    }
+
+    pub fn apex(&self) -> Vector {
+        self.base + self.axis * self.height
+    }
+
+    // every point where the infinite ray crosses the surface, sorted by t
+    pub fn crossings(&self, ray: &Line) -> Vec<SurfaceCrossing> {
+        let mut crossings = Vec::new();
+        // v points from the apex down to the base
+        let v = -self.axis;
+        let co = ray.point - self.apex();
+        let slope = self.radius / self.height;
+        let cos2 = 1.0 / (1.0 + slope * slope);
+
+        let dv = ray.direction.dot(&v);
+        let cov = co.dot(&v);
+        let a = (dv * dv - cos2 * ray.direction.dot(&ray.direction)) as f64;
+        let b = 2.0 * (dv * cov - cos2 * ray.direction.dot(&co)) as f64;
+        let c = (cov * cov - cos2 * co.dot(&co)) as f64;
+        for t in solve_quadratic(a, b, c) {
+            let t = t as f32;
+            let local = co + ray.direction * t;
+            let h = local.dot(&v);
+            // the equation also describes the mirrored cone above the apex
+            if h < 0.0 || h > self.height {
+                continue;
+            }
+            let radial = local - v * h;
+            let radial_length = radial.length();
+            if radial_length < 0.000001 {
+                continue;
+            }
+            let radial_dir = radial / radial_length;
+            let normal = (radial_dir - v * slope)._normalize();
+            let angle = radial.dot(&self.bitangent).atan2(radial.dot(&self.tangent));
+            let around = self.axis * (2.0 * std::f32::consts::PI);
+            crossings.push(SurfaceCrossing {
+                t,
+                normal,
+                uv: (angle / (2.0 * std::f32::consts::PI) + 0.5, 1.0 - h / self.height),
+                // v grows towards the apex, against h
+                derivatives: Some(SurfaceDerivatives {
+                    dpdu: around.cross(&radial),
+                    dpdv: -(v + radial_dir * slope) * self.height,
+                    dndu: around.cross(&normal),
+                    dndv: Vector::new(0.0, 0.0, 0.0),
+                }),
+            });
+        }
+
+        if let Some(crossing) = cap_crossing(ray, self.base, v, self.radius, self.tangent, self.bitangent) {
+            crossings.push(crossing);
+        }
+
+        crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+        crossings
+    }
+}
@@ -0,0 +1,152 @@
+use crate::math::{IntersectionPrimitive, RayInterval, SurfaceCrossing};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // a minus b
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// combines two solids with a boolean operation. the operands can be any primitive
+// reporting intervals (spheres, boxes, cylinders, cones, tori, closed meshes, instances
+// and other Csg nodes), open surfaces have no inside and act as empty solids.
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub a: Box<dyn IntersectionPrimitive + Send + Sync>,
+    pub b: Box<dyn IntersectionPrimitive + Send + Sync>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, a: Box<dyn IntersectionPrimitive + Send + Sync>, b: Box<dyn IntersectionPrimitive + Send + Sync>) -> Csg {
+        Csg { operation, a, b }
+    }
+
+    pub fn union(a: Box<dyn IntersectionPrimitive + Send + Sync>, b: Box<dyn IntersectionPrimitive + Send + Sync>) -> Csg {
+        Csg::new(CsgOperation::Union, a, b)
+    }
+
+    pub fn intersection(a: Box<dyn IntersectionPrimitive + Send + Sync>, b: Box<dyn IntersectionPrimitive + Send + Sync>) -> Csg {
+        Csg::new(CsgOperation::Intersection, a, b)
+    }
+
+    pub fn difference(a: Box<dyn IntersectionPrimitive + Send + Sync>, b: Box<dyn IntersectionPrimitive + Send + Sync>) -> Csg {
+        Csg::new(CsgOperation::Difference, a, b)
+    }
+
+    // walks the boundaries of both interval lists in order of t and keeps track of being
+    // inside a and b. every change of the combined inside state starts or ends an interval.
+    pub fn combine(&self, a: &[RayInterval], b: &[RayInterval]) -> Vec<RayInterval> {
+        // (crossing, from a, entering)
+        let mut events: Vec<(SurfaceCrossing, bool, bool)> = Vec::with_capacity(2 * (a.len() + b.len()));
+        for (intervals, from_a) in [(a, true), (b, false)] {
+            for interval in intervals {
+                events.push((interval.enter, from_a, true));
+                events.push((interval.exit, from_a, false));
+            }
+        }
+        events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+        let mut result = Vec::new();
+        let mut in_a = false;
+        let mut in_b = false;
+        let mut enter: Option<SurfaceCrossing> = None;
+        for (mut crossing, from_a, entering) in events {
+            let was_inside = self.operation.inside(in_a, in_b);
+            if from_a {
+                in_a = entering;
+            } else {
+                in_b = entering;
+            }
+            let inside = self.operation.inside(in_a, in_b);
+            if inside == was_inside {
+                continue;
+            }
+            // the surface of a subtracted solid faces into the result, flip it outwards
+            if !from_a && self.operation == CsgOperation::Difference {
+                crossing.normal = -crossing.normal;
+            }
+            if inside {
+                enter = Some(crossing);
+            } else if let Some(enter) = enter.take() {
+                result.push(RayInterval { enter, exit: crossing });
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{AxisAlignedBox, Cylinder, Line, Sphere};
+    use crate::math::Vector;
+
+    #[test]
+    fn lens_test() {
+        let lens = Csg::intersection(
+            Box::new(Sphere::new(Vector::new(-1.0, 0.0, 0.0), 2.0)),
+            Box::new(Sphere::new(Vector::new(1.0, 0.0, 0.0), 2.0)),
+        );
+        let ray = Line::new(Vector::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let intervals = lens.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.t - 4.0).abs() < 1e-4);
+        assert!((intervals[0].exit.t - 6.0).abs() < 1e-4);
+
+        let hit = lens.intersect(&ray);
+        assert!(hit.hit.is_some());
+        assert!((hit.normal.unwrap().x + 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn drilled_box_test() {
+        let block = Csg::difference(
+            Box::new(AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, 0.0), Vector::from_num(2.0))),
+            Box::new(Cylinder::new(Vector::new(0.0, -2.0, 0.0), Vector::new(0.0, 1.0, 0.0), 0.5, 4.0)),
+        );
+        // through the hole, the ray enters the box, leaves into the hole and enters the box again
+        let ray = Line::new(Vector::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let intervals = block.intervals(&ray);
+        assert_eq!(intervals.len(), 2);
+        assert!((intervals[0].exit.t - 4.5).abs() < 1e-4);
+        // the wall of the hole faces towards the axis of the cylinder
+        assert!((intervals[0].exit.normal.x - 1.0).abs() < 1e-4);
+        assert!((intervals[1].enter.normal.x + 1.0).abs() < 1e-4);
+
+        // straight down the hole there is nothing to hit
+        let ray = Line::new(Vector::new(0.0, 5.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        assert!(block.intersect(&ray).hit.is_none());
+    }
+
+    #[test]
+    fn union_test() {
+        // overlapping spheres merge into one interval, apart they stay two
+        let overlapping = Csg::union(
+            Box::new(Sphere::new(Vector::new(-1.0, 0.0, 0.0), 2.0)),
+            Box::new(Sphere::new(Vector::new(1.0, 0.0, 0.0), 2.0)),
+        );
+        let ray = Line::new(Vector::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let intervals = overlapping.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.t - 2.0).abs() < 1e-4);
+        assert!((intervals[0].exit.t - 8.0).abs() < 1e-4);
+
+        let apart = Csg::union(
+            Box::new(Sphere::new(Vector::new(-3.0, 0.0, 0.0), 1.0)),
+            Box::new(Sphere::new(Vector::new(3.0, 0.0, 0.0), 1.0)),
+        );
+        assert_eq!(apart.intervals(&ray).len(), 2);
+        assert!((apart.intersect(&ray).distance - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::math::{solve_quadratic, SurfaceCrossing, SurfaceDerivatives, Vector};
+
+use super::Line;
+
+// cylinder closed with two caps. base is the center of the bottom cap, the axis points to the top cap.
+// on the side u goes around the axis and v goes from the bottom to the top, caps use polar uv.
+#[derive(Debug, Clone, Copy)]
+pub struct Cylinder {
+    pub base: Vector,
+    pub axis: Vector,
+    pub radius: f32,
+    pub height: f32,
+    tangent: Vector,
+    bitangent: Vector,
+}
+
+impl Cylinder {
+    pub fn new(base: Vector, axis: Vector, radius: f32, height: f32) -> Cylinder {
+        let axis = axis._normalize();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        Cylinder {
+            base,
+            axis,
+            radius,
+            height,
+            tangent,
+            bitangent,
+        }
+    }
+
+    // every point where the infinite ray crosses the surface, sorted by t
+    pub fn crossings(&self, ray: &Line) -> Vec<SurfaceCrossing> {
+        let mut crossings = Vec::new();
+        let oc = ray.point - self.base;
+        let d_perp = ray.direction - self.axis * ray.direction.dot(&self.axis);
+        let oc_perp = oc - self.axis * oc.dot(&self.axis);
+
+        let a = d_perp.dot(&d_perp) as f64;
+        let b = 2.0 * d_perp.dot(&oc_perp) as f64;
+        let c = (oc_perp.dot(&oc_perp) - self.radius * self.radius) as f64;
+        for t in solve_quadratic(a, b, c) {
+            let t = t as f32;
+            let local = oc + ray.direction * t;
+            let h = local.dot(&self.axis);
+            if (0.0..=self.height).contains(&h) {
+                let radial = local - self.axis * h;
+                let normal = radial / self.radius;
+                let angle = radial.dot(&self.bitangent).atan2(radial.dot(&self.tangent));
+                let around = self.axis * (2.0 * std::f32::consts::PI);
+                crossings.push(SurfaceCrossing {
+                    t,
+                    normal,
+                    uv: (angle / (2.0 * std::f32::consts::PI) + 0.5, h / self.height),
+                    derivatives: Some(SurfaceDerivatives {
+                        dpdu: around.cross(&radial),
+                        dpdv: self.axis * self.height,
+                        dndu: around.cross(&normal),
+                        dndv: Vector::new(0.0, 0.0, 0.0),
+                    }),
+                });
+            }
+        }
+
+        for (center, normal) in [(self.base, -self.axis), (self.base + self.axis * self.height, self.axis)] {
+            if let Some(crossing) = cap_crossing(ray, center, normal, self.radius, self.tangent, self.bitangent) {
+                crossings.push(crossing);
+            }
+        }
+
+        crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+        crossings
+    }
+}
+
+// crossing with a round cap, used by Cylinder and Cone
+pub fn cap_crossing(ray: &Line, center: Vector, normal: Vector, radius: f32, tangent: Vector, bitangent: Vector) -> Option<SurfaceCrossing> {
+    let denom = normal.dot(&ray.direction);
+    if denom.abs() < 0.000001 {
+        return None;
+    }
+    let t = (center - ray.point).dot(&normal) / denom;
+    let local = ray.point_on_line(&t) - center;
+    if local.length_squared() > radius * radius {
+        return None;
+    }
+    let angle = local.dot(&bitangent).atan2(local.dot(&tangent));
+    Some(SurfaceCrossing {
+        t,
+        normal,
+        uv: (angle / (2.0 * std::f32::consts::PI) + 0.5, local.length() / radius),
+        derivatives: polar_derivatives(&local, radius, &tangent, &bitangent),
+    })
+}
+
+// derivatives of polar uv, u is the angle from the tangent towards the bitangent and v the
+// distance from the center over radius. None at the center, where u has no direction
+pub fn polar_derivatives(local: &Vector, radius: f32, tangent: &Vector, bitangent: &Vector) -> Option<SurfaceDerivatives> {
+    let length = local.length();
+    if length < 1e-6 {
+        return None;
+    }
+    let around = tangent.cross(bitangent) * (2.0 * std::f32::consts::PI);
+    Some(SurfaceDerivatives::flat(around.cross(local), *local * (radius / length)))
+}
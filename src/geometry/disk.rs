@@ -0,0 +1,65 @@
+use crate::math::{SurfaceCrossing, Vector};
+
+use super::cylinder::polar_derivatives;
+use super::Line;
+
+// flat circle, one sided like Surface. uv is polar: u is the angle, v the distance from the center
+#[derive(Debug, Clone, Copy)]
+pub struct Disk {
+    pub center: Vector,
+    pub normal: Vector,
+    pub radius: f32,
+    tangent: Vector,
+    bitangent: Vector,
+}
+
+impl Disk {
+    pub fn new(center: Vector, normal: Vector, radius: f32) -> Disk {
+        let normal = normal._normalize();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Disk {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+        }
+    }
+
+    pub fn crossing(&self, ray: &Line) -> Option<SurfaceCrossing> {
+        let denom = self.normal.dot(&ray.direction);
+        if denom.abs() < 0.0001 {
+            return None;
+        }
+        let t = (self.center - ray.point).dot(&self.normal) / denom;
+        let point = ray.point_on_line(&t);
+        if (point - self.center).length_squared() > self.radius * self.radius {
+            return None;
+        }
+        Some(SurfaceCrossing {
+            t,
+            normal: self.normal,
+            uv: self.get_uv(&point),
+            derivatives: polar_derivatives(&(point - self.center), self.radius, &self.tangent, &self.bitangent),
+        })
+    }
+
+    pub fn area(&self) -> f32 {
+        std::f32::consts::PI * self.radius * self.radius
+    }
+
+    // uniformly distributed point on the disk for u, v in 0..1
+    pub fn sample_surface(&self, u: f32, v: f32) -> Vector {
+        let r = self.radius * u.sqrt();
+        let angle = 2.0 * std::f32::consts::PI * v;
+        self.center + self.tangent * (r * angle.cos()) + self.bitangent * (r * angle.sin())
+    }
+
+    pub fn get_uv(&self, point: &Vector) -> (f32, f32) {
+        let local = *point - self.center;
+        let angle = local.dot(&self.bitangent).atan2(local.dot(&self.tangent));
+        let u = angle / (2.0 * std::f32::consts::PI) + 0.5;
+        let v = local.length() / self.radius;
+        (u, v)
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use crate::math::{IntersectionPrimitive, Mat4, Quaternion, SurfaceDerivatives, Vector};
+
+use super::Line;
+
+// places a primitive in the world with an object-to-world matrix. the wrapped
+// primitive is shared, so one mesh can be placed many times without copying it.
+#[derive(Clone)]
+pub struct Instance {
+    pub object: Arc<dyn IntersectionPrimitive + Send + Sync>,
+    object_to_world: Mat4,
+    world_to_object: Mat4,
+    // inverse transpose of object_to_world, used for normals
+    normal_matrix: Mat4,
+    // factor from object space to world space areas, none if the matrix stretches some
+    // directions more than others
+    area_scale: Option<f32>,
+}
+
+impl Instance {
+    // None if the matrix is singular, it has to be inverted to bring rays into object space
+    pub fn new(object: Arc<dyn IntersectionPrimitive + Send + Sync>, object_to_world: Mat4) -> Option<Instance> {
+        let world_to_object = object_to_world.inverted()?;
+        Some(Instance {
+            object,
+            object_to_world,
+            world_to_object,
+            normal_matrix: world_to_object.transposed(),
+            area_scale: uniform_area_scale(&object_to_world),
+        })
+    }
+
+    // translation * rotation * scale, the usual order for placing objects. None for a scale of 0
+    pub fn from_trs(object: Arc<dyn IntersectionPrimitive + Send + Sync>, translation: Vector, rotation: Quaternion, scale: Vector) -> Option<Instance> {
+        let mut mat = Mat4::identity();
+        mat.translate(translation);
+        mat.multiply(&rotation.to_mat4());
+        mat.multiply_scale(scale);
+        Instance::new(object, mat)
+    }
+
+    // returns the ray in object space and the factor turning object space t back into world space t.
+    // the direction is normalized again, primitives like Sphere expect unit directions
+    pub fn ray_to_object(&self, ray: &Line) -> (Line, f32) {
+        let point = self.world_to_object.transform_point(&ray.point);
+        let direction = self.world_to_object.transform_direction(&ray.direction);
+        let length = direction.length();
+        (Line::new(point, direction / length), 1.0 / length)
+    }
+
+    pub fn area_scale(&self) -> Option<f32> {
+        self.area_scale
+    }
+
+    pub fn point_to_world(&self, point: &Vector) -> Vector {
+        self.object_to_world.transform_point(point)
+    }
+
+    // unit length, for tangents
+    pub fn direction_to_world(&self, direction: &Vector) -> Vector {
+        self.object_to_world.transform_direction(direction)._normalize()
+    }
+
+    pub fn normal_to_world(&self, normal: &Vector) -> Vector {
+        self.normal_matrix.transform_direction(normal)._normalize()
+    }
+
+    // derivatives at a point with the object space normal. the normal is normalized after
+    // the transform, its derivatives lose the part along it
+    pub fn derivatives_to_world(&self, derivatives: &SurfaceDerivatives, normal: &Vector) -> SurfaceDerivatives {
+        let transformed = self.normal_matrix.transform_direction(normal);
+        let length = transformed.length();
+        let world_normal = transformed / length;
+        let normal_derivative = |dn: &Vector| {
+            let dn = self.normal_matrix.transform_direction(dn);
+            (dn - world_normal * world_normal.dot(&dn)) / length
+        };
+        SurfaceDerivatives {
+            dpdu: self.object_to_world.transform_direction(&derivatives.dpdu),
+            dpdv: self.object_to_world.transform_direction(&derivatives.dpdv),
+            dndu: normal_derivative(&derivatives.dndu),
+            dndv: normal_derivative(&derivatives.dndv),
+        }
+    }
+}
+
+// square of the scale when the matrix turns the axes into perpendicular vectors of the same
+// length, areas grow by it. none under non-uniform scale or shear
+fn uniform_area_scale(matrix: &Mat4) -> Option<f32> {
+    let axes = [Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), Vector::new(0.0, 0.0, 1.0)].map(|axis| matrix.transform_direction(&axis));
+    let scale = axes[0].length_squared();
+    let tolerance = 1e-4 * scale;
+    let uniform = axes.iter().all(|axis| (axis.length_squared() - scale).abs() <= tolerance)
+        && axes[0].dot(&axes[1]).abs() <= tolerance
+        && axes[1].dot(&axes[2]).abs() <= tolerance
+        && axes[2].dot(&axes[0]).abs() <= tolerance;
+    uniform.then_some(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Sphere;
+    use crate::math::as_radians;
+
+    use super::*;
+
+    #[test]
+    fn scaled_sphere_test() {
+        let sphere: Arc<dyn IntersectionPrimitive + Send + Sync> = Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0));
+        let instance = Instance::from_trs(sphere, Vector::new(0.0, 0.0, -10.0), Quaternion::identity(), Vector::new(1.0, 1.0, 4.0)).unwrap();
+        // the sphere is stretched to an ellipsoid reaching from z = -14 to z = -6
+        let hit = instance.intersect(&Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0)));
+        assert!((hit.distance - 6.0).abs() < 0.001);
+        assert!((hit.normal.unwrap() - Vector::new(0.0, 0.0, 1.0)).length() < 0.001);
+
+        // hitting the side, the normal must stay perpendicular to the stretched surface
+        let hit = instance.intersect(&Line::new(Vector::new(5.0, 0.0, -10.0), Vector::new(-1.0, 0.0, 0.0)));
+        assert!((hit.distance - 4.0).abs() < 0.001);
+        assert!((hit.normal.unwrap() - Vector::new(1.0, 0.0, 0.0)).length() < 0.001);
+    }
+
+    #[test]
+    fn shared_object_test() {
+        let sphere: Arc<dyn IntersectionPrimitive + Send + Sync> = Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0));
+        let mut rotation = Quaternion::identity();
+        rotation.rotate(as_radians(45.0), Vector::new(0.0, 1.0, 0.0));
+        let a = Instance::from_trs(sphere.clone(), Vector::new(-5.0, 0.0, 0.0), rotation, Vector::new(1.0, 1.0, 1.0)).unwrap();
+        let b = Instance::from_trs(sphere.clone(), Vector::new(5.0, 0.0, 0.0), Quaternion::identity(), Vector::new(2.0, 2.0, 2.0)).unwrap();
+        let ray = Line::new(Vector::new(-5.0, 0.0, 10.0), Vector::new(0.0, 0.0, -1.0));
+        assert!((a.intersect(&ray).distance - 9.0).abs() < 0.001);
+        assert!(b.intersect(&ray).is_none());
+        assert_eq!(Arc::strong_count(&sphere), 3);
+        assert!(Instance::from_trs(sphere, Vector::new(0.0, 0.0, 0.0), Quaternion::identity(), Vector::new(1.0, 0.0, 1.0)).is_none());
+    }
+}
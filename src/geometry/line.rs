@@ -2,18 +2,43 @@ use float_cmp::{approx_eq, F32Margin};
 
 
 use crate::math::{RayCastHit, Vector};
+use crate::spectrum::Wavelengths;
 
 use super::Surface;
 
+// the rays through the neighbouring pixels, to tell how much of a texture a pixel covers
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferentials {
+    pub x_point: Vector,
+    pub x_direction: Vector,
+    pub y_point: Vector,
+    pub y_direction: Vector,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Line {
     pub point: Vector,
     pub direction: Vector,
+    // in nanometers, set once the ray was split up into wavelengths by a dispersive material
+    pub wavelength: Option<f32>,
+    // in spectral mode, the wavelengths the three color channels of the ray stand for
+    pub spectral: Option<Wavelengths>,
+    // set for camera rays and carried across mirror reflection and refraction
+    pub differentials: Option<RayDifferentials>,
+    // density over solid angle of the direction of a bsdf sampled ray, emission it hits is
+    // weighted against light sampling. none for rays light sampling can't reach
+    pub bsdf_pdf: Option<f32>,
 }
 
 impl Line {
     pub fn new(point: Vector, direction: Vector) -> Line {
-        Line { point, direction }
+        Line { point, direction, wavelength: None, spectral: None, differentials: None, bsdf_pdf: None }
+    }
+
+    // secondary ray continuing this one, keeps the wavelengths. differentials have to be
+    // bent by whatever spawns the ray
+    pub fn spawn(&self, point: Vector, direction: Vector) -> Line {
+        Line { point, direction, wavelength: self.wavelength, spectral: self.spectral, differentials: None, bsdf_pdf: None }
     }
 
     pub fn from_points(start: Vector, end: Vector) -> Line {
@@ -22,6 +47,10 @@ impl Line {
         Line {
             point: start,
             direction: dir,
+            wavelength: None,
+            spectral: None,
+            differentials: None,
+            bsdf_pdf: None,
         }
     }
 
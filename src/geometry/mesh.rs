@@ -0,0 +1,35 @@
+use crate::math::Vector;
+
+use super::Triangle;
+
+// a list of triangles treated as a single object. meant to be shared between
+// several Instances (through an Arc), so it is only stored once.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub triangles: Vec<Triangle>,
+}
+
+impl Mesh {
+    pub fn add_triangle(&mut self, triangle: Triangle) {
+        self.triangles.push(triangle);
+    }
+
+    pub fn area(&self) -> f32 {
+        self.triangles.iter().map(|triangle| triangle.area()).sum()
+    }
+
+    // uniformly distributed point on the mesh with its normal. u picks a triangle by its
+    // area and is reused inside of it, walking the triangles makes this linear in their count
+    pub fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        let mut remaining = u * self.area();
+        for triangle in self.triangles.iter() {
+            let area = triangle.area();
+            if remaining <= area && area > 0.0 {
+                let point = triangle.sample_surface((remaining / area).clamp(0.0, 1.0), v);
+                return Some((point, triangle.normal));
+            }
+            remaining -= area;
+        }
+        self.triangles.last().map(|triangle| (triangle.sample_surface(1.0, v), triangle.normal))
+    }
+}
@@ -3,9 +3,27 @@ pub mod sphere;
 pub mod surface;
 pub mod triangle;
 pub mod create_geometry;
+pub mod mesh;
+pub mod instance;
+pub mod disk;
+pub mod cylinder;
+pub mod cone;
+pub mod torus;
+pub mod axis_aligned_box;
+pub mod csg;
+pub mod sdf;
 
-pub use line::Line;
+pub use line::{Line, RayDifferentials};
 pub use sphere::Sphere;
 pub use surface::Surface;
 pub use triangle::Triangle;
-pub use create_geometry::*;
\ No newline at end of file
+pub use create_geometry::*;
+pub use mesh::Mesh;
+pub use instance::Instance;
+pub use disk::Disk;
+pub use cylinder::Cylinder;
+pub use cone::Cone;
+pub use torus::Torus;
+pub use axis_aligned_box::AxisAlignedBox;
+pub use csg::Csg;
+pub use sdf::{Sdf, SdfPrimitive};
\ No newline at end of file
@@ -0,0 +1,196 @@
+use crate::math::noise::fbm;
+use crate::math::Vector;
+
+use super::{AxisAlignedBox, Line};
+
+// signed distance field, negative inside the shape. built as a tree of shapes and operations.
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere { center: Vector, radius: f32 },
+    // box with edges rounded by radius, half_size includes the rounding
+    RoundedBox { center: Vector, half_size: Vector, radius: f32 },
+    // torus lying in the xz plane
+    Torus { center: Vector, major_radius: f32, minor_radius: f32 },
+    // power 8 gives the classic mandelbulb. the fractal fits in a sphere of about 1.2 * scale
+    Mandelbulb { center: Vector, scale: f32, power: f32, iterations: u32 },
+    // union blending the two shapes together over a distance of about k
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f32),
+    // moves the surface by fbm noise of the given amplitude and frequency.
+    // the result is no exact distance anymore, lower the step scale of the SdfPrimitive
+    Displace { sdf: Box<Sdf>, amplitude: f32, frequency: f32 },
+}
+
+impl Sdf {
+    pub fn smooth_union(a: Sdf, b: Sdf, k: f32) -> Sdf {
+        Sdf::SmoothUnion(Box::new(a), Box::new(b), k)
+    }
+
+    pub fn displace(sdf: Sdf, amplitude: f32, frequency: f32) -> Sdf {
+        Sdf::Displace { sdf: Box::new(sdf), amplitude, frequency }
+    }
+
+    pub fn distance(&self, p: &Vector) -> f32 {
+        match self {
+            Sdf::Sphere { center, radius } => (*p - *center).length() - radius,
+            Sdf::RoundedBox { center, half_size, radius } => {
+                let d = *p - *center;
+                let q = Vector::new(
+                    d.x.abs() - half_size.x + radius,
+                    d.y.abs() - half_size.y + radius,
+                    d.z.abs() - half_size.z + radius,
+                );
+                let outside = Vector::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+                let inside = q.x.max(q.y).max(q.z).min(0.0);
+                outside + inside - radius
+            }
+            Sdf::Torus { center, major_radius, minor_radius } => {
+                let d = *p - *center;
+                let ring = (d.x * d.x + d.z * d.z).sqrt() - major_radius;
+                (ring * ring + d.y * d.y).sqrt() - minor_radius
+            }
+            Sdf::Mandelbulb { center, scale, power, iterations } => mandelbulb((*p - *center) / *scale, *power, *iterations) * scale,
+            Sdf::SmoothUnion(a, b, k) => {
+                let da = a.distance(p);
+                let db = b.distance(p);
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db + (da - db) * h - k * h * (1.0 - h)
+            }
+            Sdf::Displace { sdf, amplitude, frequency } => sdf.distance(p) + amplitude * fbm(&(*p * *frequency), 4, 2.0, 0.5),
+        }
+    }
+}
+
+// distance estimate of the mandelbulb around the origin
+fn mandelbulb(p: Vector, power: f32, iterations: u32) -> f32 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r).clamp(-1.0, 1.0).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vector::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + p;
+        r = z.length();
+    }
+    if r < 1e-6 {
+        return 0.0;
+    }
+    0.5 * r.ln() * r / dr
+}
+
+// renders an Sdf by sphere tracing: step along the ray by the distance to the surface until
+// it is closer than hit_epsilon. tracing only happens inside the bounds, which have to contain the shape.
+#[derive(Debug, Clone)]
+pub struct SdfPrimitive {
+    pub sdf: Sdf,
+    pub bounds: AxisAlignedBox,
+    pub max_steps: u32,
+    pub hit_epsilon: f32,
+    // multiplies every step, values below 1 are needed for fields that overestimate the distance
+    pub step_scale: f32,
+}
+
+impl SdfPrimitive {
+    pub fn new(sdf: Sdf, bounds: AxisAlignedBox) -> SdfPrimitive {
+        SdfPrimitive {
+            sdf,
+            bounds,
+            max_steps: 256,
+            hit_epsilon: 0.001,
+            step_scale: 1.0,
+        }
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> SdfPrimitive {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_hit_epsilon(mut self, hit_epsilon: f32) -> SdfPrimitive {
+        self.hit_epsilon = hit_epsilon;
+        self
+    }
+
+    pub fn with_step_scale(mut self, step_scale: f32) -> SdfPrimitive {
+        self.step_scale = step_scale;
+        self
+    }
+
+    // distance along the ray to the surface, None if the ray leaves the bounds or runs out of steps
+    pub fn trace(&self, ray: &Line) -> Option<f32> {
+        let (near, far) = self.bounds.slab(ray)?;
+        if far < 0.0 {
+            return None;
+        }
+        let mut t = near.max(0.0);
+        // shadow and bounce rays start on the surface, they have to get away from it before anything counts as a hit
+        let mut leaving = near <= 0.0;
+        for _ in 0..self.max_steps {
+            let distance = self.sdf.distance(&ray.point_on_line(&t)).abs();
+            if distance < self.hit_epsilon {
+                if !leaving {
+                    return Some(t);
+                }
+            } else {
+                leaving = false;
+            }
+            t += distance.max(self.hit_epsilon) * self.step_scale;
+            if t > far {
+                return None;
+            }
+        }
+        None
+    }
+
+    // gradient of the field by central differences
+    pub fn normal(&self, p: &Vector) -> Vector {
+        let h = self.hit_epsilon;
+        let dx = Vector::new(h, 0.0, 0.0);
+        let dy = Vector::new(0.0, h, 0.0);
+        let dz = Vector::new(0.0, 0.0, h);
+        let gradient = Vector::new(
+            self.sdf.distance(&(*p + dx)) - self.sdf.distance(&(*p - dx)),
+            self.sdf.distance(&(*p + dy)) - self.sdf.distance(&(*p - dy)),
+            self.sdf.distance(&(*p + dz)) - self.sdf.distance(&(*p - dz)),
+        );
+        gradient._normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_trace_test() {
+        let sphere = Sdf::Sphere { center: Vector::new(0.0, 0.0, -10.0), radius: 2.0 };
+        let primitive = SdfPrimitive::new(sphere, AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, -10.0), Vector::from_num(5.0)));
+        let ray = Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        let t = primitive.trace(&ray).unwrap();
+        assert!((t - 8.0).abs() < 0.01);
+        let normal = primitive.normal(&ray.point_on_line(&t));
+        assert!((normal - Vector::new(0.0, 0.0, 1.0)).length() < 0.01);
+
+        // too few steps to reach the surface
+        let capped = primitive.clone().with_max_steps(1);
+        let grazing = Line::new(Vector::new(0.0, 1.99, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert!(capped.trace(&grazing).is_none());
+        assert!(primitive.trace(&grazing).is_some());
+    }
+
+    #[test]
+    fn smooth_union_test() {
+        let a = Sdf::Sphere { center: Vector::new(-1.0, 0.0, 0.0), radius: 1.0 };
+        let b = Sdf::Sphere { center: Vector::new(1.0, 0.0, 0.0), radius: 1.0 };
+        let blended = Sdf::smooth_union(a.clone(), b.clone(), 0.5);
+        // the blend fills in where the spheres touch, far away it's either sphere
+        let middle = Vector::new(0.0, 0.3, 0.0);
+        assert!(blended.distance(&middle) < a.distance(&middle).min(b.distance(&middle)));
+        let far = Vector::new(-3.0, 0.0, 0.0);
+        assert!((blended.distance(&far) - a.distance(&far)).abs() < 1e-4);
+    }
+}
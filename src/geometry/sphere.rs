@@ -1,4 +1,6 @@
-use crate::math::Vector;
+use crate::math::{solve_quadratic, SurfaceCrossing, SurfaceDerivatives, Vector};
+
+use super::Line;
 
 
 pub struct Sphere {
@@ -20,4 +22,71 @@ impl Sphere {
     pub fn get_radius_squared(&self) -> f32 {
         self.radius_squared
     }
+
+    // spherical coordinates, u around the y axis, v from the bottom to the top
+    pub fn get_uv(&self, point: &Vector) -> (f32, f32) {
+        let n = (*point - self.center) / self.radius;
+        let u = n.z.atan2(n.x) / (2.0 * std::f32::consts::PI) + 0.5;
+        let v = n.y.clamp(-1.0, 1.0).asin() / std::f32::consts::PI + 0.5;
+        (u, v)
+    }
+
+    // direction of increasing u at the point with this normal, None at the poles
+    pub fn get_tangent(&self, normal: &Vector) -> Option<Vector> {
+        let tangent = Vector::new(-normal.z, 0.0, normal.x);
+        if tangent.length_squared() < 1e-8 {
+            return None;
+        }
+        Some(tangent._normalize())
+    }
+
+    // derivatives of get_uv at the point with this normal, None at the poles
+    pub fn get_derivatives(&self, normal: &Vector) -> Option<SurfaceDerivatives> {
+        let ring = (normal.x * normal.x + normal.z * normal.z).sqrt();
+        if ring < 1e-4 {
+            return None;
+        }
+        let dndu = Vector::new(-normal.z, 0.0, normal.x) * (2.0 * std::f32::consts::PI);
+        let dndv = Vector::new(-normal.y * normal.x / ring, ring, -normal.y * normal.z / ring) * std::f32::consts::PI;
+        Some(SurfaceDerivatives { dpdu: dndu * self.radius, dpdv: dndv * self.radius, dndu, dndv })
+    }
+
+    pub fn area(&self) -> f32 {
+        4.0 * std::f32::consts::PI * self.radius_squared
+    }
+
+    // uniformly distributed point on the sphere for u, v in 0..1, with its normal
+    pub fn sample_surface(&self, u: f32, v: f32) -> (Vector, Vector) {
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * v;
+        let normal = Vector::new(r * phi.cos(), r * phi.sin(), z);
+        (self.center + normal * self.radius, normal)
+    }
+
+    // both points where the infinite ray crosses the sphere, sorted by t
+    pub fn crossings(&self, ray: &Line) -> Vec<SurfaceCrossing> {
+        let oc = ray.point - self.center;
+        let a = ray.direction.dot(&ray.direction) as f64;
+        let b = 2.0 * oc.dot(&ray.direction) as f64;
+        let c = (oc.dot(&oc) - self.radius_squared) as f64;
+        let roots = solve_quadratic(a, b, c);
+        if roots.len() < 2 {
+            return Vec::new();
+        }
+        roots
+            .into_iter()
+            .map(|t| {
+                let t = t as f32;
+                let point = ray.point_on_line(&t);
+                let normal = (point - self.center) / self.radius;
+                SurfaceCrossing {
+                    t,
+                    normal,
+                    uv: self.get_uv(&point),
+                    derivatives: self.get_derivatives(&normal),
+                }
+            })
+            .collect()
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::math::{Vector, Quaternion};
+use crate::math::{Vector, Quaternion, SurfaceDerivatives};
 
 // Surface is defined by a point and a normal vector
 #[derive(Debug, Clone, Copy)]
@@ -114,6 +114,30 @@ impl Surface {
         (t, s)
     }
 
+    // derivatives of the uv of a bounded surface
+    pub fn get_derivatives(&self) -> Option<SurfaceDerivatives> {
+        match (self.v, self.w, self.max_v, self.max_w) {
+            (Some(v), Some(w), Some(max_v), Some(max_w)) => Some(SurfaceDerivatives::flat(v * (max_v.1 - max_v.0), w * (max_w.1 - max_w.0))),
+            _ => None,
+        }
+    }
+
+    // area of a bounded surface, None if it's infinite
+    pub fn area(&self) -> Option<f32> {
+        match (self.v, self.w, self.max_v, self.max_w) {
+            (Some(v), Some(w), Some(max_v), Some(max_w)) => Some(v.cross(&w).length() * (max_v.1 - max_v.0) * (max_w.1 - max_w.0)),
+            _ => None,
+        }
+    }
+
+    // uniformly distributed point on a bounded surface for u, v in 0..1
+    pub fn sample_surface(&self, u: f32, v: f32) -> Option<Vector> {
+        let (max_v, max_w) = (self.max_v?, self.max_w?);
+        let t = max_v.0 + (max_v.1 - max_v.0) * u;
+        let s = max_w.0 + (max_w.1 - max_w.0) * v;
+        self.point_on_surface(&t, &s)
+    }
+
     pub fn translate(&mut self, v: &Vector) {
         self.point += *v;
     }
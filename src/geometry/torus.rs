@@ -0,0 +1,94 @@
+use crate::math::{solve_quartic, SurfaceCrossing, SurfaceDerivatives, Vector};
+
+use super::Line;
+
+// ring around the axis. major_radius is the distance from the center to the middle of the tube,
+// minor_radius is the radius of the tube. u goes around the axis, v goes around the tube.
+#[derive(Debug, Clone, Copy)]
+pub struct Torus {
+    pub center: Vector,
+    pub axis: Vector,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    tangent: Vector,
+    bitangent: Vector,
+}
+
+impl Torus {
+    pub fn new(center: Vector, axis: Vector, major_radius: f32, minor_radius: f32) -> Torus {
+        let axis = axis._normalize();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        Torus {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            tangent,
+            bitangent,
+        }
+    }
+
+    fn local_coords(&self, v: &Vector) -> [f64; 3] {
+        [v.dot(&self.tangent) as f64, v.dot(&self.bitangent) as f64, v.dot(&self.axis) as f64]
+    }
+
+    // every point where the infinite ray crosses the surface, sorted by t
+    pub fn crossings(&self, ray: &Line) -> Vec<SurfaceCrossing> {
+        // everything is solved in the torus' local frame (axis = z), scaled so the major radius is 1.
+        // the origin is also moved to the point of the ray closest to the center first,
+        // otherwise the quartic coefficients get huge and f32 scenes lose all precision.
+        let scale = 1.0 / self.major_radius as f64;
+        let d = self.local_coords(&ray.direction);
+        let o = self.local_coords(&(ray.point - self.center));
+        let o = [o[0] * scale, o[1] * scale, o[2] * scale];
+        let shift = -(o[0] * d[0] + o[1] * d[1] + o[2] * d[2]);
+        let o = [o[0] + d[0] * shift, o[1] + d[1] * shift, o[2] + d[2] * shift];
+
+        let r2 = (self.minor_radius as f64 * scale).powi(2);
+        let dd = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+        let f = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let e = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] - 1.0 - r2;
+
+        // (|p|^2 - R^2 - r^2)^2 + 4R^2 (z^2 - r^2) = 0 with R = 1, divided by dd^2
+        let c4 = dd * dd;
+        let c3 = 4.0 * f * dd;
+        let c2 = 2.0 * e * dd + 4.0 * f * f + 4.0 * d[2] * d[2];
+        let c1 = 4.0 * f * e + 8.0 * o[2] * d[2];
+        let c0 = e * e + 4.0 * (o[2] * o[2] - r2);
+
+        let mut crossings = Vec::new();
+        for t in solve_quartic(c3 / c4, c2 / c4, c1 / c4, c0 / c4) {
+            let world_t = ((t + shift) / scale) as f32;
+            let point = ray.point_on_line(&world_t);
+            let local = point - self.center;
+            let (x, y, z) = (local.dot(&self.tangent), local.dot(&self.bitangent), local.dot(&self.axis));
+
+            // direction from the middle of the tube to the point
+            let ring = (x * x + y * y).sqrt();
+            let tube_center = if ring > 0.0 {
+                self.tangent * (x / ring * self.major_radius) + self.bitangent * (y / ring * self.major_radius)
+            } else {
+                self.tangent * self.major_radius
+            };
+            let normal = (local - tube_center)._normalize();
+
+            let u = y.atan2(x) / (2.0 * std::f32::consts::PI) + 0.5;
+            let v = z.atan2(ring - self.major_radius) / (2.0 * std::f32::consts::PI) + 0.5;
+            // u turns the point around the axis, v turns it around the middle of the tube
+            let around = self.axis * (2.0 * std::f32::consts::PI);
+            let derivatives = (ring > 0.0).then(|| {
+                let ring_dir = (local - self.axis * z) / ring;
+                let dndv = (self.axis * normal.dot(&ring_dir) - ring_dir * normal.dot(&self.axis)) * (2.0 * std::f32::consts::PI);
+                SurfaceDerivatives { dpdu: around.cross(&local), dpdv: dndv * self.minor_radius, dndu: around.cross(&normal), dndv }
+            });
+            crossings.push(SurfaceCrossing {
+                t: world_t,
+                normal,
+                uv: (u, v),
+                derivatives,
+            });
+        }
+        crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+        crossings
+    }
+}
@@ -1,4 +1,6 @@
-use crate::Vector;
+use crate::math::{SurfaceCrossing, SurfaceDerivatives, Vector};
+
+use super::Line;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Triangle {
@@ -15,6 +17,45 @@ impl Triangle {
         Triangle { vertices, normal, color }
     }
 
+    pub fn area(&self) -> f32 {
+        (self.vertices[1] - self.vertices[0]).cross(&(self.vertices[2] - self.vertices[0])).length() * 0.5
+    }
+
+    // uniformly distributed point on the triangle for u, v in 0..1
+    pub fn sample_surface(&self, u: f32, v: f32) -> Vector {
+        let su = u.sqrt();
+        self.vertices[0] * (1.0 - su) + self.vertices[1] * (su * (1.0 - v)) + self.vertices[2] * (su * v)
+    }
+
+    // moller-trumbore without the t > 0 check, t is the crossing of the infinite ray.
+    // uv are the barycentric coordinates of the crossing
+    pub fn crossing(&self, ray: &Line) -> Option<SurfaceCrossing> {
+        let v0v1 = self.vertices[1] - self.vertices[0];
+        let v0v2 = self.vertices[2] - self.vertices[0];
+        let pvec = ray.direction.cross(&v0v2);
+        let det = v0v1.dot(&pvec);
+        if det.abs() < 0.0001 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = ray.point - self.vertices[0];
+        let u = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let qvec = tvec.cross(&v0v1);
+        let v = ray.direction.dot(&qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        Some(SurfaceCrossing {
+            t: v0v2.dot(&qvec) * inv_det,
+            normal: self.normal,
+            uv: (u, v),
+            derivatives: Some(SurfaceDerivatives::flat(v0v1, v0v2)),
+        })
+    }
+
     pub fn to_string(&self) -> String {
         format!(
             "Triangle: vertices: {:?}, normal: {:?}, color: {:?}",
@@ -0,0 +1,161 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+// photometric data of a luminaire from an IES LM-63 file, as type C photometry: vertical
+// angles go from 0 straight down the axis of the light to 180 straight up, horizontal
+// angles turn around the axis. candela values are stored per horizontal angle
+#[derive(Debug, Clone, PartialEq)]
+pub struct IesProfile {
+    pub vertical_angles: Vec<f32>,
+    pub horizontal_angles: Vec<f32>,
+    pub candela: Vec<Vec<f32>>,
+    max_candela: f32,
+}
+
+impl IesProfile {
+    // candela rows have one value per vertical angle, one row per horizontal angle. both angle
+    // lists are in degrees and ascending
+    pub fn new(vertical_angles: Vec<f32>, horizontal_angles: Vec<f32>, candela: Vec<Vec<f32>>) -> IesProfile {
+        assert!(!vertical_angles.is_empty() && !horizontal_angles.is_empty(), "ies profiles need at least one angle");
+        assert!(candela.len() == horizontal_angles.len() && candela.iter().all(|row| row.len() == vertical_angles.len()), "candela values don't match the angles");
+        let max_candela = candela.iter().flatten().cloned().fold(0.0, f32::max);
+        IesProfile { vertical_angles, horizontal_angles, candela, max_candela }
+    }
+
+    pub fn load(path: &str) -> Result<IesProfile> {
+        IesProfile::parse(&fs::read_to_string(path)?)
+    }
+
+    // the keyword lines before TILT are skipped, tilt data is read but ignored
+    pub fn parse(text: &str) -> Result<IesProfile> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("ies: {}", message));
+        let mut lines = text.lines();
+        let tilt = lines
+            .by_ref()
+            .find(|line| line.trim_start().starts_with("TILT="))
+            .ok_or_else(|| invalid("no TILT line"))?;
+        let mut numbers = lines.flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ',')).filter(|s| !s.is_empty()).map(|s| s.parse::<f32>());
+        let mut next = || numbers.next().ok_or_else(|| invalid("ends early"))?.map_err(|_| invalid("not a number"));
+
+        if tilt.trim() == "TILT=INCLUDE" {
+            // lamp to luminaire geometry, then angles and multiplying factors
+            next()?;
+            let count = next()? as usize;
+            for _ in 0..count * 2 {
+                next()?;
+            }
+        }
+        let (_lamps, _lumens, multiplier) = (next()?, next()?, next()?);
+        let (vertical_count, horizontal_count) = (next()? as usize, next()? as usize);
+        let photometric_type = next()?;
+        if photometric_type != 1.0 {
+            return Err(invalid("only type C photometry is supported"));
+        }
+        // units, width, length, height
+        for _ in 0..4 {
+            next()?;
+        }
+        let (ballast, _future_use, _watts) = (next()?, next()?, next()?);
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<Result<Vec<f32>>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<Result<Vec<f32>>>()?;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count).map(|_| next().map(|value| value * multiplier * ballast)).collect::<Result<Vec<f32>>>()?;
+            candela.push(row);
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid("no angles"));
+        }
+        Ok(IesProfile::new(vertical_angles, horizontal_angles, candela))
+    }
+
+    pub fn max_candela(&self) -> f32 {
+        self.max_candela
+    }
+
+    // bilinear lookup in candela, angles in degrees. the horizontal angle is folded into the
+    // range the file covers, so symmetric luminaires only need part of the table. outside of
+    // the vertical range there is no light
+    pub fn candela_at(&self, vertical: f32, horizontal: f32) -> f32 {
+        let (first, last) = (self.vertical_angles[0], self.vertical_angles[self.vertical_angles.len() - 1]);
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+        let horizontal = horizontal.rem_euclid(360.0);
+        let last_horizontal = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let horizontal = if last_horizontal == 0.0 {
+            // the same in every direction
+            0.0
+        } else if last_horizontal <= 90.0 {
+            // symmetric in every quadrant
+            let h = horizontal % 180.0;
+            if h > 90.0 { 180.0 - h } else { h }
+        } else if last_horizontal <= 180.0 && horizontal > 180.0 {
+            // symmetric to the 0-180 plane
+            360.0 - horizontal
+        } else {
+            horizontal
+        };
+        let (h0, h1, th) = bracket(&self.horizontal_angles, horizontal);
+        let (v0, v1, tv) = bracket(&self.vertical_angles, vertical);
+        let row = |h: usize| self.candela[h][v0] * (1.0 - tv) + self.candela[h][v1] * tv;
+        row(h0) * (1.0 - th) + row(h1) * th
+    }
+}
+
+// indices of the angles around value and how far it is from the first to the second
+fn bracket(angles: &[f32], value: f32) -> (usize, usize, f32) {
+    let upper = angles.partition_point(|angle| *angle < value);
+    if upper == 0 {
+        return (0, 0, 0.0);
+    }
+    if upper >= angles.len() {
+        // past the last horizontal angle of a full table, wrap around to 360 = 0
+        let last = angles.len() - 1;
+        let span = 360.0 - angles[last] + angles[0];
+        return if span > 0.0 && angles[last] < 360.0 { (last, 0, ((value - angles[last]) / span).clamp(0.0, 1.0)) } else { (last, last, 0.0) };
+    }
+    let (a, b) = (angles[upper - 1], angles[upper]);
+    (upper - 1, upper, if b > a { (value - a) / (b - a) } else { 0.0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[MANUFAC] test
+TILT=NONE
+1 1000 2 3 2 1 2 0.1 0.1 0
+1 1 10
+0 45 90
+0 90
+100 50 0
+80 40 0
+";
+
+    #[test]
+    fn parse_test() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0.0, 45.0, 90.0]);
+        assert_eq!(profile.horizontal_angles, vec![0.0, 90.0]);
+        // the multiplier of 2 is applied
+        assert_eq!(profile.max_candela(), 200.0);
+        assert!(IesProfile::parse("TILT=NONE\n1 1000").is_err());
+        assert!(IesProfile::parse("no tilt").is_err());
+    }
+
+    #[test]
+    fn candela_test() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.candela_at(0.0, 0.0), 200.0);
+        assert_eq!(profile.candela_at(22.5, 0.0), 150.0);
+        assert_eq!(profile.candela_at(0.0, 45.0), 180.0);
+        // quadrant symmetry: 180 looks like 0, 270 like 90
+        assert_eq!(profile.candela_at(45.0, 180.0), 100.0);
+        assert_eq!(profile.candela_at(45.0, 270.0), 80.0);
+        // no light above the horizon
+        assert_eq!(profile.candela_at(120.0, 0.0), 0.0);
+    }
+}
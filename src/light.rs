@@ -1,4 +1,7 @@
-use crate::{color::Color, math::Vector};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::{color::Color, ies::IesProfile, math::Vector, spectrum::{emission_for, unbounded_for, EmissionSpectrum, Wavelengths}};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct LightCalculationData {
@@ -8,15 +11,21 @@ pub struct LightCalculationData {
     pub base_color: Color,
     pub shininess: f32,
     pub specular_amount: f32,
+    // wavelengths of the ray in spectral mode
+    pub spectral: Option<Wavelengths>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LightType {
     Point,
     Ambient,
+    // point light shining into a cone around its direction
+    Spot,
+    // point sampled on an emissive primitive, shining into the half space its normal (direction) points to
+    Area,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Light {
     pub light_type: LightType,
     pub position: Vector,
@@ -24,6 +33,17 @@ pub struct Light {
     pub strength: f32,
                    // const, lin, quad
     pub attenuation: (f32, f32, f32),
+    // spot lights only
+    pub direction: Vector,
+    // full strength inside the inner angle, fades out until the outer angle. half angles in radians
+    pub cone_angles: (f32, f32),
+    // emission spectrum, color then only scales it. None is an rgb light
+    pub spectrum: Option<EmissionSpectrum>,
+    // candela around direction, which is the 0 degree axis of the profile
+    pub profile: Option<Arc<IesProfile>>,
+    // area of the emitting surface the light stands for, 0 for lights only light sampling can
+    // reach
+    pub area: f32,
 }
 
 impl Light {
@@ -34,6 +54,11 @@ impl Light {
             color,
             strength,
             attenuation,
+            direction: Vector::new(0.0, -1.0, 0.0),
+            cone_angles: (0.0, 0.0),
+            spectrum: None,
+            profile: None,
+            area: 0.0,
         }
     }
 
@@ -44,6 +69,11 @@ impl Light {
             color,
             strength,
             attenuation: (0.0, 0.0, 0.0),
+            direction: Vector::new(0.0, -1.0, 0.0),
+            cone_angles: (0.0, 0.0),
+            spectrum: None,
+            profile: None,
+            area: 0.0,
         }
     }
 
@@ -54,13 +84,156 @@ impl Light {
             color,
             strength: 1.0,
             attenuation,
+            direction: Vector::new(0.0, -1.0, 0.0),
+            cone_angles: (0.0, 0.0),
+            spectrum: None,
+            profile: None,
+            area: 0.0,
+        }
+    }
+
+    pub fn new_spot(position: Vector, direction: Vector, color: Color, attenuation: (f32, f32, f32), inner_angle: f32, outer_angle: f32) -> Light {
+        Light {
+            light_type: LightType::Spot,
+            position,
+            color,
+            strength: 1.0,
+            attenuation,
+            direction: direction._normalize(),
+            cone_angles: (inner_angle.min(outer_angle), outer_angle),
+            spectrum: None,
+            profile: None,
+            area: 0.0,
+        }
+    }
+
+    // a point on an emitting surface with that radiance, standing for area of it. light colors
+    // are what a white diffuse surface reflects, radiance from a small area reflects radiance
+    // * area * cos / (pi * d^2)
+    pub fn new_area_sample(position: Vector, normal: Vector, radiance: Color, area: f32) -> Light {
+        Light {
+            light_type: LightType::Area,
+            position,
+            color: radiance * (area / PI),
+            strength: 1.0,
+            attenuation: (0.0, 0.0, 1.0),
+            direction: normal,
+            cone_angles: (0.0, 0.0),
+            spectrum: None,
+            profile: None,
+            area,
+        }
+    }
+
+    // the light stands for a surface of that area facing the shaded points, so bsdf sampled
+    // rays can hit it as well
+    pub fn with_area(mut self, area: f32) -> Light {
+        self.area = area.max(0.0);
+        self
+    }
+
+    // blackbody or standard illuminant emission, the color should be a gray intensity then
+    pub fn with_spectrum(mut self, spectrum: EmissionSpectrum) -> Light {
+        self.spectrum = Some(spectrum);
+        self
+    }
+
+    // photometric distribution of a luminaire, shining along direction (down for point lights).
+    // light falls off with the square of the distance in meters, color then is what a white
+    // surface lit with 1 lux reflects
+    pub fn with_ies_profile(mut self, profile: Arc<IesProfile>, units_per_meter: f32) -> Light {
+        self.profile = Some(profile);
+        self.attenuation = (0.0, 0.0, 1.0 / (units_per_meter * units_per_meter));
+        self
+    }
+
+    // color of the light for a ray with these wavelengths, rgb outside of spectral mode
+    pub fn emission(&self, spectral: Option<&Wavelengths>) -> Color {
+        match &self.spectrum {
+            Some(spectrum) => unbounded_for(&self.color, spectral) * spectrum.for_ray(spectral),
+            None => emission_for(&self.color, spectral),
+        }
+    }
+
+    // how much of the light reaches the point because of the cone or the facing of area samples,
+    // 1 for point and ambient lights. lights with a profile are scaled by its candela
+    pub fn spot_factor(&self, point: &Vector) -> f32 {
+        match &self.profile {
+            Some(profile) => self.cone_factor(point) * self.candela_towards(profile, point),
+            None => self.cone_factor(point),
+        }
+    }
+
+    fn candela_towards(&self, profile: &IesProfile, point: &Vector) -> f32 {
+        let to_point = (*point - self.position)._normalize();
+        let vertical = self.direction.dot(&to_point).clamp(-1.0, 1.0).acos().to_degrees();
+        // horizontal angles start at the first axis of the basis around direction
+        let (x, y) = self.direction.orthonormal_basis();
+        let horizontal = to_point.dot(&y).atan2(to_point.dot(&x)).to_degrees();
+        profile.candela_at(vertical, horizontal)
+    }
+
+    fn cone_factor(&self, point: &Vector) -> f32 {
+        if self.light_type == LightType::Area {
+            return self.direction.dot(&(*point - self.position)._normalize()).max(0.0);
+        }
+        if self.light_type != LightType::Spot {
+            return 1.0;
+        }
+        let cos_angle = self.direction.dot(&(*point - self.position)._normalize());
+        let cos_inner = self.cone_angles.0.cos();
+        let cos_outer = self.cone_angles.1.cos();
+        if cos_inner - cos_outer < 1e-6 {
+            return if cos_angle >= cos_outer { 1.0 } else { 0.0 };
+        }
+        let x = ((cos_angle - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+        x * x * (3.0 - 2.0 * x)
+    }
+
+    // how far shadow rays towards the light go. area samples lie on a surface, the ray must not hit that surface
+    pub fn shadow_distance(&self, point: &Vector) -> f32 {
+        let distance = self.position.distance(point);
+        match self.light_type {
+            LightType::Area => distance * 0.999,
+            _ => distance,
         }
     }
 
+    // direction from the point to the light and the light arriving from it, unshadowed. none
+    // for ambient lights
+    pub fn sample(&self, point: &Vector, spectral: Option<&Wavelengths>) -> Option<(Vector, Color)> {
+        if self.light_type == LightType::Ambient {
+            return None;
+        }
+        Some(((self.position - *point)._normalize(), self.radiance_at(point, spectral)))
+    }
+
+    // density over solid angle of the direction to the light seen from the point, as if it was
+    // picked on its area. none for lights without an area
+    pub fn pdf(&self, point: &Vector) -> Option<f32> {
+        let cos = self.cone_factor(point);
+        if self.area <= 0.0 || cos <= 0.0 {
+            return None;
+        }
+        Some((self.position - *point).length_squared() / (cos * self.area))
+    }
+
+    // light arriving at the point, with distance attenuation and the spot cone
+    pub fn radiance_at(&self, point: &Vector, spectral: Option<&Wavelengths>) -> Color {
+        let color = self.emission(spectral);
+        if self.light_type == LightType::Ambient {
+            return color * self.strength;
+        }
+        let distance = self.position.distance(point);
+        let attenuation = 1.0 / (self.attenuation.0 + self.attenuation.1 * distance + self.attenuation.2 * distance * distance);
+        color * (attenuation * self.spot_factor(point))
+    }
+
     pub fn calculate_lighting(&self, data: &LightCalculationData) -> Color {
+        let color = self.emission(data.spectral.as_ref());
         match self.light_type {
-            LightType::Ambient => data.base_color * (self.color * self.strength),
-            LightType::Point => {
+            LightType::Ambient => data.base_color * (color * self.strength),
+            LightType::Point | LightType::Spot | LightType::Area => {
                 let mut col = Color::black();
                 // diffuse
                 let light_dir = (self.position - data.point)._normalize();
@@ -71,16 +244,16 @@ impl Light {
                         self.attenuation.1 * (self.position - data.point).length() + // linear
                         self.attenuation.2 * (self.position - data.point).length_squared() // quadratic
                     );
-                let diffuse_color = self.color * (diff * att);
+                let diffuse_color = color * (diff * att);
                 // specular
                 
                 let view_dir = -data.view_dir;
                 let reflect_dir = (-light_dir).reflect(&data.normal);
                 let spec = view_dir.dot(&reflect_dir).max(0.0).powf(data.shininess);
-                let specular_color = self.color * (spec * data.specular_amount * att);
+                let specular_color = color * (spec * data.specular_amount * att);
 
                 col += (diffuse_color + specular_color) * data.base_color;
-                col
+                col * self.spot_factor(&data.point)
             }
         }
     }
@@ -124,3 +297,27 @@ impl RectangleAreaLight {
         self.lights.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn area_sample_test() {
+        // a small patch of radiance 1 facing a white diffuse surface d below it. the irradiance
+        // is area / d^2, the surface reflects irradiance / pi of it
+        let (area, d) = (0.01, 2.0);
+        let light = Light::new_area_sample(Vector::new(0.0, d, 0.0), Vector::new(0.0, -1.0, 0.0), Color::white(), area);
+        let data = LightCalculationData {
+            point: Vector::new(0.0, 0.0, 0.0),
+            normal: Vector::new(0.0, 1.0, 0.0),
+            view_dir: Vector::new(0.0, -1.0, 0.0),
+            base_color: Color::white(),
+            shininess: 1.0,
+            specular_amount: 0.0,
+            spectral: None,
+        };
+        let reflected = light.calculate_lighting(&data);
+        assert!((reflected.r - area / (d * d) / PI).abs() < 1e-6, "{}", reflected.r);
+    }
+}
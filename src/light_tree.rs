@@ -0,0 +1,251 @@
+use std::f32::consts::PI;
+
+use crate::light::{Light, LightType};
+use crate::math::Vector;
+
+// directions a node's lights shine into: around axis within angle_o, each of them spreading
+// light angle_e further. point lights shine everywhere
+#[derive(Debug, Clone, Copy)]
+struct Cone {
+    axis: Vector,
+    angle_o: f32,
+    angle_e: f32,
+}
+
+impl Cone {
+    fn everywhere() -> Cone {
+        Cone { axis: Vector::new(0.0, 0.0, 1.0), angle_o: PI, angle_e: PI / 2.0 }
+    }
+
+    fn of(light: &Light) -> Cone {
+        match light.light_type {
+            LightType::Spot => Cone { axis: light.direction, angle_o: 0.0, angle_e: light.cone_angles.1 },
+            LightType::Area => Cone { axis: light.direction, angle_o: 0.0, angle_e: PI / 2.0 },
+            _ => Cone::everywhere(),
+        }
+    }
+
+    // smallest cone around both, as in pbrt
+    fn union(&self, other: &Cone) -> Cone {
+        let angle_e = self.angle_e.max(other.angle_e);
+        if self.angle_o >= PI || other.angle_o >= PI {
+            return Cone { angle_e, ..Cone::everywhere() };
+        }
+        let angle_d = self.axis.dot(&other.axis).clamp(-1.0, 1.0).acos();
+        if (angle_d + other.angle_o).min(PI) <= self.angle_o {
+            return Cone { angle_e, ..*self };
+        }
+        if (angle_d + self.angle_o).min(PI) <= other.angle_o {
+            return Cone { angle_e, ..*other };
+        }
+        let angle_o = (self.angle_o + angle_d + other.angle_o) / 2.0;
+        let rotation_axis = self.axis.cross(&other.axis);
+        if angle_o >= PI || rotation_axis.length_squared() < 1e-12 {
+            return Cone { angle_e, ..Cone::everywhere() };
+        }
+        // turn self's axis towards other's until the cone touches both
+        let k = rotation_axis._normalize();
+        let (sin, cos) = (angle_o - self.angle_o).sin_cos();
+        let axis = self.axis * cos + k.cross(&self.axis) * sin + k * (k.dot(&self.axis) * (1.0 - cos));
+        Cone { axis: axis._normalize(), angle_o, angle_e }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Children {
+    Leaf(usize),
+    Inner(usize, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LightNode {
+    min: Vector,
+    max: Vector,
+    power: f32,
+    // the weakest falloff of its lights, (constant, linear, quadratic)
+    attenuation: (f32, f32, f32),
+    cone: Cone,
+    children: Children,
+}
+
+// bounding volume hierarchy over the lights of a scene, "Importance Sampling of Many Lights
+// with Adaptive Tree Splitting" (Conty Estevez, Kulla 2018). every node estimates how much its
+// lights add to a point from their power, distance and the directions they shine into, and
+// sampling walks down the tree picking children in proportion to that. ambient lights are
+// not in the tree
+#[derive(Debug, Clone)]
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+}
+
+impl LightTree {
+    // indices in the returned samples refer to lights
+    pub fn new(lights: &[Light]) -> LightTree {
+        let mut tree = LightTree { nodes: Vec::new() };
+        let mut indices: Vec<usize> = (0..lights.len()).filter(|i| lights[*i].light_type != LightType::Ambient).collect();
+        if !indices.is_empty() {
+            tree.build(lights, &mut indices);
+        }
+        tree
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // splits the lights in half along the longest side of their bounds, returns the node index
+    fn build(&mut self, lights: &[Light], indices: &mut [usize]) -> usize {
+        if indices.len() == 1 {
+            let light = &lights[indices[0]];
+            self.nodes.push(LightNode {
+                min: light.position,
+                max: light.position,
+                power: power(light),
+                attenuation: light.attenuation,
+                cone: Cone::of(light),
+                children: Children::Leaf(indices[0]),
+            });
+            return self.nodes.len() - 1;
+        }
+        let (min, max) = bounds(indices.iter().map(|i| lights[*i].position));
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let coordinate = |i: &usize| [lights[*i].position.x, lights[*i].position.y, lights[*i].position.z][axis];
+        indices.sort_by(|a, b| coordinate(a).total_cmp(&coordinate(b)));
+        let middle = indices.len() / 2;
+        let (left_indices, right_indices) = indices.split_at_mut(middle);
+        let left = self.build(lights, left_indices);
+        let right = self.build(lights, right_indices);
+        let (a, b) = (self.nodes[left], self.nodes[right]);
+        self.nodes.push(LightNode {
+            min: componentwise(&a.min, &b.min, f32::min),
+            max: componentwise(&a.max, &b.max, f32::max),
+            power: a.power + b.power,
+            attenuation: (a.attenuation.0.min(b.attenuation.0), a.attenuation.1.min(b.attenuation.1), a.attenuation.2.min(b.attenuation.2)),
+            cone: a.cone.union(&b.cone),
+            children: Children::Inner(left, right),
+        });
+        self.nodes.len() - 1
+    }
+
+    // a light picked for the point and the probability it had, none if no light reaches it
+    pub fn sample(&self, point: &Vector) -> Option<(usize, f32)> {
+        let mut node = self.nodes.len().checked_sub(1)?;
+        let mut probability = 1.0;
+        loop {
+            match self.nodes[node].children {
+                Children::Leaf(light) => return Some((light, probability)),
+                Children::Inner(left, right) => {
+                    let (left_importance, right_importance) = (self.importance(left, point), self.importance(right, point));
+                    let total = left_importance + right_importance;
+                    if total <= 0.0 || !total.is_finite() {
+                        return None;
+                    }
+                    let p_left = left_importance / total;
+                    if rand::random::<f32>() < p_left {
+                        node = left;
+                        probability *= p_left;
+                    } else {
+                        node = right;
+                        probability *= 1.0 - p_left;
+                    }
+                }
+            }
+        }
+    }
+
+    // estimated light of a node at the point. distances are kept above the size of the node
+    // so points close to or inside of it don't favour it too much
+    fn importance(&self, index: usize, point: &Vector) -> f32 {
+        let node = &self.nodes[index];
+        let center = (node.min + node.max) * 0.5;
+        let radius = (node.max - node.min).length() * 0.5;
+        let distance = point.distance(&center);
+        let d = distance.max(radius);
+        let (c, l, q) = node.attenuation;
+        let falloff = 1.0 / (c + l * d + q * d * d).max(1e-8);
+
+        // smallest angle between the cone and the direction to the point
+        let angle_w = if distance > 0.0 { node.cone.axis.dot(&((*point - center) / distance)).clamp(-1.0, 1.0).acos() } else { 0.0 };
+        let angle_b = if distance > radius { (radius / distance).asin() } else { PI };
+        let angle = (angle_w - node.cone.angle_o - angle_b).max(0.0);
+        if angle >= node.cone.angle_e {
+            return 0.0;
+        }
+        node.power * angle.cos() * falloff
+    }
+}
+
+// how bright a light is, its luminance times the peak of its profile
+fn power(light: &Light) -> f32 {
+    let luminance = 0.2126 * light.color.r + 0.7152 * light.color.g + 0.0722 * light.color.b;
+    let peak = light.profile.as_ref().map_or(1.0, |profile| profile.max_candela());
+    (luminance * peak).max(1e-6)
+}
+
+fn bounds(points: impl Iterator<Item = Vector>) -> (Vector, Vector) {
+    let mut min = Vector::from_num(f32::INFINITY);
+    let mut max = Vector::from_num(f32::NEG_INFINITY);
+    for point in points {
+        min = componentwise(&min, &point, f32::min);
+        max = componentwise(&max, &point, f32::max);
+    }
+    (min, max)
+}
+
+fn componentwise(a: &Vector, b: &Vector, f: fn(f32, f32) -> f32) -> Vector {
+    Vector::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn brightness_at(light: &Light, point: &Vector) -> f32 {
+        let color = light.radiance_at(point, None);
+        color.r + color.g + color.b
+    }
+
+    #[test]
+    fn light_tree_test() {
+        let mut lights = vec![Light::new_ambient(Color::white(), 0.1)];
+        for i in 0..10 {
+            for j in 0..10 {
+                lights.push(Light::new_point(Vector::new(i as f32 * 50.0, 100.0, j as f32 * 50.0), Color::white(), (1.0, 0.0, 0.0001)));
+            }
+        }
+        // a spot pointing away from the point never gets picked
+        lights.push(Light::new_spot(Vector::new(0.0, 100.0, 0.0), Vector::new(0.0, 1.0, 0.0), Color::white(), (1.0, 0.0, 0.0), 0.3, 0.5));
+        let tree = LightTree::new(&lights);
+        let point = Vector::new(0.0, 0.0, 0.0);
+
+        // the light divided by its probability adds up to all of the light on average
+        let total: f32 = lights.iter().skip(1).map(|light| brightness_at(light, &point)).sum();
+        let samples = 20000;
+        let mut estimate = 0.0;
+        let mut picked_close = 0;
+        for _ in 0..samples {
+            let (index, probability) = tree.sample(&point).unwrap();
+            assert!(index != 0 && index != lights.len() - 1);
+            estimate += brightness_at(&lights[index], &point) / probability / samples as f32;
+            if lights[index].position.distance(&point) < 200.0 {
+                picked_close += 1;
+            }
+        }
+        assert!((estimate - total).abs() < 0.05 * total, "{} {}", estimate, total);
+        // close lights are picked more often than their share of 9%
+        assert!(picked_close as f32 / samples as f32 > 0.15, "{}", picked_close);
+    }
+
+    #[test]
+    fn cone_union_test() {
+        let up = Cone { axis: Vector::new(0.0, 1.0, 0.0), angle_o: 0.0, angle_e: 0.5 };
+        let side = Cone { axis: Vector::new(1.0, 0.0, 0.0), angle_o: 0.0, angle_e: 0.2 };
+        let both = up.union(&side);
+        assert!((both.angle_o - PI / 4.0).abs() < 1e-4);
+        assert!((both.axis.dot(&Vector::new(1.0, 1.0, 0.0)._normalize()) - 1.0).abs() < 1e-4);
+        assert_eq!(both.angle_e, 0.5);
+        assert!(up.union(&Cone::everywhere()).angle_o >= PI);
+    }
+}
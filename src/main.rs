@@ -8,21 +8,34 @@ use image::{DynamicImage, ImageBuffer};
 use light::{Light, RectangleAreaLight};
 use material::Material;
 use math::{Quaternion, RayCastHit, Vector};
-use presentation_scenes::{full_pbr_scene, pbr_scene, reflection_refraction_scene, shading_scene, texture_test};
+use presentation_scenes::{shading_scene, reflection_refraction_scene, pbr_scene, texture_test, full_pbr_scene, instancing_scene, primitives_scene, csg_scene, sdf_scene, fog_scene, smoke_scene, colored_glass_scene, dispersion_scene, spectral_scene, emissive_scene, principled_scene, surface_detail_scene, procedural_textures_scene, shader_graph_scene, texture_filtering_scene, layered_materials_scene, thin_film_scene, subsurface_scene, daylight_scene, ies_scene};
 use scene::Scene;
 
-use crate::math::{as_degrees, as_radians, IntersectionPrimitive};
+use crate::math::{as_radians, IntersectionPrimitive};
 use crate::camera::Camera;
 
 use crate::geometry::{Line, Triangle};
 
 mod camera;
 mod scene;
+mod shader;
+mod sky;
 mod material;
+mod bsdf;
 mod color;
 mod buffer;
 mod light;
+mod light_tree;
+mod ies;
+mod medium;
+mod mipmap;
+mod photon_map;
+mod density_grid;
+mod spectrum;
+mod subsurface;
+mod texture;
 mod presentation_scenes;
+mod stats;
 
 mod geometry;
 mod math;
@@ -73,8 +86,41 @@ fn main() {
     //    camera.add_material(mat);
     //}
 
-    //let scene = reflection_refraction_scene();
-    let (scene, materials) = full_pbr_scene();
+    // the scene is picked by its name on the command line, without the _scene suffix
+    let scene_name = std::env::args().nth(1).unwrap_or(String::from("full_pbr"));
+    let (scene, materials) = match scene_name.as_str() {
+        "shading" => (shading_scene(), init_materials()),
+        "reflection_refraction" => (reflection_refraction_scene(), init_materials()),
+        "pbr" => pbr_scene(),
+        "texture_test" => texture_test(),
+        "full_pbr" => full_pbr_scene(),
+        "instancing" => instancing_scene(),
+        "primitives" => primitives_scene(),
+        "csg" => csg_scene(),
+        "sdf" => sdf_scene(),
+        "fog" => fog_scene(),
+        "smoke" => smoke_scene(),
+        "colored_glass" => colored_glass_scene(),
+        "dispersion" => dispersion_scene(),
+        "spectral" => spectral_scene(),
+        "emissive" => emissive_scene(),
+        "principled" => principled_scene(),
+        "surface_detail" => surface_detail_scene(),
+        "procedural_textures" => procedural_textures_scene(),
+        "shader_graph" => shader_graph_scene(),
+        "texture_filtering" => texture_filtering_scene(),
+        "layered_materials" => layered_materials_scene(),
+        "thin_film" => thin_film_scene(),
+        "subsurface" => subsurface_scene(),
+        "daylight" => daylight_scene(),
+        "ies" => ies_scene(),
+        name => {
+            println!("unknown scene {}", name);
+            return;
+        }
+    };
+    // the spectral scene compares emission spectra, rgb rendering would lose them
+    camera.spectral = scene_name == "spectral";
 
     for mat in materials {
         camera.add_material(mat);
@@ -85,7 +131,7 @@ fn main() {
     camera.pinhole_distance = 690.0;
     camera.max_bounces = 10;
 
-    //camera.render_scene(&scene, "output"); 
+    //camera.render_scene(&mut scene, "output"); 
     camera.render_scene_multithreaded(scene, "multithread.png");
     camera.antialias_debug_buffer.save("aa_debug.png");
 }
@@ -1,6 +1,14 @@
 use image::ImageBuffer;
 
+use crate::bsdf::{dielectric_fresnel, diffuse_fresnel};
 use crate::color::Color;
+use crate::geometry::Line;
+use crate::math::Vector;
+use crate::mipmap::{MipMap, TextureFilter};
+use crate::shader::{ShaderGraph, ShaderInput};
+use crate::spectrum::{reflectance_for, Wavelengths};
+use crate::subsurface::SubsurfaceScattering;
+use crate::texture::{Texture, TextureInput};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MaterialType {
@@ -10,6 +18,145 @@ pub enum MaterialType {
     PBR,
 }
 
+// how the refractive index changes with the wavelength. wavelengths are in micrometers in the formulas
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dispersion {
+    None,
+    // n = a + b / wavelength^2
+    Cauchy { a: f32, b: f32 },
+    // n^2 = 1 + sum of b[i] * wavelength^2 / (wavelength^2 - c[i])
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Dispersion {
+    // schott N-BK7, the usual crown glass
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.039_612, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65],
+        }
+    }
+
+    // flint glass, disperses a lot more than bk7
+    pub fn dense_flint() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.739_759_6, 0.313_747_35, 1.878_787_4],
+            c: [0.013_188_707, 0.062_306_814, 155.236_3],
+        }
+    }
+
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [0.330_6, 4.335_6, 0.0],
+            c: [0.030_625, 0.011_236, 0.0],
+        }
+    }
+
+    // refractive index at a wavelength in nanometers, None without dispersion
+    pub fn refractive_index(&self, wavelength: f32) -> Option<f32> {
+        let micrometers = wavelength / 1000.0;
+        let l2 = micrometers * micrometers;
+        match self {
+            Dispersion::None => None,
+            Dispersion::Cauchy { a, b } => Some(a + b / l2),
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>();
+                Some(n2.max(1.0).sqrt())
+            }
+        }
+    }
+}
+
+// material inputs a texture or the shader graph can drive, replacing the fixed value
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureChannel {
+    BaseColor,
+    Metallic,
+    Roughness,
+    // phong
+    Specular,
+    Shininess,
+    // multiplied by emission_strength. lights cast by emitters use the fixed emission
+    Emission,
+    // principled lobes
+    SpecularTint,
+    Sheen,
+    SheenTint,
+    Clearcoat,
+    ClearcoatRoughness,
+    Transmission,
+    Subsurface,
+    Anisotropy,
+    // scales the thickness of the thin film
+    FilmThickness,
+}
+
+// textured and shaded inputs of a material at one hit, inputs not in here keep their field
+pub struct ChannelValues(Vec<(TextureChannel, Color)>);
+
+impl ChannelValues {
+    pub fn color(&self, channel: TextureChannel) -> Option<Color> {
+        self.0.iter().rev().find(|(c, _)| *c == channel).map(|(_, color)| *color)
+    }
+
+    // brightness of the input, 0..1 unless it's the shininess
+    pub fn scalar(&self, channel: TextureChannel, default: f32) -> f32 {
+        match self.color(channel) {
+            Some(color) if channel == TextureChannel::Shininess => color.brightness().max(0.0),
+            Some(color) => color.brightness().clamp(0.0, 1.0),
+            None => default,
+        }
+    }
+}
+
+// clear dielectric layer over a base, like varnish, car paint or water. tint is the color
+// of light after crossing the layer once straight on
+#[derive(Debug, Clone, Copy)]
+pub struct Coating {
+    ior: f32,
+    tint: Color,
+    // share of the light coming back up from the base that the coating reflects down again
+    internal: f32,
+}
+
+impl Coating {
+    pub fn new(ior: f32, tint: Color) -> Coating {
+        Coating { ior, tint, internal: diffuse_fresnel(1.0 / ior) }
+    }
+
+    // how the layer splits light seen from the view angle: the share reflected at its top and
+    // the weight of the shaded base below it. light reaching the base gets in and out through
+    // the coating, part of it is reflected back down inside the coating and bounces again off
+    // the base, whose albedo is base_albedo
+    pub fn transfer(&self, cos_view: f32, base_albedo: f32, spectral: Option<&Wavelengths>) -> (f32, Color) {
+        let reflected = dielectric_fresnel(cos_view, self.ior);
+        let escaping = (1.0 - self.internal) / (1.0 - base_albedo.clamp(0.0, 1.0) * self.internal);
+        // way down and back up at the refracted angle
+        let sin_t2 = (1.0 - cos_view * cos_view) / (self.ior * self.ior);
+        let exponent = 2.0 / (1.0 - sin_t2).max(0.01).sqrt();
+        let tint = reflectance_for(&self.tint, spectral);
+        let absorption = Color::new(tint.r.max(0.0).powf(exponent), tint.g.max(0.0).powf(exponent), tint.b.max(0.0).powf(exponent));
+        (reflected, absorption * ((1.0 - reflected) * escaping))
+    }
+}
+
+// layer thinner than a wavelength on top of the surface, like soap, oil or lens coatings.
+// light reflected at its top and bottom interferes, so its reflectance depends on the
+// wavelength. thickness is in nanometers
+#[derive(Debug, Clone, Copy)]
+pub struct ThinFilm {
+    pub thickness: f32,
+    pub ior: f32,
+}
+
+// materials made of other materials of the scene's list, referenced by their index
+#[derive(Debug, Clone)]
+pub enum Composite {
+    // a where the mask is 0, b where it's 1. both are shaded and mixed by the mask
+    Blend { a: usize, b: usize, mask: Texture },
+    Coated { base: usize, coating: Coating },
+}
+
 #[derive(Debug, Clone)]
 pub struct Material {
     pub base_color: Color,
@@ -18,6 +165,16 @@ pub struct Material {
     pub material_type: MaterialType,
     pub max_bounce_depth: f32,
     pub refractive_index: f32,
+    // light travelling through refractive materials is tinted by absorption_color once per
+    // 1 / absorption_density units of distance. a density of 0 keeps the glass clear
+    pub absorption_color: Color,
+    pub absorption_density: f32,
+    // replaces refractive_index for rays carrying a wavelength
+    pub dispersion: Dispersion,
+    // light given off by the surface, emission * emission_strength. primitives with an
+    // emissive material are turned into area lights by Scene::register_emitters
+    pub emission: Color,
+    pub emission_strength: f32,
 
     // PBR
     pub metallic: f32,
@@ -25,11 +182,35 @@ pub struct Material {
     pub ior: f32,
     pub anisotropy: f32,
     pub anisotropy_rotation: f32,
+    // principled bsdf lobes, all 0..1 and off by default
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub transmission: f32,
+    pub subsurface: f32,
+    // iridescent layer changing the fresnel term of pbr and refractive materials
+    pub thin_film: Option<ThinFilm>,
+    // replaces the diffuse base of pbr materials with light scattered inside the primitive
+    pub subsurface_scattering: Option<SubsurfaceScattering>,
 
     pub textured: bool,
-    pub albedo_map: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-    pub metallic_map: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-    pub roughness_map: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    pub albedo_map: MipMap,
+    pub metallic_map: MipMap,
+    pub roughness_map: MipMap,
+
+    // surface detail for every shading model but refraction. the normal map is in tangent
+    // space, the bump map is a greyscale height field tilting the normal by bump_strength
+    pub normal_map: Option<ImageBuffer<image::Rgb<u8>, Vec<u8>>>,
+    pub bump_map: Option<ImageBuffer<image::Rgb<u8>, Vec<u8>>>,
+    pub bump_strength: f32,
+    // procedural or image textures, they win over the image maps above
+    pub textures: Vec<(TextureChannel, Texture)>,
+    // evaluated per hit, its outputs win over the textures
+    pub shader: Option<ShaderGraph>,
+    // the other fields don't matter for composite materials
+    pub composite: Option<Composite>,
 }
 
 impl Default for Material {
@@ -41,15 +222,35 @@ impl Default for Material {
             material_type: MaterialType::Phong,
             max_bounce_depth: 0.0,
             refractive_index: 1.0,
+            absorption_color: Color::white(),
+            absorption_density: 0.0,
+            dispersion: Dispersion::None,
+            emission: Color::black(),
+            emission_strength: 0.0,
             metallic: 0.0,
             roughness: 0.0,
             ior: 1.3,
             anisotropy: 0.0,
             anisotropy_rotation: 0.0,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+            transmission: 0.0,
+            subsurface: 0.0,
+            thin_film: None,
+            subsurface_scattering: None,
             textured: false,
-            albedo_map: ImageBuffer::new(1, 1),
-            metallic_map: ImageBuffer::new(1, 1),
-            roughness_map: ImageBuffer::new(1, 1),
+            albedo_map: MipMap::new(&ImageBuffer::new(1, 1)),
+            metallic_map: MipMap::new(&ImageBuffer::new(1, 1)),
+            roughness_map: MipMap::new(&ImageBuffer::new(1, 1)),
+            normal_map: None,
+            bump_map: None,
+            bump_strength: 0.0,
+            textures: Vec::new(),
+            shader: None,
+            composite: None,
         }
     }
 }
@@ -92,10 +293,173 @@ impl Material {
             base_color,
             material_type: MaterialType::Refractive,
             refractive_index,
+            absorption_color: base_color,
             ..Default::default()
         }
     }
 
+    // colored glass, see absorption_color
+    pub fn with_absorption(mut self, color: Color, density: f32) -> Material {
+        self.absorption_color = color;
+        self.absorption_density = density.max(0.0);
+        self
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Material {
+        self.dispersion = dispersion;
+        self
+    }
+
+    pub fn with_emission(mut self, color: Color, strength: f32) -> Material {
+        self.emission = color;
+        self.emission_strength = strength;
+        self
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission_strength > 0.0 && !self.emission.is_black()
+    }
+
+    pub fn emitted_radiance(&self) -> Color {
+        self.emission * self.emission_strength
+    }
+
+    pub fn emitted_radiance_at(&self, channels: &ChannelValues) -> Color {
+        channels.color(TextureChannel::Emission).unwrap_or(self.emission) * self.emission_strength
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        self.dispersion != Dispersion::None
+    }
+
+    // index for a ray, depends on its wavelength for dispersive materials
+    pub fn refractive_index_for(&self, ray: &Line) -> f32 {
+        ray.wavelength
+            .and_then(|wavelength| self.dispersion.refractive_index(wavelength))
+            .unwrap_or(self.refractive_index)
+    }
+
+    // beer-lambert, fraction of the light left after travelling distance inside the material
+    pub fn absorption(&self, distance: f32, spectral: Option<&Wavelengths>) -> Color {
+        if self.absorption_density <= 0.0 {
+            return Color::white();
+        }
+        let exponent = self.absorption_density * distance;
+        let absorption_color = reflectance_for(&self.absorption_color, spectral);
+        Color::new(
+            absorption_color.r.max(0.0).powf(exponent),
+            absorption_color.g.max(0.0).powf(exponent),
+            absorption_color.b.max(0.0).powf(exponent),
+        )
+    }
+
+    // textures and shader outputs at a hit, shader outputs come last so they win
+    pub fn channels_at(&self, input: &ShaderInput) -> ChannelValues {
+        let mut values: Vec<(TextureChannel, Color)> = self.textures.iter().map(|(channel, texture)| (*channel, texture.value(&input.texture))).collect();
+        if let Some(shader) = &self.shader {
+            values.extend(shader.evaluate(input));
+        }
+        ChannelValues(values)
+    }
+
+    // albedo, metallic and roughness at a point, from the maps of textured materials or the
+    // material itself, overridden by the textured or shaded channels
+    pub fn surface_at(&self, input: &TextureInput, channels: &ChannelValues) -> (Color, f32, f32) {
+        let (albedo, metallic, roughness) = self.maps_at(input).unwrap_or((self.base_color, self.metallic, self.roughness));
+        (
+            channels.color(TextureChannel::BaseColor).unwrap_or(albedo),
+            channels.scalar(TextureChannel::Metallic, metallic).clamp(0.01, 0.99),
+            channels.scalar(TextureChannel::Roughness, roughness).clamp(0.01, 0.99),
+        )
+    }
+
+    // albedo, metallic and roughness read from the maps of a textured material, None for
+    // untextured materials and points without uv
+    pub fn maps_at(&self, input: &TextureInput) -> Option<(Color, f32, f32)> {
+        let (u, v) = input.uv.filter(|_| self.textured)?;
+        let sample = |map: &MipMap| map.sample(u, v, input.footprint.as_ref());
+        let metallic = sample(&self.metallic_map).r.clamp(0.01, 0.99);
+        let roughness = sample(&self.roughness_map).r.clamp(0.01, 0.99);
+        Some((sample(&self.albedo_map), metallic, roughness))
+    }
+
+    pub fn with_texture(mut self, channel: TextureChannel, texture: Texture) -> Material {
+        self.textures.retain(|(c, _)| *c != channel);
+        self.textures.push((channel, texture));
+        self
+    }
+
+    pub fn texture(&self, channel: TextureChannel) -> Option<&Texture> {
+        self.textures.iter().find(|(c, _)| *c == channel).map(|(_, texture)| texture)
+    }
+
+    // filtering of the albedo, metallic and roughness maps
+    pub fn with_texture_filter(mut self, filter: TextureFilter) -> Material {
+        self.albedo_map.filter = filter;
+        self.metallic_map.filter = filter;
+        self.roughness_map.filter = filter;
+        self
+    }
+
+    pub fn with_shader(mut self, shader: ShaderGraph) -> Material {
+        self.shader = Some(shader);
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: ImageBuffer<image::Rgb<u8>, Vec<u8>>) -> Material {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    // strength is how far the normal tilts for a black to white step between two texels
+    pub fn with_bump_map(mut self, bump_map: ImageBuffer<image::Rgb<u8>, Vec<u8>>, strength: f32) -> Material {
+        self.bump_map = Some(bump_map);
+        self.bump_strength = strength;
+        self
+    }
+
+    // whether anything of the material is looked up at the hit and so needs its footprint.
+    // blend masks can be textured too
+    pub fn has_textures(&self) -> bool {
+        self.textured || !self.textures.is_empty() || self.shader.is_some() || self.composite.is_some()
+    }
+
+    pub fn has_detail_maps(&self) -> bool {
+        self.normal_map.is_some() || self.bump_map.is_some()
+    }
+
+    // normal used for shading, the geometric normal changed by the normal and bump maps.
+    // without a tangent the maps are applied in an arbitrary frame around the normal
+    pub fn shading_normal(&self, normal: &Vector, tangent: Option<Vector>, uv: Option<(f32, f32)>) -> Vector {
+        let (u, v) = match uv {
+            Some(uv) if self.has_detail_maps() => uv,
+            _ => return *normal,
+        };
+        // gram-schmidt, the tangent of an instance or a curved surface isn't exactly orthogonal
+        let tangent = tangent
+            .map(|t| t - *normal * normal.dot(&t))
+            .filter(|t| t.length_squared() > 1e-8)
+            .map(|t| t._normalize())
+            .unwrap_or_else(|| normal.orthonormal_basis().0);
+        let bitangent = normal.cross(&tangent);
+
+        let mut shading = *normal;
+        if let Some(map) = &self.normal_map {
+            let texel = sample_map(map, u, v);
+            let local = Vector::new(texel.r * 2.0 - 1.0, texel.g * 2.0 - 1.0, texel.b * 2.0 - 1.0);
+            shading = (tangent * local.x + bitangent * local.y + shading * local.z)._normalize();
+        }
+        if let Some(map) = &self.bump_map {
+            // forward differences of the height one texel along u and v
+            let (width, height) = map.dimensions();
+            let h = sample_map(map, u, v).r;
+            let dh_du = sample_map(map, u + 1.0 / width as f32, v).r - h;
+            let dh_dv = sample_map(map, u, v + 1.0 / height as f32).r - h;
+            shading = (shading - (tangent * dh_du + bitangent * dh_dv) * self.bump_strength)._normalize();
+        }
+        shading
+    }
+
     pub fn new_pbr(albedo: Color, metallic: f32, roughness: f32, ior: f32, anisotropy: f32, anisotropy_rotation: f32) -> Material {
         let roughness = roughness.clamp(0.01, 0.99);
         let metallic = metallic.clamp(0.01, 0.99);
@@ -112,16 +476,190 @@ impl Material {
         }
     }
 
+    // tints the dielectric highlight towards the base color
+    pub fn with_specular_tint(mut self, specular_tint: f32) -> Material {
+        self.specular_tint = specular_tint.clamp(0.0, 1.0);
+        self
+    }
+
+    // soft rim of cloth, tint moves it from white to the base color
+    pub fn with_sheen(mut self, sheen: f32, tint: f32) -> Material {
+        self.sheen = sheen.clamp(0.0, 1.0);
+        self.sheen_tint = tint.clamp(0.0, 1.0);
+        self
+    }
+
+    // second, clear specular layer like car paint or varnish
+    pub fn with_clearcoat(mut self, clearcoat: f32, roughness: f32) -> Material {
+        self.clearcoat = clearcoat.clamp(0.0, 1.0);
+        self.clearcoat_roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    // share of the light refracted through the surface with ior, for glass-like pbr materials
+    pub fn with_transmission(mut self, transmission: f32) -> Material {
+        self.transmission = transmission.clamp(0.0, 1.0);
+        self
+    }
+
+    // flattens the diffuse lobe like light scattered below the surface (skin, wax)
+    pub fn with_subsurface(mut self, subsurface: f32) -> Material {
+        self.subsurface = subsurface.clamp(0.0, 1.0);
+        self
+    }
+
+    // thickness in nanometers, a few hundred give the strongest colors
+    pub fn with_thin_film(mut self, thickness: f32, ior: f32) -> Material {
+        self.thin_film = Some(ThinFilm { thickness: thickness.max(0.0), ior });
+        self
+    }
+
+    // light travels mean_free_path * scale between scattering events, per channel. the base
+    // color is how bright the material looks overall
+    pub fn with_subsurface_scattering(mut self, mean_free_path: Color, scale: f32) -> Material {
+        self.subsurface_scattering = Some(SubsurfaceScattering { mean_free_path, scale });
+        self
+    }
+
+    pub fn new_blend(a: usize, b: usize, mask: Texture) -> Material {
+        Material {
+            composite: Some(Composite::Blend { a, b, mask }),
+            ..Default::default()
+        }
+    }
+
+    pub fn new_coated(base: usize, ior: f32, tint: Color) -> Material {
+        Material {
+            composite: Some(Composite::Coated { base, coating: Coating::new(ior, tint) }),
+            ..Default::default()
+        }
+    }
+
     pub fn new_textured_pbr(albedo: ImageBuffer<image::Rgb<u8>, Vec<u8>>, metal: ImageBuffer<image::Rgb<u8>, Vec<u8>>, roughness: ImageBuffer<image::Rgb<u8>, Vec<u8>>) -> Material {
         Material {
             material_type: MaterialType::PBR,
             max_bounce_depth: 8000.0,
             textured: true,
             roughness: 1.0,
-            albedo_map: albedo,
-            metallic_map: metal,
-            roughness_map: roughness,
+            albedo_map: MipMap::new(&albedo),
+            metallic_map: MipMap::new(&metal),
+            roughness_map: MipMap::new(&roughness),
             ..Default::default()
         }
     }
-}
\ No newline at end of file
+}
+
+// the materials shading a hit with a composite material, each with its share of the hit and
+// the coatings over it from the outermost in. blends nested deeper than a few levels are most
+// likely a cycle and stop there
+pub fn resolve_composite(materials: &[Material], index: usize, input: &TextureInput) -> Vec<(usize, f32, Vec<Coating>)> {
+    let mut layers = Vec::new();
+    resolve_into(materials, index, 1.0, Vec::new(), input, 0, &mut layers);
+    layers
+}
+
+fn resolve_into(materials: &[Material], index: usize, share: f32, coatings: Vec<Coating>, input: &TextureInput, depth: usize, layers: &mut Vec<(usize, f32, Vec<Coating>)>) {
+    match &materials[index].composite {
+        Some(_) if depth >= 16 => layers.push((index, share, coatings)),
+        Some(Composite::Blend { a, b, mask }) => {
+            let mask = mask.scalar(input).clamp(0.0, 1.0);
+            for (index, weight) in [(*a, 1.0 - mask), (*b, mask)] {
+                if weight > 0.0 {
+                    resolve_into(materials, index, share * weight, coatings.clone(), input, depth + 1, layers);
+                }
+            }
+        }
+        Some(Composite::Coated { base, coating }) => {
+            let mut coatings = coatings;
+            coatings.push(*coating);
+            resolve_into(materials, *base, share, coatings, input, depth + 1, layers);
+        }
+        None => layers.push((index, share, coatings)),
+    }
+}
+
+// nearest texel at uv, uv outside of 0..1 repeat the edge
+fn sample_map(map: &ImageBuffer<image::Rgb<u8>, Vec<u8>>, u: f32, v: f32) -> Color {
+    let (width, height) = map.dimensions();
+    let x = (u.clamp(0.0, 1.0) * (width - 1) as f32) as u32;
+    let y = (v.clamp(0.0, 1.0) * (height - 1) as f32) as u32;
+    let pixel = map.get_pixel(x, y);
+    Color::new(pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispersion_test() {
+        let bk7 = Dispersion::bk7();
+        // n_d of bk7 at the helium d line
+        assert!((bk7.refractive_index(587.6).unwrap() - 1.5168).abs() < 1e-3);
+        // blue bends more than red
+        assert!(bk7.refractive_index(450.0).unwrap() > bk7.refractive_index(650.0).unwrap());
+        assert!((Dispersion::diamond().refractive_index(589.0).unwrap() - 2.417).abs() < 0.01);
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.0042 };
+        assert!((cauchy.refractive_index(500.0).unwrap() - 1.5168).abs() < 1e-4);
+        assert_eq!(Dispersion::None.refractive_index(500.0), None);
+    }
+
+    #[test]
+    fn coating_test() {
+        let coating = Coating::new(1.5, Color::white());
+        for cos_view in [1.0, 0.5, 0.1] {
+            // nothing is lost over a white base
+            let (reflected, through) = coating.transfer(cos_view, 1.0, None);
+            assert!((reflected + through.g - 1.0).abs() < 1e-3, "{} {}", reflected, through);
+            // light bouncing inside the coating is partly absorbed by a dark base
+            let (_, dark) = coating.transfer(cos_view, 0.2, None);
+            assert!(dark.g < through.g);
+        }
+        // tinted coatings absorb more at grazing angles
+        let tinted = Coating::new(1.5, Color::new(0.5, 0.5, 0.5));
+        assert!(tinted.transfer(0.2, 1.0, None).1.r < tinted.transfer(1.0, 1.0, None).1.r);
+    }
+
+    #[test]
+    fn composite_test() {
+        let materials = vec![
+            Material::new_phong(Color::red(), 0.0, 0.0),
+            Material::new_phong(Color::blue(), 0.0, 0.0),
+            Material::new_blend(0, 1, Texture::Constant(Color::white())),
+            Material::new_coated(2, 1.5, Color::white()),
+            Material::new_blend(3, 0, Texture::Constant(Color::black())),
+            Material::new_blend(0, 3, Texture::Constant(Color::new(0.25, 0.25, 0.25))),
+        ];
+        let input = TextureInput::new(None, Vector::new(0.0, 0.0, 0.0), None);
+        let layers = resolve_composite(&materials, 4, &input);
+        assert_eq!(layers.len(), 1);
+        let (index, share, coatings) = &layers[0];
+        assert_eq!((*index, *share), (1, 1.0));
+        assert_eq!(coatings.len(), 1);
+        assert_eq!(resolve_composite(&materials, 0, &input)[0].0, 0);
+        // a partial mask shades both sides, the shares add up to the whole hit
+        let layers = resolve_composite(&materials, 5, &input);
+        assert_eq!(layers.iter().map(|(index, _, _)| *index).collect::<Vec<_>>(), vec![0, 1]);
+        assert!((layers[0].1 - 0.75).abs() < 1e-6 && (layers[1].1 - 0.25).abs() < 1e-6);
+        assert!(layers[0].2.is_empty() && layers[1].2.len() == 1);
+    }
+
+    #[test]
+    fn shading_normal_test() {
+        let normal = Vector::new(0.0, 0.0, 1.0);
+        let tangent = Some(Vector::new(1.0, 0.0, 0.0));
+        // a flat normal map keeps the normal
+        let flat = Material::default().with_normal_map(ImageBuffer::from_pixel(4, 4, image::Rgb([128, 128, 255])));
+        let shading = flat.shading_normal(&normal, tangent, Some((0.5, 0.5)));
+        assert!((shading - normal).length() < 0.01);
+        // a texel pointing along the tangent tilts the normal that way
+        let tilted = Material::default().with_normal_map(ImageBuffer::from_pixel(4, 4, image::Rgb([255, 128, 128])));
+        assert!(tilted.shading_normal(&normal, tangent, Some((0.5, 0.5))).x > 0.9);
+        // height rising along u tilts the normal back towards -u
+        let ramp = ImageBuffer::from_fn(8, 8, |x, _| image::Rgb([(x * 30) as u8; 3]));
+        let bumped = Material::default().with_bump_map(ramp, 2.0).shading_normal(&normal, tangent, Some((0.5, 0.5)));
+        assert!(bumped.x < -0.1 && bumped.y.abs() < 1e-4);
+        // no uv, no maps
+        assert_eq!(flat.shading_normal(&normal, tangent, None).z, 1.0);
+    }
+}
@@ -1,12 +1,84 @@
 use float_cmp::{approx_eq};
 
-use crate::{geometry::{Line, Sphere, Surface, Triangle}};
+use crate::{geometry::{AxisAlignedBox, Cone, Csg, Cylinder, Disk, Instance, Line, Mesh, SdfPrimitive, Sphere, Surface, Torus, Triangle}};
 
-use super::{RayCastHit, Vector};
+use super::{RayCastHit, SurfaceDerivatives, Vector};
 
 
 pub trait IntersectionPrimitive {
     fn intersect(&self, ray: &Line) -> RayCastHit;
+
+    // every part of the infinite ray that lies inside the primitive, sorted by t.
+    // only closed solids have an inside, open surfaces (planes, triangles, disks) return nothing.
+    // used by Csg to combine solids.
+    fn intervals(&self, _ray: &Line) -> Vec<RayInterval> {
+        Vec::new()
+    }
+
+    // surface area. primitives with an area can be emitters, 0 means they can't be sampled
+    fn area(&self) -> f32 {
+        0.0
+    }
+
+    // point and normal uniformly distributed over the surface for u, v in 0..1
+    fn sample_surface(&self, _u: f32, _v: f32) -> Option<(Vector, Vector)> {
+        None
+    }
+}
+
+// hits closer than this are ignored, so rays don't hit the surface they start on
+pub const HIT_EPSILON: f32 = 0.0001;
+
+// a point where an (infinite) ray crosses the surface of a primitive. t is negative behind the ray origin
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceCrossing {
+    pub t: f32,
+    pub normal: Vector,
+    pub uv: (f32, f32),
+    pub derivatives: Option<SurfaceDerivatives>,
+}
+
+impl SurfaceCrossing {
+    pub fn hit(&self, ray: &Line) -> RayCastHit {
+        let intersection = ray.point_on_line(&self.t);
+        let angle = ray.direction.angle_radians(&self.normal);
+        let distance = (intersection - ray.point).length();
+        RayCastHit::new(Some((intersection, angle))).with_normal(self.normal).with_distance(distance).with_uv(self.uv).with_derivatives(self.derivatives)
+    }
+}
+
+// the ray enters a solid at enter and leaves it at exit. normals point out of the solid at both ends
+#[derive(Debug, Clone, Copy)]
+pub struct RayInterval {
+    pub enter: SurfaceCrossing,
+    pub exit: SurfaceCrossing,
+}
+
+// pairs up sorted crossings of a closed surface into inside intervals.
+// a grazing ray can produce an odd count, the unpaired crossing is dropped.
+pub fn pair_crossings(crossings: &[SurfaceCrossing]) -> Vec<RayInterval> {
+    crossings
+        .chunks_exact(2)
+        .map(|pair| RayInterval { enter: pair[0], exit: pair[1] })
+        .collect()
+}
+
+// the hit for the first interval boundary in front of the ray
+pub fn closest_interval_hit(ray: &Line, intervals: &[RayInterval]) -> RayCastHit {
+    let crossings: Vec<SurfaceCrossing> = intervals.iter().flat_map(|i| [i.enter, i.exit]).collect();
+    closest_crossing_hit(ray, &crossings)
+}
+
+// the hit for the closest crossing in front of the ray
+pub fn closest_crossing_hit(ray: &Line, crossings: &[SurfaceCrossing]) -> RayCastHit {
+    let closest = crossings
+        .iter()
+        .filter(|crossing| crossing.t > HIT_EPSILON)
+        .min_by(|a, b| a.t.total_cmp(&b.t));
+    match closest {
+        Some(crossing) => crossing.hit(ray),
+        None => RayCastHit::new(None),
+    }
 }
 
 impl IntersectionPrimitive for Surface {
@@ -36,7 +108,7 @@ impl IntersectionPrimitive for Surface {
                 let v = (ts.1 - self.max_w.unwrap().0) / (self.max_w.unwrap().1 - self.max_w.unwrap().0);
                 let uv = (u, v);
                 //println!("uv: {:.3?}", uv);
-                return RayCastHit::new(Some((intersection, angle))).with_normal(self.normal).with_distance(distance).with_uv(uv)
+                return RayCastHit::new(Some((intersection, angle))).with_normal(self.normal).with_distance(distance).with_uv(uv).with_tangent(self.v.unwrap()._normalize()).with_derivatives(self.get_derivatives())
             }
 
             RayCastHit::new(Some((intersection, angle))).with_normal(self.normal).with_distance(distance)//.with_uv(uv)
@@ -44,6 +116,14 @@ impl IntersectionPrimitive for Surface {
             RayCastHit::new(None)
         }
     }
+
+    fn area(&self) -> f32 {
+        Surface::area(self).unwrap_or(0.0)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        Surface::sample_surface(self, u, v).map(|point| (point, self.normal))
+    }
 }
 
 impl IntersectionPrimitive for Sphere {
@@ -52,7 +132,9 @@ impl IntersectionPrimitive for Sphere {
         let r2 = self.get_radius_squared();
         let l = self.center - ray.point;
         let tca = l.dot(&ray.direction);
-        if tca < 0.0 {
+        // pointing away from the center only misses when the ray starts outside. rays starting
+        // inside, like the steps of a random walk or rays leaving glass, always hit the far side
+        if tca < 0.0 && l.dot(&l) > r2 {
             return RayCastHit::new(None);
         }
         let d2 = l.dot(&l) - tca * tca;
@@ -78,7 +160,21 @@ impl IntersectionPrimitive for Sphere {
 
         let angle = ray.direction.angle_radians(&normal);
         let distance = (intersection - ray.point).length();
-        RayCastHit::new(Some((intersection, angle))).with_normal(normal).with_distance(distance)
+        let mut hit = RayCastHit::new(Some((intersection, angle))).with_normal(normal).with_distance(distance).with_uv(self.get_uv(&intersection)).with_derivatives(self.get_derivatives(&normal));
+        hit.tangent = self.get_tangent(&normal);
+        hit
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        pair_crossings(&self.crossings(ray))
+    }
+
+    fn area(&self) -> f32 {
+        Sphere::area(self)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        Some(Sphere::sample_surface(self, u, v))
     }
 }
 
@@ -113,11 +209,377 @@ impl IntersectionPrimitive for Triangle {
             let intersection = ray.point_on_line(&t);
             let angle = ray.direction.angle_radians(&self.normal);
             let distance = (intersection - ray.point).length();
-            RayCastHit::new(Some((intersection, angle))).with_normal(self.normal).with_distance(distance)
+            RayCastHit::new(Some((intersection, angle))).with_normal(self.normal).with_distance(distance).with_tangent(v0v1._normalize())
         } else {
             //println!("t is out of bounds");
             RayCastHit::new(None)
         }
     }
 
+    fn area(&self) -> f32 {
+        Triangle::area(self)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        Some((Triangle::sample_surface(self, u, v), self.normal))
+    }
+}
+
+impl IntersectionPrimitive for Mesh {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        let mut closest = RayCastHit::new(None);
+        for triangle in self.triangles.iter() {
+            let hit = triangle.intersect(ray);
+            if hit.is_some() && (closest.is_none() || hit.distance < closest.distance) {
+                closest = hit;
+            }
+        }
+        closest
+    }
+
+    // only meaningful for closed meshes with consistently wound triangles
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        let mut crossings: Vec<SurfaceCrossing> = self.triangles.iter().filter_map(|t| t.crossing(ray)).collect();
+        crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+        pair_crossings(&crossings)
+    }
+
+    fn area(&self) -> f32 {
+        Mesh::area(self)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        Mesh::sample_surface(self, u, v)
+    }
+}
+
+impl IntersectionPrimitive for Instance {
+    // the ray is moved into object space, the hit is moved back to world space
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        let (local_ray, _) = self.ray_to_object(ray);
+        let local_hit = self.object.intersect(&local_ray);
+        if local_hit.is_none() {
+            return local_hit;
+        }
+
+        let intersection = self.point_to_world(&local_hit.unwrap().0);
+        let normal = self.normal_to_world(&local_hit.normal.unwrap());
+        let angle = ray.direction.angle_radians(&normal);
+        let distance = (intersection - ray.point).length();
+        let mut hit = RayCastHit::new(Some((intersection, angle))).with_normal(normal).with_distance(distance);
+        hit.uv = local_hit.uv;
+        hit.tangent = local_hit.tangent.map(|tangent| self.direction_to_world(&tangent));
+        hit.derivatives = local_hit.derivatives.map(|derivatives| self.derivatives_to_world(&derivatives, &local_hit.normal.unwrap()));
+        // nested instances keep the space of the innermost primitive
+        hit.object_position = local_hit.object_position.or(Some(local_hit.unwrap().0));
+        hit
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        let (local_ray, to_world) = self.ray_to_object(ray);
+        let to_world_crossing = |crossing: SurfaceCrossing| SurfaceCrossing {
+            t: crossing.t * to_world,
+            normal: self.normal_to_world(&crossing.normal),
+            uv: crossing.uv,
+            derivatives: crossing.derivatives.map(|derivatives| self.derivatives_to_world(&derivatives, &crossing.normal)),
+        };
+        self.object
+            .intervals(&local_ray)
+            .into_iter()
+            .map(|interval| RayInterval {
+                enter: to_world_crossing(interval.enter),
+                exit: to_world_crossing(interval.exit),
+            })
+            .collect()
+    }
+
+    // the wrapped primitive is sampled in object space. its area only carries over when the
+    // matrix scales every direction alike, other instances can't be emitters
+    fn area(&self) -> f32 {
+        self.area_scale().map_or(0.0, |scale| self.object.area() * scale)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        self.area_scale()?;
+        let (point, normal) = self.object.sample_surface(u, v)?;
+        Some((self.point_to_world(&point), self.normal_to_world(&normal)))
+    }
+}
+
+impl IntersectionPrimitive for SdfPrimitive {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        let t = match self.trace(ray) {
+            Some(t) => t,
+            None => return RayCastHit::new(None),
+        };
+        let intersection = ray.point_on_line(&t);
+        let normal = self.normal(&intersection);
+        let angle = ray.direction.angle_radians(&normal);
+        let distance = (intersection - ray.point).length();
+        RayCastHit::new(Some((intersection, angle))).with_normal(normal).with_distance(distance)
+    }
+}
+
+impl IntersectionPrimitive for Csg {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        closest_interval_hit(ray, &self.intervals(ray))
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        self.combine(&self.a.intervals(ray), &self.b.intervals(ray))
+    }
+}
+
+impl IntersectionPrimitive for Disk {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        match self.crossing(ray) {
+            Some(crossing) if crossing.t > HIT_EPSILON => crossing.hit(ray),
+            _ => RayCastHit::new(None),
+        }
+    }
+
+    fn area(&self) -> f32 {
+        Disk::area(self)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        Some((Disk::sample_surface(self, u, v), self.normal))
+    }
+}
+
+impl IntersectionPrimitive for Cylinder {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        closest_crossing_hit(ray, &self.crossings(ray))
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        pair_crossings(&self.crossings(ray))
+    }
+}
+
+impl IntersectionPrimitive for Cone {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        closest_crossing_hit(ray, &self.crossings(ray))
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        pair_crossings(&self.crossings(ray))
+    }
+}
+
+impl IntersectionPrimitive for Torus {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        closest_crossing_hit(ray, &self.crossings(ray))
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        pair_crossings(&self.crossings(ray))
+    }
+}
+
+impl IntersectionPrimitive for AxisAlignedBox {
+    fn intersect(&self, ray: &Line) -> RayCastHit {
+        closest_crossing_hit(ray, &self.crossings(ray))
+    }
+
+    fn intervals(&self, ray: &Line) -> Vec<RayInterval> {
+        pair_crossings(&self.crossings(ray))
+    }
+
+    fn area(&self) -> f32 {
+        AxisAlignedBox::area(self)
+    }
+
+    fn sample_surface(&self, u: f32, v: f32) -> Option<(Vector, Vector)> {
+        Some(AxisAlignedBox::sample_surface(self, u, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Sdf;
+
+    fn assert_hit(hit: RayCastHit, distance: f32, normal: Vector) {
+        assert!(hit.is_some());
+        assert!((hit.distance - distance).abs() < 0.001, "distance: {}", hit.distance);
+        assert!((hit.normal.unwrap() - normal).length() < 0.001, "normal: {}", hit.normal.unwrap().to_string());
+    }
+
+    #[test]
+    fn torus_test() {
+        let torus = Torus::new(Vector::new(0.0, 0.0, -100.0), Vector::new(0.0, 1.0, 0.0), 40.0, 10.0);
+        // straight through the hole
+        let ray = Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert_hit(torus.intersect(&ray), 50.0, Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(torus.crossings(&ray).len(), 4);
+        // down the axis misses
+        let ray = Line::new(Vector::new(0.0, 100.0, -100.0), Vector::new(0.0, -1.0, 0.0));
+        assert!(torus.intersect(&ray).is_none());
+        // onto the top of the tube
+        let ray = Line::new(Vector::new(40.0, 100.0, -100.0), Vector::new(0.0, -1.0, 0.0));
+        assert_hit(torus.intersect(&ray), 90.0, Vector::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn sphere_test() {
+        let sphere = Sphere::new(Vector::new(0.0, 0.0, -10.0), 2.0);
+        let ray = Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert_hit(sphere.intersect(&ray), 8.0, Vector::new(0.0, 0.0, 1.0));
+        // behind the ray
+        let ray = Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(sphere.intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn sphere_inside_test() {
+        let sphere = Sphere::new(Vector::new(0.0, 0.0, -10.0), 2.0);
+        // towards the center and away from it, both leave through the far side
+        let ray = Line::new(Vector::new(0.0, 0.0, -11.0), Vector::new(0.0, 0.0, -1.0));
+        assert_hit(sphere.intersect(&ray), 1.0, Vector::new(0.0, 0.0, -1.0));
+        let ray = Line::new(Vector::new(0.0, 0.0, -9.0), Vector::new(0.0, 0.0, 1.0));
+        assert_hit(sphere.intersect(&ray), 1.0, Vector::new(0.0, 0.0, 1.0));
+        let ray = Line::new(Vector::new(0.0, 1.0, -10.0), Vector::new(0.0, 1.0, 0.0));
+        assert_hit(sphere.intersect(&ray), 1.0, Vector::new(0.0, 1.0, 0.0));
+        // sideways from off center
+        let ray = Line::new(Vector::new(0.0, 1.0, -10.0), Vector::new(1.0, 0.0, 0.0));
+        assert_hit(sphere.intersect(&ray), 3f32.sqrt(), Vector::new(3f32.sqrt() / 2.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn cylinder_test() {
+        let cylinder = Cylinder::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), 10.0, 20.0);
+        let ray = Line::new(Vector::new(0.0, 10.0, 50.0), Vector::new(0.0, 0.0, -1.0));
+        assert_hit(cylinder.intersect(&ray), 40.0, Vector::new(0.0, 0.0, 1.0));
+        let ray = Line::new(Vector::new(5.0, 50.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        assert_hit(cylinder.intersect(&ray), 30.0, Vector::new(0.0, 1.0, 0.0));
+        // from the inside the far wall is hit
+        let ray = Line::new(Vector::new(0.0, 10.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        assert_hit(cylinder.intersect(&ray), 10.0, Vector::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cone_test() {
+        let cone = Cone::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), 10.0, 10.0);
+        // halfway up the radius is 5
+        let ray = Line::new(Vector::new(50.0, 5.0, 0.0), Vector::new(-1.0, 0.0, 0.0));
+        let normal = Vector::new(1.0, 1.0, 0.0)._normalize();
+        assert_hit(cone.intersect(&ray), 45.0, normal);
+        let ray = Line::new(Vector::new(0.0, -10.0, 0.0), Vector::new(0.0, 1.0, 0.0));
+        assert_hit(cone.intersect(&ray), 10.0, Vector::new(0.0, -1.0, 0.0));
+        // the mirrored cone above the apex is not part of the shape
+        let ray = Line::new(Vector::new(50.0, 15.0, 0.0), Vector::new(-1.0, 0.0, 0.0));
+        assert!(cone.intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn box_and_disk_test() {
+        let aabb = AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, -10.0), Vector::new(2.0, 2.0, 2.0));
+        let ray = Line::new(Vector::new(0.5, 0.5, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert_hit(aabb.intersect(&ray), 9.0, Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(aabb.intersect(&ray).uv, Some((0.75, 0.75)));
+        let ray = Line::new(Vector::new(3.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert!(aabb.intersect(&ray).is_none());
+
+        let disk = Disk::new(Vector::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0), 1.0);
+        let ray = Line::new(Vector::new(0.5, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert_hit(disk.intersect(&ray), 5.0, Vector::new(0.0, 0.0, 1.0));
+        let ray = Line::new(Vector::new(1.5, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert!(disk.intersect(&ray).is_none());
+    }
+
+    // moving the hit by the uv step to a nearby hit along the derivatives lands close to it
+    fn assert_derivatives(primitive: &dyn IntersectionPrimitive, ray: &Line, step: Vector) {
+        let hit = primitive.intersect(ray);
+        let moved = primitive.intersect(&Line::new(ray.point + step, ray.direction));
+        let derivatives = hit.derivatives.unwrap();
+        let ((u, v), (moved_u, moved_v)) = (hit.uv.unwrap(), moved.uv.unwrap());
+        let (du, dv) = (moved_u - u, moved_v - v);
+        let point = hit.unwrap().0 + derivatives.dpdu * du + derivatives.dpdv * dv;
+        assert!((point - moved.unwrap().0).length() < 0.1 * step.length(), "point: {} {}", point.to_string(), moved.unwrap().0.to_string());
+        let (normal, moved_normal) = (hit.normal.unwrap(), moved.normal.unwrap());
+        let predicted = normal + derivatives.dndu * du + derivatives.dndv * dv;
+        assert!((predicted - moved_normal).length() < 0.1 * (moved_normal - normal).length() + 1e-4, "normal: {} {}", predicted.to_string(), moved_normal.to_string());
+    }
+
+    #[test]
+    fn derivatives_test() {
+        let forward = Vector::new(0.0, 0.0, -1.0);
+        let step = Vector::new(0.03, 0.04, 0.0);
+        let sphere = Sphere::new(Vector::new(0.0, 0.0, -10.0), 2.0);
+        assert_derivatives(&sphere, &Line::new(Vector::new(0.3, 0.4, 0.0), forward), step);
+        let cylinder = Cylinder::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), 10.0, 20.0);
+        assert_derivatives(&cylinder, &Line::new(Vector::new(1.0, 10.0, 50.0), forward), step);
+        let cone = Cone::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), 10.0, 10.0);
+        assert_derivatives(&cone, &Line::new(Vector::new(1.0, 5.0, 50.0), forward), step);
+        let torus = Torus::new(Vector::new(0.0, 0.0, -100.0), Vector::new(0.0, 1.0, 0.0), 40.0, 10.0);
+        assert_derivatives(&torus, &Line::new(Vector::new(3.0, 2.0, 0.0), forward), step);
+        let aabb = AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, -10.0), Vector::new(2.0, 2.0, 2.0));
+        assert_derivatives(&aabb, &Line::new(Vector::new(0.5, 0.5, 0.0), forward), step);
+        let disk = Disk::new(Vector::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0), 1.0);
+        assert_derivatives(&disk, &Line::new(Vector::new(0.5, 0.2, 0.0), forward), step);
+        let quad = Surface::new_vw(Vector::new(-2.0, -2.0, -5.0), Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), Some((0.0, 4.0)), Some((0.0, 4.0)), Vector::new(0.0, 0.0, 1.0));
+        assert_derivatives(&quad, &Line::new(Vector::new(0.5, 0.2, 0.0), forward), step);
+        // stretched and turned
+        let mut rotation = crate::math::Quaternion::identity();
+        rotation.rotate(0.5, Vector::new(0.0, 0.0, 1.0));
+        let ellipsoid = Instance::from_trs(std::sync::Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0)), Vector::new(0.0, 0.0, -10.0), rotation, Vector::new(3.0, 1.0, 2.0)).unwrap();
+        assert_derivatives(&ellipsoid, &Line::new(Vector::new(0.3, 0.4, 0.0), forward), step);
+    }
+
+    #[test]
+    fn sample_surface_test() {
+        let sphere = Sphere::new(Vector::new(1.0, 2.0, 3.0), 2.0);
+        let quad = Surface::new_vw(Vector::new(0.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0), Some((0.0, 4.0)), Some((-1.0, 1.0)), Vector::new(0.0, 1.0, 0.0));
+        let triangle = Triangle::new([Vector::new(0.0, 0.0, 0.0), Vector::new(2.0, 0.0, 0.0), Vector::new(0.0, 2.0, 0.0)], Vector::from_num(1.0));
+        assert!((IntersectionPrimitive::area(&quad) - 8.0).abs() < 1e-5);
+        assert!((IntersectionPrimitive::area(&triangle) - 2.0).abs() < 1e-5);
+        for (u, v) in [(0.1, 0.9), (0.5, 0.5), (0.99, 0.01)] {
+            let (point, normal) = IntersectionPrimitive::sample_surface(&sphere, u, v).unwrap();
+            assert!(((point - sphere.center).length() - 2.0).abs() < 1e-4);
+            assert!((normal.length() - 1.0).abs() < 1e-4);
+            let (point, _) = IntersectionPrimitive::sample_surface(&quad, u, v).unwrap();
+            assert!(point.x >= 0.0 && point.x <= 4.0 && point.z.abs() <= 1.0 && point.y == 0.0);
+            let (point, _) = IntersectionPrimitive::sample_surface(&triangle, u, v).unwrap();
+            assert!(point.x >= 0.0 && point.y >= 0.0 && point.x + point.y <= 2.0 + 1e-5);
+        }
+        // infinite planes can't be sampled
+        assert_eq!(IntersectionPrimitive::area(&Surface::new_normal(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0))), 0.0);
+
+        // points land on a face of the box with its outward normal
+        let block = AxisAlignedBox::new(Vector::new(-1.0, 0.0, 0.0), Vector::new(1.0, 1.0, 3.0));
+        assert!((IntersectionPrimitive::area(&block) - 22.0).abs() < 1e-5);
+        for (u, v) in [(0.0, 0.3), (0.2, 0.9), (0.5, 0.5), (0.8, 0.1), (0.999, 0.7)] {
+            let (point, normal) = IntersectionPrimitive::sample_surface(&block, u, v).unwrap();
+            assert!(block.contains(&(point - normal * 1e-3)) && !block.contains(&(point + normal * 1e-3)), "{:?} {:?}", point, normal);
+        }
+
+        // instances carry the area over under uniform scale and sample in world space
+        let mut rotation = crate::math::Quaternion::identity();
+        rotation.rotate(0.7, Vector::new(1.0, 1.0, 0.0)._normalize());
+        let ball = Instance::from_trs(std::sync::Arc::new(sphere), Vector::new(0.0, 5.0, 0.0), rotation, Vector::from_num(3.0)).unwrap();
+        assert!((IntersectionPrimitive::area(&ball) - 4.0 * std::f32::consts::PI * 36.0).abs() < 1e-2);
+        let (point, normal) = ball.sample_surface(0.3, 0.6).unwrap();
+        let center = ball.point_to_world(&Vector::new(1.0, 2.0, 3.0));
+        assert!(((point - center).length() - 6.0).abs() < 1e-3);
+        assert!((normal - (point - center) / 6.0).length() < 1e-3);
+        let stretched = Instance::from_trs(std::sync::Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0)), Vector::new(0.0, 0.0, 0.0), crate::math::Quaternion::identity(), Vector::new(1.0, 2.0, 1.0)).unwrap();
+        assert_eq!(IntersectionPrimitive::area(&stretched), 0.0);
+        assert!(stretched.sample_surface(0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn sdf_test() {
+        let sdf = Sdf::Sphere { center: Vector::new(0.0, 0.0, -10.0), radius: 2.0 };
+        let bounds = AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, -10.0), Vector::from_num(5.0));
+        let primitive = SdfPrimitive::new(sdf, bounds);
+        let ray = Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        let hit = primitive.intersect(&ray);
+        assert!((hit.distance - 8.0).abs() < 0.01);
+        assert!((hit.normal.unwrap().z - 1.0).abs() < 0.01);
+
+        // a ray starting on the surface and leaving it doesn't hit the shape again
+        let ray = Line::new(Vector::new(0.0, 0.0, -8.0), Vector::new(0.0, 1.0, 1.0)._normalize());
+        assert!(primitive.intersect(&ray).is_none());
+    }
 }
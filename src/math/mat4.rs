@@ -57,6 +57,16 @@ impl Mat4 {
         self.m[2][2] *= z;
     }
 
+    // multiplies the matrix with a scale matrix. like rotate, the scale is applied before
+    // whatever the matrix already does, so it also works on rotated matrices
+    pub fn multiply_scale(&mut self, Vector { x, y, z, .. }: Vector) {
+        for row in self.m.iter_mut() {
+            row[0] *= x;
+            row[1] *= y;
+            row[2] *= z;
+        }
+    }
+
     // for information about this algorithm, see:
     // https://en.wikipedia.org/wiki/Rotation_matrix#Rotation_matrix_from_axis_and_angle
     pub fn rotate(&mut self, angle: f32, Vector { x, y, z, w}: Vector) {
@@ -222,6 +232,47 @@ impl Mat4 {
         true
     }
 
+    // returns the inverse without modifying self, None if the matrix is singular
+    pub fn inverted(&self) -> Option<Mat4> {
+        let mut out = *self;
+        if out.inverse() {
+            Some(out)
+        } else {
+            None
+        }
+    }
+
+    pub fn transposed(&self) -> Mat4 {
+        let mut out = *self;
+        out.transpose();
+        out
+    }
+
+    // transforms a point, translation included
+    pub fn transform_point(&self, p: &Vector) -> Vector {
+        let m = &self.m;
+        let mut out = Vector::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        );
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w != 1.0 && w != 0.0 {
+            out /= w;
+        }
+        out
+    }
+
+    // transforms a direction, translation is ignored
+    pub fn transform_direction(&self, d: &Vector) -> Vector {
+        let m = &self.m;
+        Vector::new(
+            m[0][0] * d.x + m[0][1] * d.y + m[0][2] * d.z,
+            m[1][0] * d.x + m[1][1] * d.y + m[1][2] * d.z,
+            m[2][0] * d.x + m[2][1] * d.y + m[2][2] * d.z,
+        )
+    }
+
     pub fn transpose(&mut self) {
         let mut m = self.m;
         for i in 0..4 {
@@ -507,6 +558,32 @@ mod test {
         assert_eq!(m, result);
     }
 
+    #[test]
+    fn scale_after_rotate_test() {
+        let mut m = Mat4::identity();
+        m.translate(Vector::new(0.0, 0.0, 5.0));
+        m.rotate(as_radians(90.0), Vector::new(0.0, 0.0, 1.0));
+        m.multiply_scale(Vector::new(2.0, 1.0, 1.0));
+        // scaled along x first, then rotated onto y, then translated
+        let p = m.transform_point(&Vector::new(1.0, 0.0, 0.0));
+        assert!((p - Vector::new(0.0, 2.0, 5.0)).length() < 0.0001);
+        let d = m.transform_direction(&Vector::new(1.0, 0.0, 0.0));
+        assert!((d - Vector::new(0.0, 2.0, 0.0)).length() < 0.0001);
+    }
+
+    #[test]
+    fn inverted_test() {
+        let mut m = Mat4::identity();
+        m.translate(Vector::new(1.0, -2.0, 3.0));
+        m.rotate(as_radians(30.0), Vector::new(1.0, 1.0, 0.0));
+        m.multiply_scale(Vector::new(2.0, 3.0, 4.0));
+        let inv = m.inverted().unwrap();
+        let p = Vector::new(0.3, 0.7, -1.2);
+        let back = inv.transform_point(&m.transform_point(&p));
+        assert!((back - p).length() < 0.0001);
+        assert!(Mat4::new().inverted().is_none());
+    }
+
     #[test]
     fn transpose_test() {
         let mut m = Mat4::identity();
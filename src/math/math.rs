@@ -3,9 +3,121 @@ pub fn as_radians(angle: f32) -> f32 {
     angle * std::f32::consts::PI / 180.0
 }
 
-// convert radians to degrees
-pub fn as_degrees(angle: f32) -> f32 {
-    angle * 180.0 / std::f32::consts::PI
+// real roots of a*x^2 + b*x + c, in ascending order
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return Vec::new();
+        }
+        return vec![-c / b];
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    // numerically stable form, avoids subtracting two close numbers
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let mut roots = if q.abs() < 1e-12 {
+        vec![0.0, 0.0]
+    } else {
+        vec![q / a, c / q]
+    };
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+// real roots of x^3 + a*x^2 + b*x + c, in ascending order
+// cardano's method, as in Graphics Gems I (Jochen Schwarze, "Cubic and Quartic Roots")
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let sq_a = a * a;
+    let p = (1.0 / 3.0) * (-(1.0 / 3.0) * sq_a + b);
+    let q = 0.5 * ((2.0 / 27.0) * a * sq_a - (1.0 / 3.0) * a * b + c);
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if d.abs() < 1e-14 {
+        if q.abs() < 1e-14 {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        let phi = (1.0 / 3.0) * (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos();
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::PI / 3.0).cos(),
+            -t * (phi - std::f64::consts::PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    let sub = (1.0 / 3.0) * a;
+    for root in roots.iter_mut() {
+        *root -= sub;
+    }
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+// real roots of x^4 + a*x^3 + b*x^2 + c*x + d, in ascending order
+// ferrari's method, reduced to a cubic resolvent. roots are polished with a few newton steps
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // substitute x = y - a/4 to get y^4 + p*y^2 + q*y + r
+    let sq_a = a * a;
+    let p = -(3.0 / 8.0) * sq_a + b;
+    let q = (1.0 / 8.0) * sq_a * a - 0.5 * a * b + c;
+    let r = -(3.0 / 256.0) * sq_a * sq_a + (1.0 / 16.0) * sq_a * b - 0.25 * a * c + d;
+
+    let mut roots = Vec::new();
+    if r.abs() < 1e-14 {
+        // y * (y^3 + p*y + q) = 0
+        roots.push(0.0);
+        roots.append(&mut solve_cubic(0.0, p, q));
+    } else {
+        // take one root of the resolvent cubic
+        let z = solve_cubic(-0.5 * p, -r, 0.5 * r * p - 0.125 * q * q);
+        let z = z[z.len() - 1];
+
+        let mut u = z * z - r;
+        let mut v = 2.0 * z - p;
+        if u.abs() < 1e-14 {
+            u = 0.0;
+        } else if u > 0.0 {
+            u = u.sqrt();
+        } else {
+            return Vec::new();
+        }
+        if v.abs() < 1e-14 {
+            v = 0.0;
+        } else if v > 0.0 {
+            v = v.sqrt();
+        } else {
+            return Vec::new();
+        }
+
+        let v_signed = if q < 0.0 { -v } else { v };
+        roots.append(&mut solve_quadratic(1.0, v_signed, z - u));
+        roots.append(&mut solve_quadratic(1.0, -v_signed, z + u));
+    }
+
+    let sub = 0.25 * a;
+    for root in roots.iter_mut() {
+        *root -= sub;
+        for _ in 0..2 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df.abs() > 1e-12 {
+                *root = x - f / df;
+            }
+        }
+    }
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
 }
 
 
@@ -22,8 +134,31 @@ mod test {
         assert_eq!(as_radians(180.0), std::f32::consts::PI);
     }
 
+    fn assert_roots(roots: Vec<f64>, expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "roots: {:?}", roots);
+        for (root, expected) in roots.iter().zip(expected.iter()) {
+            assert!((root - expected).abs() < 1e-6, "roots: {:?}", roots);
+        }
+    }
+
+    #[test]
+    fn test_solve_quadratic() {
+        // (x - 1)(x - 3)
+        assert_roots(solve_quadratic(1.0, -4.0, 3.0), &[1.0, 3.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+    }
+
+    #[test]
+    fn test_solve_cubic() {
+        // (x + 2)(x - 1)(x - 4)
+        assert_roots(solve_cubic(-3.0, -6.0, 8.0), &[-2.0, 1.0, 4.0]);
+    }
+
     #[test]
-    fn test_as_degrees() {
-        assert_eq!(as_degrees(std::f32::consts::PI), 180.0);
+    fn test_solve_quartic() {
+        // (x + 3)(x + 1)(x - 2)(x - 5)
+        assert_roots(solve_quartic(-3.0, -15.0, 19.0, 30.0), &[-3.0, -1.0, 2.0, 5.0]);
+        // (x^2 + 1)(x - 1)(x - 2), two complex roots
+        assert_roots(solve_quartic(-3.0, 3.0, -3.0, 2.0), &[1.0, 2.0]);
     }
 }
\ No newline at end of file
@@ -4,10 +4,11 @@ pub mod math;
 pub mod quaternion;
 pub mod vector;
 pub mod raycasthit;
+pub mod noise;
 
-pub use intersection::IntersectionPrimitive;
+pub use intersection::{IntersectionPrimitive, RayInterval, SurfaceCrossing};
 pub use mat4::Mat4;
-pub use math::{as_degrees, as_radians};
+pub use math::{as_radians, solve_quadratic, solve_quartic};
 pub use quaternion::Quaternion;
 pub use vector::Vector;
-pub use raycasthit::RayCastHit;
\ No newline at end of file
+pub use raycasthit::{RayCastHit, SurfaceDerivatives};
\ No newline at end of file
@@ -0,0 +1,194 @@
+use super::Vector;
+
+// integer hash of a lattice point (murmur3 finalizer), replaces perlin's permutation table
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+// dot product with one of the 12 edge directions of a cube, as in improved perlin noise
+fn gradient(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// perlin gradient noise, roughly in -1..1 and zero at every integer point
+pub fn perlin(p: &Vector) -> f32 {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (fx as i32, fy as i32, fz as i32);
+    let (dx, dy, dz) = (p.x - fx, p.y - fy, p.z - fz);
+    let (u, v, w) = (fade(dx), fade(dy), fade(dz));
+
+    let corner = |i: i32, j: i32, k: i32| gradient(hash(x + i, y + j, z + k), dx - i as f32, dy - j as f32, dz - k as f32);
+
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+            v,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+            v,
+        ),
+        w,
+    )
+}
+
+// fractal brownian motion, octaves of perlin noise with rising frequency and falling amplitude
+pub fn fbm(p: &Vector, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves {
+        sum += amplitude * perlin(&(*p * frequency));
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    sum
+}
+
+// sum of the absolute values of perlin octaves, the creases give marble its veins. roughly 0..1.5
+pub fn turbulence(p: &Vector, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves {
+        sum += amplitude * perlin(&(*p * frequency)).abs();
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum
+}
+
+// 3d simplex noise (Gustavson's "Simplex noise demystified"), roughly in -1..1 and
+// without the grid aligned look of perlin noise
+pub fn simplex(p: &Vector) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+    // skew to the simplex grid and find the cell
+    let s = (p.x + p.y + p.z) * F3;
+    let (i, j, k) = ((p.x + s).floor(), (p.y + s).floor(), (p.z + s).floor());
+    let t = (i + j + k) * G3;
+    let (x0, y0, z0) = (p.x - (i - t), p.y - (j - t), p.z - (k - t));
+    // which of the six tetrahedra of the cell the point is in
+    let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+        if y0 >= z0 {
+            ((1, 0, 0), (1, 1, 0))
+        } else if x0 >= z0 {
+            ((1, 0, 0), (1, 0, 1))
+        } else {
+            ((0, 0, 1), (1, 0, 1))
+        }
+    } else if y0 < z0 {
+        ((0, 0, 1), (0, 1, 1))
+    } else if x0 < z0 {
+        ((0, 1, 0), (0, 1, 1))
+    } else {
+        ((0, 1, 0), (1, 1, 0))
+    };
+    let (i, j, k) = (i as i32, j as i32, k as i32);
+    let corner = |di: i32, dj: i32, dk: i32, offset: f32| {
+        let (x, y, z) = (x0 - di as f32 + offset, y0 - dj as f32 + offset, z0 - dk as f32 + offset);
+        let t = 0.6 - x * x - y * y - z * z;
+        if t < 0.0 {
+            0.0
+        } else {
+            t * t * t * t * gradient(hash(i + di, j + dj, k + dk), x, y, z)
+        }
+    };
+    32.0 * (corner(0, 0, 0, 0.0) + corner(i1, j1, k1, G3) + corner(i2, j2, k2, 2.0 * G3) + corner(1, 1, 1, 3.0 * G3))
+}
+
+// 0..1 from the top bits of a hash
+fn unit(hash: u32) -> f32 {
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+// cellular noise, one feature point jittered inside every unit cell. returns the distance to the
+// closest feature point and a hash of its cell, the same for every point of a voronoi cell
+pub fn worley(p: &Vector) -> (f32, u32) {
+    let (x, y, z) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+    let mut closest = (f32::MAX, 0);
+    for i in x - 1..=x + 1 {
+        for j in y - 1..=y + 1 {
+            for k in z - 1..=z + 1 {
+                let h = hash(i, j, k);
+                let feature = Vector::new(
+                    i as f32 + unit(h),
+                    j as f32 + unit(hash(h as i32, 1, 0)),
+                    k as f32 + unit(hash(h as i32, 2, 0)),
+                );
+                let distance = (feature - *p).length();
+                if distance < closest.0 {
+                    closest = (distance, h);
+                }
+            }
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_test() {
+        assert_eq!(perlin(&Vector::new(3.0, -2.0, 7.0)), 0.0);
+        let mut min = f32::MAX;
+        let mut max = f32::MIN;
+        for i in 0..1000 {
+            let p = Vector::new(i as f32 * 0.137, i as f32 * 0.291 - 40.0, i as f32 * 0.053);
+            let n = perlin(&p);
+            assert_eq!(n, perlin(&p));
+            min = min.min(n);
+            max = max.max(n);
+        }
+        assert!(min >= -1.5 && max <= 1.5);
+        assert!(max - min > 0.5);
+    }
+
+    #[test]
+    fn simplex_worley_test() {
+        let mut min = f32::MAX;
+        let mut max = f32::MIN;
+        for i in 0..1000 {
+            let p = Vector::new(i as f32 * 0.137, i as f32 * 0.291 - 40.0, i as f32 * 0.053);
+            let n = simplex(&p);
+            min = min.min(n);
+            max = max.max(n);
+            // the closest feature point is never further away than the cell diagonal
+            let (distance, _) = worley(&p);
+            assert!((0.0..=3.0f32.sqrt()).contains(&distance));
+        }
+        assert!(min >= -1.2 && max <= 1.2);
+        assert!(max - min > 0.5);
+    }
+}
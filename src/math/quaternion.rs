@@ -67,6 +67,7 @@ impl Quaternion {
         mat.m[2][1] = 2.0 * q3 * q4 + 2.0 * q1 * q2;
         mat.m[2][2] = 1.0 - 2.0 * q2 * q2 - 2.0 * q3 * q3;
         //mat[2][3] = 0.0;
+        mat.m[3][3] = 1.0;
 
         mat
     }
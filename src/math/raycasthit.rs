@@ -7,6 +7,30 @@ pub struct RayCastHit {
     pub distance: f32,
     pub pos_on_screen: (i32, i32),
     pub uv: Option<(f32, f32)>,
+    // direction of increasing u on the surface, normal and bump maps are applied in the
+    // frame it forms with the normal
+    pub tangent: Option<Vector>,
+    // the hit in the space of the primitive inside an Instance, for object space textures
+    pub object_position: Option<Vector>,
+    pub derivatives: Option<SurfaceDerivatives>,
+}
+
+// how the point and the normal change along u and v of the surface. they give the footprint
+// of textures and how much reflected and refracted rays spread
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceDerivatives {
+    pub dpdu: Vector,
+    pub dpdv: Vector,
+    pub dndu: Vector,
+    pub dndv: Vector,
+}
+
+impl SurfaceDerivatives {
+    // a surface that doesn't bend
+    pub fn flat(dpdu: Vector, dpdv: Vector) -> SurfaceDerivatives {
+        let zero = Vector::new(0.0, 0.0, 0.0);
+        SurfaceDerivatives { dpdu, dpdv, dndu: zero, dndv: zero }
+    }
 }
 
 impl RayCastHit {
@@ -17,6 +41,9 @@ impl RayCastHit {
             distance: 0.0,
             pos_on_screen: (0, 0),
             uv: None,
+            tangent: None,
+            object_position: None,
+            derivatives: None,
         }
     }
 
@@ -35,6 +62,16 @@ impl RayCastHit {
         self
     }
 
+    pub fn with_tangent(mut self, tangent: Vector) -> RayCastHit {
+        self.tangent = Some(tangent);
+        self
+    }
+
+    pub fn with_derivatives(mut self, derivatives: Option<SurfaceDerivatives>) -> RayCastHit {
+        self.derivatives = derivatives;
+        self
+    }
+
     pub fn is_some(&self) -> bool {
         self.hit.is_some()
     }
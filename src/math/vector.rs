@@ -183,6 +183,18 @@ impl Vector {
     pub fn lerp(a: &Vector, b: &Vector, t: f32) -> Vector {
         *a * (1.0 - t) + *b * t
     }
+
+    // two unit vectors perpendicular to self and to each other. self has to be normalized
+    pub fn orthonormal_basis(&self) -> (Vector, Vector) {
+        let helper = if self.x.abs() > 0.9 {
+            Vector::new(0.0, 1.0, 0.0)
+        } else {
+            Vector::new(1.0, 0.0, 0.0)
+        };
+        let tangent = self.cross(&helper)._normalize();
+        let bitangent = self.cross(&tangent);
+        (tangent, bitangent)
+    }
 }
 
 impl From<Color> for Vector {
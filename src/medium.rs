@@ -0,0 +1,242 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::density_grid::DensityGrid;
+use crate::geometry::AxisAlignedBox;
+use crate::math::Vector;
+use crate::spectrum::{emission_for, unbounded_for, Wavelengths};
+
+// henyey-greenstein phase function. g > 0 scatters forward, g < 0 backward, 0 is isotropic.
+// cos_theta is the cosine between the direction light travels in and the scattered direction
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let g2 = g * g;
+    let denom = 1.0 + g2 - 2.0 * g * cos_theta;
+    (1.0 - g2) / (4.0 * PI * denom * denom.sqrt())
+}
+
+// participating medium. coefficients are per unit of scene distance, a Volume with a
+// density grid scales them per point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    // henyey-greenstein asymmetry, -1..1
+    pub g: f32,
+    // light emitted per unit of distance, for fire
+    pub emission: Color,
+}
+
+impl Medium {
+    pub fn new(absorption: Color, scattering: Color, g: f32) -> Medium {
+        Medium {
+            absorption,
+            scattering,
+            g: g.clamp(-0.99, 0.99),
+            emission: Color::black(),
+        }
+    }
+
+    pub fn with_emission(mut self, emission: Color) -> Medium {
+        self.emission = emission;
+        self
+    }
+
+    // largest extinction of the three channels
+    pub fn max_extinction(&self) -> f32 {
+        let extinction = self.extinction();
+        extinction.r.max(extinction.g).max(extinction.b)
+    }
+
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    // beer-lambert, the fraction of light left after travelling distance through the medium
+    pub fn transmittance(&self, distance: f32) -> Color {
+        let extinction = self.extinction();
+        Color::new(
+            (-extinction.r * distance).exp(),
+            (-extinction.g * distance).exp(),
+            (-extinction.b * distance).exp(),
+        )
+    }
+
+    // the medium for a ray with these wavelengths, unchanged outside of spectral mode
+    pub fn for_ray(&self, spectral: Option<&Wavelengths>) -> Medium {
+        Medium {
+            absorption: unbounded_for(&self.absorption, spectral),
+            scattering: unbounded_for(&self.scattering, spectral),
+            g: self.g,
+            emission: emission_for(&self.emission, spectral),
+        }
+    }
+
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        henyey_greenstein(cos_theta, self.g)
+    }
+
+    // distance after which less than 1% of the light is left in the thinnest channel
+    pub fn visibility_distance(&self) -> f32 {
+        let extinction = self.extinction();
+        let min = extinction.r.min(extinction.g).min(extinction.b);
+        if min <= 0.0 {
+            return f32::INFINITY;
+        }
+        100.0_f32.ln() / min
+    }
+}
+
+// a medium filling a box of the scene. without grids the medium is homogeneous, a density
+// grid stretched over the box scales the coefficients and makes it heterogeneous (smoke, clouds).
+// an emission grid (e.g. temperature) scales the emission, otherwise the density does.
+#[derive(Debug, Clone)]
+pub struct Volume {
+    pub bounds: AxisAlignedBox,
+    pub medium: Medium,
+    pub density: Option<Arc<DensityGrid>>,
+    pub emission_grid: Option<Arc<DensityGrid>>,
+    // index of the scene primitive the medium fills instead of the box, set by
+    // Scene::add_primitive_with_volume. the box then only places the grids
+    pub shape: Option<usize>,
+}
+
+impl Volume {
+    pub fn new(bounds: AxisAlignedBox, medium: Medium) -> Volume {
+        Volume {
+            bounds,
+            medium,
+            density: None,
+            emission_grid: None,
+            shape: None,
+        }
+    }
+
+    pub fn with_density(mut self, density: Arc<DensityGrid>) -> Volume {
+        self.density = Some(density);
+        self
+    }
+
+    pub fn with_emission_grid(mut self, emission_grid: Arc<DensityGrid>) -> Volume {
+        self.emission_grid = Some(emission_grid);
+        self
+    }
+
+    // only for volumes filling their box, the scene knows the shapes of the others
+    pub fn contains(&self, point: &Vector) -> bool {
+        self.shape.is_none() && self.bounds.contains(point)
+    }
+
+    // the emission grid doesn't matter here, it doesn't change how light travels
+    pub fn is_homogeneous(&self) -> bool {
+        self.density.is_none()
+    }
+
+    // position inside the box mapped to 0..1 for the grid lookups
+    fn grid_position(&self, point: &Vector) -> Vector {
+        let size = self.bounds.size();
+        let local = *point - self.bounds.min;
+        Vector::new(local.x / size.x, local.y / size.y, local.z / size.z)
+    }
+
+    pub fn density_at(&self, point: &Vector) -> f32 {
+        match &self.density {
+            Some(grid) => grid.sample(&self.grid_position(point)),
+            None => 1.0,
+        }
+    }
+
+    pub fn max_density(&self) -> f32 {
+        match &self.density {
+            Some(grid) => grid.max_value(),
+            None => 1.0,
+        }
+    }
+
+    // how much of the medium's emission there is at the point
+    pub fn emission_scale_at(&self, point: &Vector) -> f32 {
+        match &self.emission_grid {
+            Some(grid) => grid.sample(&self.grid_position(point)),
+            None => self.density_at(point),
+        }
+    }
+}
+
+// a piece of a ray inside a single medium. volume is None for the global medium.
+// the medium is already converted for the wavelengths of the ray
+#[derive(Debug, Clone, Copy)]
+pub struct MediumSegment<'a> {
+    pub start: f32,
+    pub end: f32,
+    pub medium: Medium,
+    pub volume: Option<&'a Volume>,
+}
+
+impl MediumSegment<'_> {
+    pub fn length(&self) -> f32 {
+        self.end - self.start
+    }
+
+    pub fn emission_at(&self, point: &Vector) -> Color {
+        match self.volume {
+            Some(_) if self.medium.emission.is_black() => Color::black(),
+            Some(volume) => self.medium.emission * volume.emission_scale_at(point),
+            None => self.medium.emission,
+        }
+    }
+
+    // upper bound of the extinction in the segment, used for delta and ratio tracking
+    pub fn majorant(&self) -> f32 {
+        let max_density = self.volume.map_or(1.0, |volume| volume.max_density());
+        self.medium.max_extinction() * max_density
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn henyey_greenstein_test() {
+        // integrates to one over the sphere
+        let steps = 10000;
+        let mut sum = 0.0;
+        for i in 0..steps {
+            let cos_theta = -1.0 + 2.0 * (i as f32 + 0.5) / steps as f32;
+            sum += henyey_greenstein(cos_theta, 0.6) * 2.0 * PI * (2.0 / steps as f32);
+        }
+        assert!((sum - 1.0).abs() < 1e-3);
+        assert!((henyey_greenstein(0.3, 0.0) - 1.0 / (4.0 * PI)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn transmittance_test() {
+        let medium = Medium::new(Color::new(0.1, 0.2, 0.0), Color::new(0.1, 0.0, 0.0), 0.0);
+        let t = medium.transmittance(5.0);
+        assert!((t.r - (-1.0_f32).exp()).abs() < 1e-6);
+        assert!((t.g - (-1.0_f32).exp()).abs() < 1e-6);
+        assert_eq!(t.b, 1.0);
+    }
+
+    #[test]
+    fn shaped_volume_test() {
+        use crate::camera::medium_segments;
+        use crate::geometry::{Line, Sphere};
+        use crate::scene::Scene;
+        use crate::stats::RenderStats;
+
+        let mut scene = Scene::new();
+        let medium = Medium::new(Color::new(0.1, 0.1, 0.1), Color::black(), 0.0);
+        let bounds = AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, -10.0), Vector::from_num(4.0));
+        scene.add_primitive_with_volume(Box::new(Sphere::new(Vector::new(0.0, 0.0, -10.0), 2.0)), 0, Volume::new(bounds, medium));
+
+        // the medium starts at the sphere, not at the box around it
+        let ray = Line::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        let segments = medium_segments(&ray, 100.0, &scene, &mut RenderStats::new());
+        assert_eq!(segments.len(), 1);
+        assert!((segments[0].start - 8.0).abs() < 1e-4 && (segments[0].end - 12.0).abs() < 1e-4);
+        // a corner of the box is outside of the sphere
+        assert!(scene.volume_at(&Vector::new(1.9, 1.9, -8.1)).is_none());
+        assert!(scene.volume_at(&Vector::new(0.5, 0.5, -10.0)).is_some());
+    }
+}
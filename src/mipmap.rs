@@ -0,0 +1,254 @@
+use image::ImageBuffer;
+
+use crate::color::Color;
+
+// lookups further apart than this along their major axis are blurred along it
+const MAX_ANISOTROPY: f32 = 8.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureFilter {
+    // isotropic, blurs surfaces seen at grazing angles
+    Trilinear,
+    // elliptical weighted average, sharper at grazing angles but slower
+    Ewa,
+}
+
+// how uv changes from one pixel to the next, to the right (x) and down (y)
+#[derive(Debug, Clone, Copy)]
+pub struct Footprint {
+    pub dx: (f32, f32),
+    pub dy: (f32, f32),
+}
+
+#[derive(Debug, Clone)]
+struct MipLevel {
+    width: u32,
+    height: u32,
+    texels: Vec<Color>,
+}
+
+// image with its pyramid of prefiltered, half sized copies down to 1x1
+#[derive(Debug, Clone)]
+pub struct MipMap {
+    levels: Vec<MipLevel>,
+    pub filter: TextureFilter,
+    // uv outside of 0..1 repeat the image instead of its edge
+    pub repeat: bool,
+}
+
+impl MipMap {
+    pub fn new(image: &ImageBuffer<image::Rgb<u8>, Vec<u8>>) -> MipMap {
+        let (width, height) = image.dimensions();
+        let texels = image.pixels().map(|p| Color::new(p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0)).collect();
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(last) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            levels.push(downsample(last));
+        }
+        MipMap { levels, filter: TextureFilter::Trilinear, repeat: false }
+    }
+
+    pub fn with_filter(mut self, filter: TextureFilter) -> MipMap {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: bool) -> MipMap {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    // filtered over the footprint, rays without one get the nearest texel of the full image
+    pub fn sample(&self, u: f32, v: f32, footprint: Option<&Footprint>) -> Color {
+        match footprint {
+            None => self.nearest(u, v),
+            Some(footprint) => match self.filter {
+                TextureFilter::Trilinear => {
+                    let width = 2.0 * footprint.dx.0.abs().max(footprint.dx.1.abs()).max(footprint.dy.0.abs()).max(footprint.dy.1.abs());
+                    self.trilinear(u, v, width)
+                }
+                TextureFilter::Ewa => self.ewa(u, v, footprint.dx, footprint.dy),
+            },
+        }
+    }
+
+    pub fn nearest(&self, u: f32, v: f32) -> Color {
+        let level = &self.levels[0];
+        let (u, v) = self.wrap(u, v);
+        let x = (u * (level.width - 1) as f32) as i64;
+        let y = (v * (level.height - 1) as f32) as i64;
+        self.texel(0, x, y)
+    }
+
+    // blend of the two levels whose texels are closest to width in size
+    pub fn trilinear(&self, u: f32, v: f32, width: f32) -> Color {
+        let level = (self.levels.len() - 1) as f32 + width.max(1e-8).log2();
+        if level <= 0.0 {
+            return self.bilinear(0, u, v);
+        }
+        if level >= (self.levels.len() - 1) as f32 {
+            return self.texel(self.levels.len() - 1, 0, 0);
+        }
+        let lower = level.floor() as usize;
+        let mut color = self.bilinear(lower, u, v);
+        color.blend(&self.bilinear(lower + 1, u, v), level - lower as f32);
+        color
+    }
+
+    pub fn bilinear(&self, level: usize, u: f32, v: f32) -> Color {
+        let (u, v) = self.wrap(u, v);
+        let (width, height) = (self.levels[level].width, self.levels[level].height);
+        let (x, y) = (u * width as f32 - 0.5, v * height as f32 - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        self.texel(level, x0, y0) * ((1.0 - fx) * (1.0 - fy))
+            + self.texel(level, x0 + 1, y0) * (fx * (1.0 - fy))
+            + self.texel(level, x0, y0 + 1) * ((1.0 - fx) * fy)
+            + self.texel(level, x0 + 1, y0 + 1) * (fx * fy)
+    }
+
+    // "Creating Raster Omnimax Images from Multiple Perspective Views Using the Elliptical
+    // Weighted Average Filter" (Greene, Heckbert 1986), as in pbrt. the level is picked by
+    // the minor axis of the footprint ellipse, which is then filtered along its major axis
+    pub fn ewa(&self, u: f32, v: f32, dx: (f32, f32), dy: (f32, f32)) -> Color {
+        let length = |d: (f32, f32)| (d.0 * d.0 + d.1 * d.1).sqrt();
+        let (major_axis, mut minor_axis) = if length(dx) >= length(dy) { (dx, dy) } else { (dy, dx) };
+        let major = length(major_axis);
+        let mut minor = length(minor_axis);
+        // very long ellipses would take too many texels, make them wider
+        if minor * MAX_ANISOTROPY < major && minor > 0.0 {
+            let scale = major / (minor * MAX_ANISOTROPY);
+            minor_axis = (minor_axis.0 * scale, minor_axis.1 * scale);
+            minor *= scale;
+        }
+        if minor == 0.0 {
+            return self.bilinear(0, u, v);
+        }
+        let level = ((self.levels.len() - 1) as f32 + minor.log2()).max(0.0);
+        let lower = level.floor() as usize;
+        let mut color = self.ewa_level(lower, u, v, major_axis, minor_axis);
+        color.blend(&self.ewa_level(lower + 1, u, v, major_axis, minor_axis), level - lower as f32);
+        color
+    }
+
+    fn ewa_level(&self, level: usize, u: f32, v: f32, d0: (f32, f32), d1: (f32, f32)) -> Color {
+        if level >= self.levels.len() - 1 {
+            return self.texel(self.levels.len() - 1, 0, 0);
+        }
+        let (u, v) = self.wrap(u, v);
+        let (width, height) = (self.levels[level].width as f32, self.levels[level].height as f32);
+        let (s, t) = (u * width - 0.5, v * height - 0.5);
+        let (d0, d1) = ((d0.0 * width, d0.1 * height), (d1.0 * width, d1.1 * height));
+        // implicit ellipse a*s^2 + b*s*t + c*t^2 < 1 around the lookup
+        let mut a = d0.1 * d0.1 + d1.1 * d1.1 + 1.0;
+        let mut b = -2.0 * (d0.0 * d0.1 + d1.0 * d1.1);
+        let mut c = d0.0 * d0.0 + d1.0 * d1.0 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+        // bounding box of the ellipse
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let (u_sqrt, v_sqrt) = ((det * c).sqrt(), (a * det).sqrt());
+        let (s0, s1) = ((s - 2.0 * inv_det * u_sqrt).ceil() as i64, (s + 2.0 * inv_det * u_sqrt).floor() as i64);
+        let (t0, t1) = ((t - 2.0 * inv_det * v_sqrt).ceil() as i64, (t + 2.0 * inv_det * v_sqrt).floor() as i64);
+
+        let mut sum = Color::black();
+        let mut weights = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f32 - t;
+            for is in s0..=s1 {
+                let ss = is as f32 - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    // gaussian falling to 0 at the edge of the ellipse
+                    let weight = (-2.0 * r2).exp() - (-2.0f32).exp();
+                    sum += self.texel(level, is, it) * weight;
+                    weights += weight;
+                }
+            }
+        }
+        if weights > 0.0 { sum / weights } else { self.bilinear(level, u, v) }
+    }
+
+    fn wrap(&self, u: f32, v: f32) -> (f32, f32) {
+        if self.repeat {
+            (u.rem_euclid(1.0), v.rem_euclid(1.0))
+        } else {
+            (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
+        }
+    }
+
+    fn texel(&self, level: usize, x: i64, y: i64) -> Color {
+        let level = &self.levels[level];
+        let (width, height) = (level.width as i64, level.height as i64);
+        let (x, y) = if self.repeat {
+            (x.rem_euclid(width), y.rem_euclid(height))
+        } else {
+            (x.clamp(0, width - 1), y.clamp(0, height - 1))
+        };
+        level.texels[(y * width + x) as usize]
+    }
+}
+
+// box filter of 2x2 texels, the last row or column of odd sizes is folded into its neighbour
+fn downsample(level: &MipLevel) -> MipLevel {
+    let (width, height) = ((level.width / 2).max(1), (level.height / 2).max(1));
+    let at = |x: u32, y: u32| level.texels[(y.min(level.height - 1) * level.width + x.min(level.width - 1)) as usize];
+    let mut texels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (x0, y0) = (x * 2, y * 2);
+            texels.push((at(x0, y0) + at(x0 + 1, y0) + at(x0, y0 + 1) + at(x0 + 1, y0 + 1)) / 4.0);
+        }
+    }
+    MipLevel { width, height, texels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard() -> MipMap {
+        MipMap::new(&ImageBuffer::from_fn(64, 32, |x, y| image::Rgb([if (x + y) % 2 == 0 { 255 } else { 0 }; 3])))
+    }
+
+    #[test]
+    fn pyramid_test() {
+        let mipmap = checkerboard();
+        // 64x32, 32x16, ... 2x1, 1x1
+        assert_eq!(mipmap.level_count(), 7);
+        assert_eq!(mipmap.dimensions(), (64, 32));
+        // single texels are black or white, the smaller levels average to grey
+        let texel = mipmap.sample(0.3, 0.3, None).r;
+        assert!(texel == 0.0 || texel == 1.0);
+        assert!((mipmap.bilinear(1, 0.3, 0.3).r - 0.5).abs() < 1e-5);
+        assert!((mipmap.trilinear(0.3, 0.3, 0.5).r - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn filter_test() {
+        // a footprint of a few texels along u and a fraction of one along v
+        let footprint = Footprint { dx: (0.1, 0.0), dy: (0.0, 0.01) };
+        for filter in [TextureFilter::Trilinear, TextureFilter::Ewa] {
+            let color = checkerboard().with_filter(filter).sample(0.5, 0.5, Some(&footprint));
+            assert!((color.r - 0.5).abs() < 0.05, "{:?}: {}", filter, color);
+        }
+        // stripes along u stay sharp with ewa, trilinear blurs them away
+        let stripes = MipMap::new(&ImageBuffer::from_fn(64, 64, |_, y| image::Rgb([if (y / 4) % 2 == 0 { 255 } else { 0 }; 3])));
+        let footprint = Footprint { dx: (0.2, 0.0), dy: (0.0, 0.005) };
+        let sharp = stripes.clone().with_filter(TextureFilter::Ewa).sample(0.5, 2.0 / 64.0, Some(&footprint));
+        let blurred = stripes.with_filter(TextureFilter::Trilinear).sample(0.5, 2.0 / 64.0, Some(&footprint));
+        assert!(sharp.r > 0.9, "{}", sharp);
+        assert!((blurred.r - 0.5).abs() < 0.2, "{}", blurred);
+    }
+}
@@ -0,0 +1,387 @@
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::geometry::Line;
+use crate::light::{Light, LightType};
+use crate::material::{Material, MaterialType};
+use crate::math::Vector;
+use crate::scene::Scene;
+use crate::stats::RenderStats;
+
+// cells of the projection maps, equal area bands of z times slices around z
+const Z_CELLS: usize = 32;
+const PHI_CELLS: usize = 64;
+// rays probing every cell for specular objects
+const PROBES_PER_CELL: usize = 2;
+// points on an emitter the projection map is probed from
+const EMITTER_PROBE_POINTS: usize = 4;
+const MAX_PHOTON_BOUNCES: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub position: Vector,
+    // where the photon was travelling when it landed
+    pub direction: Vector,
+    pub power: Color,
+}
+
+// kd-tree over photons, stored in place: the middle of every range splits it along its axis
+#[derive(Debug, Clone, Default)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<u8>,
+}
+
+impl PhotonMap {
+    pub fn new(photons: Vec<Photon>) -> PhotonMap {
+        let mut map = PhotonMap { axes: vec![0; photons.len()], photons };
+        let len = map.photons.len();
+        map.build(0, len);
+        map
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    fn build(&mut self, start: usize, end: usize) {
+        if end - start <= 1 {
+            return;
+        }
+        let (mut min, mut max) = (Vector::from_num(f32::INFINITY), Vector::from_num(f32::NEG_INFINITY));
+        for photon in self.photons[start..end].iter() {
+            let p = photon.position;
+            min = Vector::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let middle = (start + end) / 2;
+        self.photons[start..end].select_nth_unstable_by(middle - start, |a, b| coordinate(&a.position, axis).total_cmp(&coordinate(&b.position, axis)));
+        self.axes[middle] = axis as u8;
+        self.build(start, middle);
+        self.build(middle + 1, end);
+    }
+
+    // calls f for every photon closer to point than radius
+    pub fn gather(&self, point: &Vector, radius: f32, f: &mut impl FnMut(&Photon)) {
+        self.gather_range(0, self.photons.len(), point, radius, f);
+    }
+
+    fn gather_range(&self, start: usize, end: usize, point: &Vector, radius: f32, f: &mut impl FnMut(&Photon)) {
+        if start >= end {
+            return;
+        }
+        let middle = (start + end) / 2;
+        let photon = &self.photons[middle];
+        if photon.position.distance(point) <= radius {
+            f(photon);
+        }
+        if end - start == 1 {
+            return;
+        }
+        let axis = self.axes[middle] as usize;
+        let offset = coordinate(point, axis) - coordinate(&photon.position, axis);
+        if offset <= radius {
+            self.gather_range(start, middle, point, radius, f);
+        }
+        if offset >= -radius {
+            self.gather_range(middle + 1, end, point, radius, f);
+        }
+    }
+}
+
+// light focused by mirrors and glass onto diffuse surfaces, which shadow rays can't find.
+// photons are shot from the lights and emitters towards the specular objects, traced through
+// them and stored where they land on something diffuse. with more than one pass, every pass
+// has its own photons and a smaller radius, as in "Progressive Photon Mapping: A Probabilistic
+// Approach" (Knaus, Zwicker 2011), and the estimates are averaged. the sun doesn't shoot photons
+#[derive(Debug, Clone)]
+pub struct CausticPhotons {
+    // per pass
+    pub photons: usize,
+    // gather radius of the first pass
+    pub radius: f32,
+    pub passes: usize,
+    // how much of the radius every pass keeps, in 0..1. smaller shrinks faster
+    pub alpha: f32,
+    maps: Vec<(PhotonMap, f32)>,
+}
+
+// where photons come from, with the projection map cells that see specular objects
+struct Source {
+    kind: SourceKind,
+    cells: Vec<usize>,
+    // expected power of its photons, photons are split between sources by it
+    weight: f32,
+}
+
+enum SourceKind {
+    Light(usize),
+    Emitter(usize),
+}
+
+impl CausticPhotons {
+    pub fn new(photons: usize, radius: f32) -> CausticPhotons {
+        CausticPhotons { photons, radius, passes: 1, alpha: 0.7, maps: Vec::new() }
+    }
+
+    pub fn with_progressive(mut self, passes: usize, alpha: f32) -> CausticPhotons {
+        self.passes = passes.max(1);
+        self.alpha = alpha.clamp(0.01, 1.0);
+        self
+    }
+
+    pub fn stored_photons(&self) -> usize {
+        self.maps.iter().map(|(map, _)| map.len()).sum()
+    }
+
+    // shoots the photons of all passes, the camera calls this before rendering
+    pub fn trace(&mut self, scene: &Scene, materials: &[Material], stats: &mut RenderStats) {
+        self.maps.clear();
+        let sources = projection_maps(scene, materials, stats);
+        let total_weight: f32 = sources.iter().map(|source| source.weight).sum();
+        if total_weight <= 0.0 {
+            return;
+        }
+        let mut radius = self.radius;
+        for pass in 0..self.passes {
+            let mut photons = Vec::new();
+            for _ in 0..self.photons {
+                // pick a source by weight
+                let mut pick = rand::random::<f32>() * total_weight;
+                let source = sources.iter().find(|source| {
+                    pick -= source.weight;
+                    pick <= 0.0
+                }).unwrap_or(&sources[sources.len() - 1]);
+                let probability = source.weight / total_weight;
+                if let Some(photon) = shoot_photon(scene, materials, source, probability * self.photons as f32, stats) {
+                    photons.push(photon);
+                }
+            }
+            self.maps.push((PhotonMap::new(photons), radius));
+            radius *= ((pass as f32 + self.alpha) / (pass as f32 + 1.0)).sqrt();
+        }
+    }
+
+    // calls f with the direction to the light and the irradiance of every photon around the
+    // point that arrived on the side normal faces
+    pub fn gather(&self, point: &Vector, normal: &Vector, mut f: impl FnMut(&Vector, Color)) {
+        for (map, radius) in self.maps.iter() {
+            let scale = 1.0 / (PI * radius * radius * self.maps.len() as f32);
+            map.gather(point, *radius, &mut |photon| {
+                if photon.direction.dot(normal) < 0.0 {
+                    f(&-photon.direction, photon.power * scale);
+                }
+            });
+        }
+    }
+}
+
+fn coordinate(v: &Vector, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn is_specular(material: &Material) -> bool {
+    matches!(material.material_type, MaterialType::Reflective | MaterialType::Refractive)
+}
+
+// closest primitive along the ray: its index, the point, normal and distance
+fn first_hit(scene: &Scene, ray: &Line, stats: &mut RenderStats) -> Option<(usize, Vector, Vector, f32)> {
+    stats.intersection_tests += scene.primitives.len() as u64;
+    let mut closest: Option<(usize, Vector, Vector, f32)> = None;
+    for (i, primitive) in scene.primitives.iter().enumerate() {
+        let hit = primitive.intersect(ray);
+        if hit.is_none() {
+            continue;
+        }
+        let point = hit.unwrap().0;
+        let to_hit = point - ray.point;
+        if to_hit.dot(&ray.direction) < 0.0 {
+            continue;
+        }
+        let distance = to_hit.length();
+        if closest.as_ref().is_none_or(|closest| distance < closest.3) {
+            if let Some(normal) = hit.normal {
+                closest = Some((i, point, normal, distance));
+            }
+        }
+    }
+    closest
+}
+
+// direction in a cell of the projection map, u and v place it inside the cell
+fn cell_direction(cell: usize, u: f32, v: f32) -> Vector {
+    let (band, slice) = (cell / PHI_CELLS, cell % PHI_CELLS);
+    let z = -1.0 + 2.0 * (band as f32 + u) / Z_CELLS as f32;
+    let phi = 2.0 * PI * (slice as f32 + v) / PHI_CELLS as f32;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vector::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn cell_solid_angle() -> f32 {
+    4.0 * PI / (Z_CELLS * PHI_CELLS) as f32
+}
+
+fn luminance(color: &Color) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+// "Global Illumination using Photon Maps" (Jensen 1996): cells of directions around every
+// source, marked where probe rays hit a specular object first. photons are only shot into
+// those and their neighbours
+fn projection_maps(scene: &Scene, materials: &[Material], stats: &mut RenderStats) -> Vec<Source> {
+    let mut specular_from = |origin: &Vector, direction: &Vector| {
+        first_hit(scene, &Line::new(*origin + *direction * 0.01, *direction), stats)
+            .is_some_and(|(primitive, ..)| is_specular(&materials[scene.material_index[primitive]]))
+    };
+    let mut sources = Vec::new();
+    for (i, light) in scene.lights.iter().enumerate() {
+        if light.light_type == LightType::Ambient {
+            continue;
+        }
+        let cells = marked_cells(|direction| light.spot_factor(&(light.position + *direction)) > 0.0 && specular_from(&light.position, direction));
+        let intensity = luminance(&light.emission(None)) * PI * light.profile.as_ref().map_or(1.0, |profile| profile.max_candela());
+        let weight = intensity * cells.len() as f32 * cell_solid_angle();
+        sources.push(Source { kind: SourceKind::Light(i), cells, weight });
+    }
+    for (i, emitter) in scene.emitters.iter().enumerate() {
+        let primitive = &scene.primitives[emitter.primitive];
+        let points: Vec<(Vector, Vector)> = (0..EMITTER_PROBE_POINTS).filter_map(|_| primitive.sample_surface(rand::random::<f32>(), rand::random::<f32>())).collect();
+        let cells = marked_cells(|direction| points.iter().any(|(point, normal)| normal.dot(direction) > 0.0 && specular_from(point, direction)));
+        let weight = luminance(&emitter.radiance) * emitter.area * cells.len() as f32 * cell_solid_angle();
+        sources.push(Source { kind: SourceKind::Emitter(i), cells, weight });
+    }
+    sources.retain(|source| source.weight > 0.0 && !source.cells.is_empty());
+    sources
+}
+
+// cells where probe hits for some direction, grown by one cell on every side
+fn marked_cells(mut probe: impl FnMut(&Vector) -> bool) -> Vec<usize> {
+    let count = Z_CELLS * PHI_CELLS;
+    let hit: Vec<bool> = (0..count)
+        .map(|cell| (0..PROBES_PER_CELL).any(|_| probe(&cell_direction(cell, rand::random::<f32>(), rand::random::<f32>()))))
+        .collect();
+    (0..count)
+        .filter(|cell| {
+            let (band, slice) = ((cell / PHI_CELLS) as i32, (cell % PHI_CELLS) as i32);
+            (-1..=1).any(|db: i32| {
+                (-1..=1).any(|ds: i32| {
+                    let b = band + db;
+                    let s = (slice + ds).rem_euclid(PHI_CELLS as i32);
+                    b >= 0 && b < Z_CELLS as i32 && hit[b as usize * PHI_CELLS + s as usize]
+                })
+            })
+        })
+        .collect()
+}
+
+// one photon from the source into one of its cells, traced through specular objects. count
+// is how many photons the source shoots on average, none if it doesn't land after a specular
+// bounce
+fn shoot_photon(scene: &Scene, materials: &[Material], source: &Source, count: f32, stats: &mut RenderStats) -> Option<Photon> {
+    let cell = source.cells[((rand::random::<f32>() * source.cells.len() as f32) as usize).min(source.cells.len() - 1)];
+    let direction = cell_direction(cell, rand::random::<f32>(), rand::random::<f32>());
+    let solid_angle = source.cells.len() as f32 * cell_solid_angle();
+    let (origin, power, light) = match source.kind {
+        SourceKind::Light(i) => {
+            // the intensity of a light is what makes a white surface reflect its color
+            let light = &scene.lights[i];
+            let intensity = light.emission(None) * (PI * light.spot_factor(&(light.position + direction)));
+            (light.position, intensity * (solid_angle / count), Some(light))
+        }
+        SourceKind::Emitter(i) => {
+            let emitter = &scene.emitters[i];
+            let (point, normal) = scene.primitives[emitter.primitive].sample_surface(rand::random::<f32>(), rand::random::<f32>())?;
+            let cos = normal.dot(&direction);
+            if cos <= 0.0 {
+                return None;
+            }
+            (point, emitter.radiance * (cos * emitter.area * solid_angle / count), None)
+        }
+    };
+    trace_photon(scene, materials, Line::new(origin + direction * 0.01, direction), power, light, stats)
+}
+
+fn trace_photon(scene: &Scene, materials: &[Material], mut ray: Line, mut power: Color, light: Option<&Light>, stats: &mut RenderStats) -> Option<Photon> {
+    let mut travelled = 0.0;
+    for bounce in 0..MAX_PHOTON_BOUNCES {
+        let (primitive, point, normal, distance) = first_hit(scene, &ray, stats)?;
+        travelled += distance;
+        let material = &materials[scene.material_index[primitive]];
+        let direction = match material.material_type {
+            MaterialType::Reflective => ray.direction.reflect(&normal),
+            MaterialType::Refractive => {
+                if ray.direction.dot(&normal) > 0.0 {
+                    power *= material.absorption(distance, None);
+                }
+                let refracted = ray.direction.refract(&normal, material.refractive_index);
+                if refracted.length_squared() == 0.0 { ray.direction.reflect(&normal) } else { refracted }
+            }
+            _ if bounce == 0 => return None,
+            _ => {
+                // photons spread out with the square of the distance, lights can fall off differently
+                let falloff = light.map_or(1.0, |light| {
+                    let (c, l, q) = light.attenuation;
+                    travelled * travelled / (c + l * travelled + q * travelled * travelled)
+                });
+                return Some(Photon { position: point, direction: ray.direction, power: power * falloff });
+            }
+        };
+        ray = Line::new(point + direction * 0.1, direction._normalize());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Surface;
+
+    #[test]
+    fn photon_map_test() {
+        let photons: Vec<Photon> = (0..500)
+            .map(|_| Photon { position: Vector::random(-10.0, 10.0), direction: Vector::new(0.0, -1.0, 0.0), power: Color::white() })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        // the tree finds the same photons as looking at all of them
+        for point in [Vector::new(0.0, 0.0, 0.0), Vector::new(5.0, -3.0, 8.0), Vector::new(20.0, 0.0, 0.0)] {
+            let mut found = 0;
+            map.gather(&point, 4.0, &mut |_| found += 1);
+            let expected = photons.iter().filter(|photon| photon.position.distance(&point) <= 4.0).count();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn caustic_test() {
+        // a light above a mirror floor lights the ceiling through its mirror image
+        let mut scene = Scene::new();
+        let floor = Surface::new_vw(Vector::new(0.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0), None, None, Vector::new(0.0, 1.0, 0.0));
+        let ceiling = Surface::new_vw(Vector::new(0.0, 200.0, 0.0), Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0), None, None, Vector::new(0.0, -1.0, 0.0));
+        scene.add_primitive(Box::new(floor), 0);
+        scene.add_primitive(Box::new(ceiling), 1);
+        scene.add_light(Light::new_point(Vector::new(0.0, 100.0, 0.0), Color::white(), (0.0, 0.0, 1.0)));
+        let materials = vec![Material::new_reflective(Color::white(), 0.0, 1.0, 1.0), Material::new_phong(Color::white(), 0.0, 1.0)];
+
+        let mut caustics = CausticPhotons::new(400000, 30.0);
+        caustics.trace(&scene, &materials, &mut RenderStats::new());
+        assert!(caustics.stored_photons() > 10000);
+        let mut irradiance = Color::black();
+        caustics.gather(&Vector::new(0.0, 200.0, 0.0), &Vector::new(0.0, -1.0, 0.0), |l, e| {
+            assert!(l.y < 0.0);
+            irradiance += e;
+        });
+        // pi * color over the distance to the mirror image squared
+        let expected = PI / (300.0 * 300.0);
+        assert!((irradiance.g - expected).abs() < 0.15 * expected, "{} {}", irradiance.g, expected);
+        // nothing arrives on the other side
+        let mut behind = 0;
+        caustics.gather(&Vector::new(0.0, 200.0, 0.0), &Vector::new(0.0, 1.0, 0.0), |_, _| behind += 1);
+        assert_eq!(behind, 0);
+    }
+}
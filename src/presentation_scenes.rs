@@ -1,4 +1,6 @@
-use crate::{color::Color, geometry::{Sphere, Surface}, light::{Light, RectangleAreaLight}, material::Material, math::{as_radians, Vector}, scene::Scene, BLACK_MAT, BLUE_MAT, GLASS_MAT, GREEN_MAT, MIRROR_MAT, RED_MAT, WHITE_MAT};
+use std::sync::Arc;
+
+use crate::{color::Color, density_grid::DensityGrid, medium::{Medium, Volume}, geometry::{AxisAlignedBox, Cone, Csg, Cylinder, Disk, Instance, Mesh, Sdf, SdfPrimitive, Sphere, Surface, Torus, Triangle}, ies::IesProfile, light::{Light, RectangleAreaLight}, mipmap::TextureFilter, material::{Dispersion, Material, TextureChannel}, math::{as_radians, IntersectionPrimitive, Quaternion, Vector}, photon_map::CausticPhotons, scene::Scene, sky::Sky, shader::{MathOp, ShaderGraph, ShaderNode}, spectrum::{EmissionSpectrum, Illuminant}, texture::{Texture, TextureSpace}, BLACK_MAT, BLUE_MAT, GLASS_MAT, GREEN_MAT, MIRROR_MAT, RED_MAT, WHITE_MAT};
 use image::{io::Reader as ImageReader, ImageBuffer};
 
 pub fn shading_scene() -> Scene {
@@ -143,7 +145,9 @@ pub fn reflection_refraction_scene() -> Scene {
         6.0,
     );
     scene.add_lights(area_light.get_lights());
-    
+    // the glass sphere focuses the light onto the floor
+    scene.set_caustics(CausticPhotons::new(200000, 12.0));
+
     scene
 }
 
@@ -447,9 +451,977 @@ pub fn full_pbr_scene() -> (Scene, Vec<Material>) {
         9.0,
     );
     scene.add_lights(area_light.get_lights());
+    scene.set_caustics(CausticPhotons::new(100000, 40.0).with_progressive(4, 0.7));
 
     // let point = Light::new_point(Vector::new(0.0, 0.0, 500.0), Color::white(), (1.0, 0.000001, 0.000001));
     // scene.add_light(point);
 
     (scene, materials)
-}
\ No newline at end of file
+}
+
+pub fn instancing_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.6, 0.1), 0.9, 0.3, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.1, 0.4, 0.9), 0.0, 0.4, 1.3, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+
+    // a unit pyramid, stored once and placed five times
+    let top = Vector::new(0.0, 1.0, 0.0);
+    let corners = [
+        Vector::new(-1.0, 0.0, 1.0),
+        Vector::new(1.0, 0.0, 1.0),
+        Vector::new(1.0, 0.0, -1.0),
+        Vector::new(-1.0, 0.0, -1.0),
+    ];
+    let mut pyramid = Mesh::default();
+    for i in 0..4 {
+        pyramid.add_triangle(Triangle::new([corners[i], corners[(i + 1) % 4], top], Vector::from_num(1.0)));
+    }
+    let pyramid: Arc<dyn IntersectionPrimitive + Send + Sync> = Arc::new(pyramid);
+
+    for i in 0..5 {
+        let mut rotation = Quaternion::identity();
+        rotation.rotate(as_radians(i as f32 * 18.0), Vector::new(0.0, 1.0, 0.0));
+        let size = 60.0 + i as f32 * 15.0;
+        let instance = Instance::from_trs(
+            pyramid.clone(),
+            Vector::new(-400.0 + i as f32 * 200.0, -300.0, -700.0),
+            rotation,
+            Vector::new(size, size * 1.5, size),
+        ).unwrap();
+        scene.add_primitive(Box::new(instance), 1);
+    }
+
+    // one sphere squashed into ellipsoids
+    let sphere: Arc<dyn IntersectionPrimitive + Send + Sync> = Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0));
+    for i in 0..3 {
+        let mut rotation = Quaternion::identity();
+        rotation.rotate(as_radians(30.0 * i as f32), Vector::new(0.0, 0.0, 1.0));
+        let instance = Instance::from_trs(
+            sphere.clone(),
+            Vector::new(-250.0 + i as f32 * 250.0, 0.0, -600.0),
+            rotation,
+            Vector::new(100.0, 50.0, 50.0),
+        ).unwrap();
+        scene.add_primitive(Box::new(instance), 2);
+    }
+
+    let point = Light::new_point(Vector::new(0.0, 300.0, 0.0), Color::white(), (1.0, 0.0001, 0.000005));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+pub fn primitives_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.2, 0.1), 0.0, 0.3, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.1, 0.8, 0.2), 0.0, 0.4, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.1, 0.3, 0.9), 0.3, 0.3, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.7, 0.1), 0.9, 0.2, 1.3, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+
+    let disk = Disk::new(Vector::new(-450.0, -100.0, -800.0), Vector::new(0.3, 0.2, 1.0), 120.0);
+    scene.add_primitive(Box::new(disk), 1);
+
+    let cylinder = Cylinder::new(Vector::new(-200.0, -300.0, -700.0), Vector::new(0.0, 1.0, 0.0), 70.0, 220.0);
+    scene.add_primitive(Box::new(cylinder), 2);
+
+    let cone = Cone::new(Vector::new(50.0, -300.0, -700.0), Vector::new(0.0, 1.0, 0.0), 90.0, 250.0);
+    scene.add_primitive(Box::new(cone), 3);
+
+    let torus = Torus::new(Vector::new(300.0, -150.0, -700.0), Vector::new(0.0, 1.0, 1.0), 100.0, 35.0);
+    scene.add_primitive(Box::new(torus), 4);
+
+    // a box turned as a whole, which six separate Surfaces can't do
+    let cube: Arc<dyn IntersectionPrimitive + Send + Sync> = Arc::new(AxisAlignedBox::from_center_size(Vector::new(0.0, 0.0, 0.0), Vector::from_num(1.0)));
+    let mut rotation = Quaternion::identity();
+    rotation.rotate(as_radians(35.0), Vector::new(1.0, 1.0, 0.0)._normalize());
+    let cube = Instance::from_trs(cube, Vector::new(0.0, 150.0, -900.0), rotation, Vector::from_num(150.0)).unwrap();
+    scene.add_primitive(Box::new(cube), 1);
+
+    let point = Light::new_point(Vector::new(-200.0, 300.0, 0.0), Color::white(), (1.0, 0.0001, 0.000005));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+pub fn csg_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_refractive(Color::white(), 1.5),
+        Material::new_pbr(Color::new(0.9, 0.3, 0.1), 0.0, 0.4, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.7, 0.3), 1.0, 0.25, 1.3, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+
+    // biconvex lens, the overlap of two spheres
+    let lens = Csg::intersection(
+        Box::new(Sphere::new(Vector::new(-250.0, -150.0, -800.0), 160.0)),
+        Box::new(Sphere::new(Vector::new(-450.0, -150.0, -800.0), 160.0)),
+    );
+    scene.add_primitive(Box::new(lens), 1);
+
+    // block drilled through from the front and from the top
+    let block = AxisAlignedBox::from_center_size(Vector::new(0.0, -200.0, -800.0), Vector::from_num(200.0));
+    let hole_z = Cylinder::new(Vector::new(0.0, -200.0, -950.0), Vector::new(0.0, 0.0, 1.0), 60.0, 300.0);
+    let hole_y = Cylinder::new(Vector::new(0.0, -350.0, -800.0), Vector::new(0.0, 1.0, 0.0), 60.0, 300.0);
+    let drilled = Csg::difference(Box::new(block), Box::new(Csg::union(Box::new(hole_z), Box::new(hole_y))));
+    scene.add_primitive(Box::new(drilled), 2);
+
+    // bowl, the lower half of a hollow sphere
+    let shell = Csg::difference(
+        Box::new(Sphere::new(Vector::new(350.0, -150.0, -800.0), 150.0)),
+        Box::new(Sphere::new(Vector::new(350.0, -150.0, -800.0), 130.0)),
+    );
+    let lower_half = AxisAlignedBox::new(Vector::new(150.0, -350.0, -1000.0), Vector::new(550.0, -150.0, -600.0));
+    let bowl = Csg::intersection(Box::new(shell), Box::new(lower_half));
+    scene.add_primitive(Box::new(bowl), 3);
+
+    let point = Light::new_point(Vector::new(-200.0, 300.0, 0.0), Color::white(), (1.0, 0.0001, 0.000005));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+pub fn sdf_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.2, 0.5, 0.9), 0.0, 0.3, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.3, 0.1), 0.0, 0.5, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.7, 0.3), 0.0, 0.4, 1.3, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+
+    // rounded box melted into a torus
+    let rounded_box = Sdf::RoundedBox { center: Vector::new(-380.0, -220.0, -800.0), half_size: Vector::from_num(80.0), radius: 20.0 };
+    let ring = Sdf::Torus { center: Vector::new(-380.0, -140.0, -800.0), major_radius: 110.0, minor_radius: 25.0 };
+    let blob = SdfPrimitive::new(
+        Sdf::smooth_union(rounded_box, ring, 40.0),
+        AxisAlignedBox::from_center_size(Vector::new(-380.0, -180.0, -800.0), Vector::new(300.0, 250.0, 300.0)),
+    );
+    scene.add_primitive(Box::new(blob), 1);
+
+    // sphere with a noisy surface, the noise makes the distance inexact so the steps are shortened
+    let rock = Sdf::displace(Sdf::Sphere { center: Vector::new(0.0, -180.0, -850.0), radius: 110.0 }, 15.0, 1.0 / 40.0);
+    let rock = SdfPrimitive::new(rock, AxisAlignedBox::from_center_size(Vector::new(0.0, -180.0, -850.0), Vector::from_num(300.0)))
+        .with_step_scale(0.5)
+        .with_max_steps(512)
+        .with_hit_epsilon(0.01);
+    scene.add_primitive(Box::new(rock), 2);
+
+    let bulb = Sdf::Mandelbulb { center: Vector::new(380.0, -160.0, -800.0), scale: 120.0, power: 8.0, iterations: 12 };
+    let bulb = SdfPrimitive::new(bulb, AxisAlignedBox::from_center_size(Vector::new(380.0, -160.0, -800.0), Vector::from_num(300.0)))
+        .with_hit_epsilon(0.05);
+    scene.add_primitive(Box::new(bulb), 3);
+
+    let point = Light::new_point(Vector::new(-200.0, 300.0, 0.0), Color::white(), (1.0, 0.0001, 0.000005));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+pub fn fog_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.3, 0.1), 0.0, 0.4, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.2, 0.5, 0.9), 0.0, 0.3, 1.3, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+
+    // spheres floating in the cone of the spot light, their shadows cut through the beam
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-120.0, 60.0, -800.0), 50.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(80.0, -20.0, -750.0), 40.0)), 2);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, -220.0, -850.0), 80.0)), 1);
+
+    // thin haze everywhere and a denser bank of fog close to the floor
+    scene.set_global_medium(Medium::new(Color::new(0.0001, 0.0001, 0.0001), Color::new(0.0006, 0.0006, 0.0006), 0.5));
+    scene.add_volume(
+        AxisAlignedBox::new(Vector::new(-2000.0, -300.0, -2000.0), Vector::new(2000.0, -220.0, 0.0)),
+        Medium::new(Color::new(0.0005, 0.0005, 0.0005), Color::new(0.003, 0.003, 0.004), 0.2),
+    );
+
+    let spot = Light::new_spot(
+        Vector::new(-300.0, 400.0, -700.0),
+        Vector::new(0.45, -1.0, -0.15),
+        Color::new(100.0, 90.0, 70.0),
+        (1.0, 0.0, 0.000002),
+        as_radians(12.0),
+        as_radians(18.0),
+    );
+    scene.add_light(spot);
+    let point = Light::new_point(Vector::new(300.0, 200.0, 0.0), Color::new(2.0, 2.5, 4.0), (1.0, 0.0001, 0.000005));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+pub fn smoke_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_refractive(Color::white(), 1.5),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+
+    // cloud from a procedural noise grid
+    let cloud = Arc::new(DensityGrid::cloud(48, 4.0, 4));
+    let cloud_medium = Medium::new(Color::new(0.001, 0.001, 0.001), Color::new(0.03, 0.03, 0.03), 0.4);
+    let bounds = AxisAlignedBox::from_center_size(Vector::new(-220.0, -50.0, -900.0), Vector::new(420.0, 300.0, 320.0));
+    scene.add_volume_object(Volume::new(bounds, cloud_medium).with_density(cloud));
+
+    // fire, a dark smoky flame whose emission follows a temperature grid hottest at the bottom
+    let flame = Arc::new(DensityGrid::from_fn((32, 48, 32), |p| {
+        let radius = (Vector::new(p.x - 0.5, 0.0, p.z - 0.5)).length() * 2.0;
+        let noise = crate::math::noise::fbm(&(p * 6.0), 3, 2.0, 0.5);
+        ((1.0 - radius - p.y * 0.7) + noise * 0.5).max(0.0)
+    }));
+    let temperature = Arc::new(DensityGrid::from_fn((32, 48, 32), |p| {
+        let radius = (Vector::new(p.x - 0.5, 0.0, p.z - 0.5)).length() * 2.0;
+        ((1.0 - radius) * (1.0 - p.y)).max(0.0).powi(2)
+    }));
+    let fire_medium = Medium::new(Color::new(0.02, 0.02, 0.02), Color::new(0.005, 0.005, 0.005), 0.0)
+        .with_emission(Color::new(0.04, 0.012, 0.002));
+    let bounds = AxisAlignedBox::new(Vector::new(150.0, -300.0, -850.0), Vector::new(350.0, 0.0, -650.0));
+    scene.add_volume_object(Volume::new(bounds, fire_medium).with_density(flame).with_emission_grid(temperature));
+
+    // glass orb filled with a faintly glowing gas, the medium ends at the glass
+    let orb_center = Vector::new(-40.0, -210.0, -650.0);
+    let gas = Medium::new(Color::new(0.004, 0.003, 0.002), Color::new(0.002, 0.002, 0.002), 0.0)
+        .with_emission(Color::new(0.0002, 0.0006, 0.0015));
+    let orb_bounds = AxisAlignedBox::from_center_size(orb_center, Vector::from_num(180.0));
+    scene.add_primitive_with_volume(Box::new(Sphere::new(orb_center, 90.0)), 1, Volume::new(orb_bounds, gas));
+
+    let point = Light::new_point(Vector::new(-200.0, 400.0, -300.0), Color::new(15.0, 15.0, 15.0), (1.0, 0.0001, 0.000001));
+    scene.add_light(point);
+    scene.add_light(Light::new_ambient(Color::new(0.1, 0.12, 0.15), 1.0));
+
+    (scene, materials)
+}
+
+pub fn colored_glass_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.1, 0.6, 1.3, 0.0, 0.0),
+        Material::new_refractive(Color::white(), 1.5).with_absorption(Color::new(0.4, 0.8, 0.5), 1.0 / 60.0),
+        Material::new_refractive(Color::white(), 1.5).with_absorption(Color::new(0.9, 0.5, 0.2), 1.0 / 60.0),
+        Material::new_refractive(Color::white(), 1.5).with_absorption(Color::new(0.3, 0.5, 0.9), 1.0 / 60.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    let back_wall = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1400.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(back_wall), 0);
+
+    // the same glass gets more saturated the thicker it is
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-380.0, -220.0, -800.0), 80.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-80.0, -140.0, -850.0), 160.0)), 2);
+    scene.add_primitive(Box::new(AxisAlignedBox::new(Vector::new(180.0, -300.0, -900.0), Vector::new(480.0, -20.0, -860.0))), 3);
+
+    let point = Light::new_point(Vector::new(-200.0, 400.0, 0.0), Color::white(), (1.0, 0.0001, 0.000001));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+pub fn dispersion_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::new(0.05, 0.05, 0.05), 0.0, 0.8, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::white(), 0.0, 0.5, 1.3, 0.0, 0.0),
+        Material::new_refractive(Color::white(), 1.7).with_dispersion(Dispersion::dense_flint()),
+        Material::new_refractive(Color::white(), 2.4).with_dispersion(Dispersion::diamond()),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    let back_wall = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1400.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(back_wall), 0);
+
+    // white bars on the dark wall, their edges get colored fringes behind the glass
+    for i in 0..12 {
+        let x = -880.0 + i as f32 * 160.0;
+        let bar = AxisAlignedBox::new(Vector::new(x, -300.0, -1400.0), Vector::new(x + 50.0, 600.0, -1380.0));
+        scene.add_primitive(Box::new(bar), 1);
+    }
+
+    // triangular flint glass prism across the view, apex down
+    let (left, right) = (-450.0, 50.0);
+    let corners = [(-60.0, -650.0), (-60.0, -850.0), (-233.0, -750.0)];
+    let corner = |i: usize, x: f32| Vector::new(x, corners[i].0, corners[i].1);
+    let mut prism = Mesh::default();
+    prism.add_triangle(Triangle::new([corner(0, left), corner(2, left), corner(1, left)], Vector::from_num(1.0)));
+    prism.add_triangle(Triangle::new([corner(0, right), corner(1, right), corner(2, right)], Vector::from_num(1.0)));
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        prism.add_triangle(Triangle::new([corner(i, left), corner(j, left), corner(j, right)], Vector::from_num(1.0)));
+        prism.add_triangle(Triangle::new([corner(i, left), corner(j, right), corner(i, right)], Vector::from_num(1.0)));
+    }
+    scene.add_primitive(Box::new(prism), 2);
+
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(250.0, -170.0, -800.0), 130.0)), 3);
+
+    let point = Light::new_point(Vector::new(0.0, 400.0, -300.0), Color::new(3.0, 3.0, 3.0), (1.0, 0.0001, 0.000001));
+    scene.add_light(point);
+
+    (scene, materials)
+}
+
+// render with camera.spectral = true. three spot lights with different emission spectra over
+// the same colored spheres: incandescent, daylight and a tri-band fluorescent
+pub fn spectral_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let materials = vec![
+        Material::new_pbr(Color::new(0.8, 0.8, 0.8), 0.0, 0.9, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.8, 0.1, 0.1), 0.0, 0.4, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.1, 0.7, 0.2), 0.0, 0.4, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.1, 0.2, 0.8), 0.0, 0.4, 1.3, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    let back_wall = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1200.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(back_wall), 0);
+
+    let illuminants = [Illuminant::A, Illuminant::D65, Illuminant::F11];
+    for (i, illuminant) in illuminants.iter().enumerate() {
+        let x = -500.0 + i as f32 * 500.0;
+        for (j, material) in [1, 2, 3].iter().enumerate() {
+            let center = Vector::new(x - 130.0 + j as f32 * 130.0, -240.0, -900.0);
+            scene.add_primitive(Box::new(Sphere::new(center, 60.0)), *material);
+        }
+        let spot = Light::new_spot(
+            Vector::new(x, 400.0, -700.0),
+            Vector::new(0.0, -1.0, -0.4),
+            Color::new(4.0, 4.0, 4.0),
+            (1.0, 0.0001, 0.000002),
+            as_radians(18.0),
+            as_radians(26.0),
+        ).with_spectrum(EmissionSpectrum::new(*illuminant));
+        scene.add_light(spot);
+    }
+
+    (scene, materials)
+}
+
+// lit only by emissive primitives: a softbox above, a glowing orb and a zigzag neon tube on the wall
+pub fn emissive_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    scene.emitter_samples = 8;
+    let materials = vec![
+        Material::new_pbr(Color::new(0.6, 0.6, 0.6), 0.0, 0.8, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.8, 0.3, 0.1), 0.0, 0.3, 1.3, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.9, 0.9, 0.9), 0.9, 0.2, 1.3, 0.0, 0.0),
+        // emission strengths are radiance, a white diffuse surface reflects 1 / pi of it per
+        // unit of solid angle the emitter covers
+        Material::new_phong(Color::white(), 0.0, 1.0).with_emission(Color::new(1.0, 0.95, 0.9), 9.0),
+        Material::new_phong(Color::white(), 0.0, 1.0).with_emission(Color::new(0.3, 0.6, 1.0), 9.0),
+        Material::new_phong(Color::white(), 0.0, 1.0).with_emission(Color::new(1.0, 0.1, 0.5), 18.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    let back_wall = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1200.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(back_wall), 0);
+
+    // softbox facing down
+    let softbox = Surface::new_vw(
+        Vector::new(-250.0, 350.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        Some((-150.0, 150.0)),
+        Some((-100.0, 100.0)),
+        Vector::new(0.0, -1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(softbox), 3);
+
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-250.0, -200.0, -800.0), 100.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(150.0, -220.0, -700.0), 80.0)), 2);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(380.0, -250.0, -850.0), 50.0)), 4);
+
+    // neon zigzag in front of the back wall, a flat ribbon facing the camera
+    let mut neon = Mesh::default();
+    let half_width = 6.0;
+    let points = [(-100.0, 150.0), (0.0, 250.0), (100.0, 150.0), (200.0, 250.0), (300.0, 150.0)];
+    for pair in points.windows(2) {
+        let (a, b) = (Vector::new(pair[0].0, pair[0].1, -1150.0), Vector::new(pair[1].0, pair[1].1, -1150.0));
+        let side = (b - a).cross(&Vector::new(0.0, 0.0, 1.0))._normalize() * half_width;
+        neon.add_triangle(Triangle::new([a - side, b - side, b + side], Vector::from_num(1.0)));
+        neon.add_triangle(Triangle::new([a - side, b + side, a + side], Vector::from_num(1.0)));
+    }
+    scene.add_primitive(Box::new(neon), 5);
+
+    (scene, materials)
+}
+
+// principled bsdf lobes, left to right: plain plastic, specular tint, sheen, clearcoat,
+// subsurface, transmission. the back row is brushed metal turning its anisotropy
+pub fn principled_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    // the brushed metals show their anisotropy in what they reflect
+    scene.glossy_reflections = true;
+    let base = Color::new(0.7, 0.15, 0.1);
+    let mut materials = vec![
+        Material::new_pbr(Color::new(0.5, 0.5, 0.5), 0.0, 0.8, 1.5, 0.0, 0.0),
+        Material::new_pbr(base, 0.0, 0.4, 1.5, 0.0, 0.0),
+        Material::new_pbr(base, 0.0, 0.4, 1.5, 0.0, 0.0).with_specular_tint(1.0),
+        Material::new_pbr(base, 0.0, 0.9, 1.5, 0.0, 0.0).with_sheen(1.0, 0.3),
+        Material::new_pbr(base, 0.0, 0.6, 1.5, 0.0, 0.0).with_clearcoat(1.0, 0.05),
+        Material::new_pbr(Color::new(0.9, 0.6, 0.5), 0.0, 0.5, 1.4, 0.0, 0.0).with_subsurface(1.0),
+        Material::new_pbr(Color::new(0.9, 0.95, 1.0), 0.0, 0.05, 1.5, 0.0, 0.0).with_transmission(1.0),
+    ];
+    for i in 0..4 {
+        materials.push(Material::new_pbr(Color::new(0.9, 0.85, 0.8), 1.0, 0.4, 1.5, 0.9, as_radians(45.0 * i as f32)));
+    }
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -300.0, 0.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    let back_wall = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1400.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(back_wall), 0);
+
+    for i in 0..6 {
+        let center = Vector::new(-500.0 + i as f32 * 200.0, -220.0, -700.0);
+        scene.add_primitive(Box::new(Sphere::new(center, 80.0)), i + 1);
+    }
+    for i in 0..4 {
+        let center = Vector::new(-330.0 + i as f32 * 220.0, -200.0, -1050.0);
+        scene.add_primitive(Box::new(Sphere::new(center, 100.0)), i + 7);
+    }
+
+    scene.add_light(Light::new_point(Vector::new(-300.0, 300.0, -200.0), Color::new(1.5, 1.5, 1.5), (1.0, 0.0001, 0.000001)));
+    scene.add_light(Light::new_point(Vector::new(500.0, 100.0, -500.0), Color::new(0.6, 0.6, 0.7), (1.0, 0.0001, 0.000001)));
+
+    (scene, materials)
+}
+
+pub fn surface_detail_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let size = 256;
+
+    // tiles with mortar grooves and a little roughness, as a height field
+    let tiles = ImageBuffer::from_fn(size, size, |x, y| {
+        let (u, v) = (x as f32 / size as f32 * 4.0, y as f32 / size as f32 * 4.0);
+        let groove = (u.fract().min(1.0 - u.fract())).min(v.fract().min(1.0 - v.fract()));
+        let edge = (groove / 0.04).min(1.0);
+        let noise = crate::math::noise::fbm(&Vector::new(u * 8.0, v * 8.0, 0.0), 3, 2.0, 0.5);
+        image::Rgb([((edge * 0.85 + noise * 0.1).clamp(0.0, 1.0) * 255.0) as u8; 3])
+    });
+    // hammered metal, a normal map from the slopes of noise
+    let height = |u: f32, v: f32| crate::math::noise::fbm(&Vector::new(u * 24.0, v * 12.0, 0.0), 2, 2.0, 0.5);
+    let hammered = ImageBuffer::from_fn(size, size, |x, y| {
+        let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+        let step = 1.0 / size as f32;
+        let du = (height(u + step, v) - height(u, v)) * 15.0;
+        let dv = (height(u, v + step) - height(u, v)) * 15.0;
+        let normal = Vector::new(-du, -dv, 1.0)._normalize();
+        image::Rgb([((normal.x * 0.5 + 0.5) * 255.0) as u8, ((normal.y * 0.5 + 0.5) * 255.0) as u8, ((normal.z * 0.5 + 0.5) * 255.0) as u8])
+    });
+    let stripes = ImageBuffer::from_fn(size, size, |_, y| image::Rgb([if (y / 8) % 2 == 0 { 255 } else { 0 }; 3]));
+
+    let materials = vec![
+        Material::new_pbr(Color::new(0.6, 0.55, 0.5), 0.0, 0.7, 1.5, 0.0, 0.0).with_bump_map(tiles, 3.0),
+        Material::new_phong(Color::new(0.2, 0.4, 0.8), 0.6, 40.0).with_normal_map(hammered.clone()),
+        Material::new_pbr(Color::new(0.95, 0.8, 0.5), 1.0, 0.3, 1.5, 0.0, 0.0).with_normal_map(hammered),
+        Material::new_reflective(Color::white(), 0.0, 0.0, 3.0).with_bump_map(stripes, 1.5),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        Some((-800.0, 800.0)),
+        Some((-800.0, 800.0)),
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-300.0, -80.0, -800.0), 120.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, -80.0, -800.0), 120.0)), 2);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(300.0, -80.0, -800.0), 120.0)), 3);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.05));
+    // low light, the shadow terminator shows on the spheres
+    scene.add_light(Light::new_point(Vector::new(-900.0, 100.0, -500.0), Color::new(1.4, 1.3, 1.2), (1.0, 0.0001, 0.0000005)));
+
+    (scene, materials)
+}
+
+pub fn procedural_textures_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let pbr = |color: Color, roughness: f32| Material::new_pbr(color, 0.0, roughness, 1.5, 0.0, 0.0);
+
+    let checker = Texture::checker(Texture::Constant(Color::new(0.8, 0.8, 0.8)), Texture::Constant(Color::new(0.2, 0.2, 0.25)));
+    let marble = Texture::Marble { octaves: 5, distortion: 4.0 }.scaled(0.02).in_space(TextureSpace::World).ramp(Color::new(0.25, 0.25, 0.3), Color::new(0.95, 0.93, 0.9));
+    let wood = Texture::Wood { rings: 12.0, distortion: 0.4 }.scaled(0.01).in_space(TextureSpace::World).ramp(Color::new(0.35, 0.18, 0.07), Color::new(0.7, 0.45, 0.2));
+    // rust in the creases of worley cells
+    let rust = Texture::mix(
+        Texture::Constant(Color::new(0.9, 0.9, 0.9)),
+        Texture::Constant(Color::new(0.45, 0.2, 0.08)),
+        Texture::Worley.scaled(8.0),
+    );
+    let rust_roughness = Texture::Turbulence { octaves: 4 }.scaled(10.0);
+    let rust_metallic = Texture::Worley.scaled(8.0).ramp(Color::white(), Color::black());
+    let materials = vec![
+        pbr(Color::white(), 0.8).with_texture(TextureChannel::BaseColor, checker.scaled(8.0)),
+        pbr(Color::white(), 0.2).with_texture(TextureChannel::BaseColor, marble),
+        pbr(Color::white(), 0.5).with_texture(TextureChannel::BaseColor, wood),
+        pbr(Color::white(), 0.5)
+            .with_texture(TextureChannel::BaseColor, rust)
+            .with_texture(TextureChannel::Roughness, rust_roughness)
+            .with_texture(TextureChannel::Metallic, rust_metallic),
+        pbr(Color::white(), 0.6).with_texture(TextureChannel::BaseColor, Texture::Voronoi.scaled(10.0)),
+        pbr(Color::white(), 0.6).with_texture(TextureChannel::BaseColor, Texture::UvGrid { cells: 8.0, line_width: 0.04 }),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        Some((-800.0, 800.0)),
+        Some((-800.0, 800.0)),
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    for i in 0..5 {
+        let center = Vector::new(-440.0 + i as f32 * 220.0, -100.0, -800.0);
+        scene.add_primitive(Box::new(Sphere::new(center, 100.0)), i + 1);
+    }
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.05));
+    scene.add_light(Light::new_point(Vector::new(-300.0, 400.0, -300.0), Color::new(1.3, 1.3, 1.3), (1.0, 0.0001, 0.000001)));
+
+    (scene, materials)
+}
+
+pub fn shader_graph_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+
+    // floor: checker warped by noise, glossier the further away it is
+    let mut floor_shader = ShaderGraph::new();
+    let position = floor_shader.add(ShaderNode::Position);
+    let frequency = floor_shader.value(0.01);
+    let point = floor_shader.math(MathOp::Multiply, position, frequency);
+    let warp = floor_shader.add(ShaderNode::TextureAt { texture: Texture::Fbm { octaves: 3, lacunarity: 2.0, gain: 0.5 }, point });
+    let warped = floor_shader.math(MathOp::Add, point, warp);
+    let checker = Texture::checker(Texture::Constant(Color::new(0.8, 0.75, 0.7)), Texture::Constant(Color::new(0.15, 0.15, 0.2)));
+    let color = floor_shader.add(ShaderNode::TextureAt { texture: checker, point: warped });
+    floor_shader.connect(TextureChannel::BaseColor, color);
+    let distance = floor_shader.add(ShaderNode::HitDistance);
+    let range = floor_shader.value(2000.0);
+    let roughness = floor_shader.math(MathOp::Divide, distance, range);
+    let roughness = floor_shader.add(ShaderNode::Clamp { input: roughness, min: 0.1, max: 0.9 });
+    floor_shader.connect(TextureChannel::Roughness, roughness);
+
+    // sphere shaders written as text, see ShaderGraph::parse
+    let rim_shader = ShaderGraph::load("res/rim.shader").unwrap();
+    let band_shader = ShaderGraph::load("res/bands.shader").unwrap();
+
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.0, 0.5, 1.5, 0.0, 0.0).with_shader(floor_shader),
+        Material::new_pbr(Color::white(), 0.0, 0.3, 1.5, 0.0, 0.0).with_emission(Color::black(), 2.0).with_shader(rim_shader),
+        Material::new_pbr(Color::white(), 0.0, 0.3, 1.5, 0.0, 0.0).with_shader(band_shader),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 0);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-180.0, -60.0, -800.0), 140.0)), 1);
+    // an instance, the bands are in the space of the sphere
+    let sphere: Arc<dyn IntersectionPrimitive + Send + Sync> = Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 100.0));
+    let mut rotation = Quaternion::identity();
+    rotation.rotate(as_radians(30.0), Vector::new(0.0, 0.0, 1.0));
+    let tilted = Instance::from_trs(sphere, Vector::new(180.0, -60.0, -800.0), rotation, Vector::from_num(1.4)).unwrap();
+    scene.add_primitive(Box::new(tilted), 2);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.05));
+    scene.add_light(Light::new_point(Vector::new(-300.0, 400.0, -300.0), Color::new(1.3, 1.3, 1.3), (1.0, 0.0001, 0.000001)));
+
+    (scene, materials)
+}
+
+pub fn texture_filtering_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+
+    // fine stripes and text-like detail alias the most on a receding floor
+    let image = ImageBuffer::from_fn(512, 512, |x, y| {
+        let checker = ((x / 16) + (y / 16)) % 2 == 0;
+        let line = x % 64 < 2 || y % 64 < 2;
+        if line { image::Rgb([200, 40, 30]) } else if checker { image::Rgb([230, 230, 220]) } else { image::Rgb([30, 30, 40]) }
+    });
+    // repeated once per 500 units of the 2000 x 20000 floor halves
+    let tiled = |filter: TextureFilter| Texture::Transform { scale: Vector::new(4.0, 40.0, 1.0), offset: Vector::new(0.0, 0.0, 0.0), texture: Box::new(Texture::image(&image, filter)) };
+    // trilinear on the left half, ewa on the right
+    let materials = vec![
+        Material::new_pbr(Color::white(), 0.0, 0.8, 1.5, 0.0, 0.0).with_texture(TextureChannel::BaseColor, tiled(TextureFilter::Trilinear)),
+        Material::new_pbr(Color::white(), 0.0, 0.8, 1.5, 0.0, 0.0).with_texture(TextureChannel::BaseColor, tiled(TextureFilter::Ewa)),
+        Material::new_reflective(Color::white(), 0.0, 0.0, 3.0),
+    ];
+    for (i, x) in [(-2000.0, 0.0), (0.0, 2000.0)].into_iter().enumerate() {
+        let floor = Surface::new_vw(
+            Vector::new(0.0, -200.0, 0.0),
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 0.0, -1.0),
+            Some(x),
+            Some((0.0, 20000.0)),
+            Vector::new(0.0, 1.0, 0.0)
+        );
+        scene.add_primitive(Box::new(floor), i);
+    }
+    // the differentials are carried through the mirror, the reflected floor is filtered too
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, -50.0, -900.0), 150.0)), 2);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.2));
+    scene.add_light(Light::new_point(Vector::new(0.0, 800.0, -1500.0), Color::new(1.5, 1.5, 1.5), (1.0, 0.0, 0.0000002)));
+
+    (scene, materials)
+}
+
+pub fn layered_materials_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+
+    // metal flakes, every voronoi cell turned a little differently
+    let flakes = Texture::Voronoi.scaled(300.0).in_space(TextureSpace::World).ramp(Color::new(0.5, 0.02, 0.05), Color::new(0.9, 0.15, 0.2));
+    // puddles where the noise is high
+    let puddles = Texture::mix(
+        Texture::Constant(Color::black()),
+        Texture::Constant(Color::white()),
+        Texture::Fbm { octaves: 4, lacunarity: 2.0, gain: 0.5 }.scaled(0.004).in_space(TextureSpace::World),
+    );
+    let puddles = Texture::Transform { scale: Vector::from_num(4.0), offset: Vector::from_num(-1.6), texture: Box::new(puddles) };
+    // round stickers on a plastic ball
+    let stickers = Texture::Worley.scaled(6.0).ramp(Color::white(), Color::black());
+    let stickers = Texture::Transform { scale: Vector::from_num(8.0), offset: Vector::from_num(-5.0), texture: Box::new(stickers) };
+
+    let materials = vec![
+        // 0: car paint, flakes under clearcoat
+        Material::new_pbr(Color::white(), 0.9, 0.45, 1.5, 0.0, 0.0).with_texture(TextureChannel::BaseColor, flakes),
+        Material::new_coated(0, 1.5, Color::white()),
+        // 3: dry and wet stone, blended by the puddles
+        Material::new_pbr(Color::new(0.45, 0.42, 0.4), 0.0, 0.9, 1.5, 0.0, 0.0),
+        Material::new_coated(2, 1.33, Color::new(0.9, 0.9, 0.85)),
+        Material::new_blend(2, 3, puddles),
+        // 6: decals
+        Material::new_phong(Color::new(0.2, 0.5, 0.9), 0.3, 20.0),
+        Material::new_phong(Color::new(1.0, 0.85, 0.1), 0.3, 20.0),
+        Material::new_blend(5, 6, stickers),
+        // 8: varnished wood
+        Material::new_pbr(Color::white(), 0.0, 0.7, 1.5, 0.0, 0.0)
+            .with_texture(TextureChannel::BaseColor, Texture::Wood { rings: 10.0, distortion: 0.5 }.scaled(0.01).in_space(TextureSpace::Object).ramp(Color::new(0.3, 0.15, 0.05), Color::new(0.6, 0.35, 0.15))),
+        Material::new_coated(8, 1.5, Color::new(0.95, 0.85, 0.6)),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 4);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-300.0, -60.0, -800.0), 140.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, -60.0, -800.0), 140.0)), 7);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(300.0, -60.0, -800.0), 140.0)), 9);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.05));
+    scene.add_light(Light::new_point(Vector::new(-300.0, 400.0, -300.0), Color::new(1.3, 1.3, 1.3), (1.0, 0.0001, 0.000001)));
+
+    (scene, materials)
+}
+
+pub fn thin_film_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+
+    // oil spreading unevenly over wet asphalt
+    let oil = Texture::Fbm { octaves: 4, lacunarity: 2.0, gain: 0.5 }.scaled(0.005).in_space(TextureSpace::World);
+    let wall = Texture::checker(Texture::Constant(Color::new(0.9, 0.9, 0.9)), Texture::Constant(Color::new(0.2, 0.3, 0.5))).scaled(0.01).in_space(TextureSpace::World);
+
+    let materials = vec![
+        // 0: soap bubble, a film with air on both sides
+        Material::new_refractive(Color::white(), 1.0).with_thin_film(380.0, 1.33),
+        // 1: oil slick
+        Material::new_pbr(Color::new(0.03, 0.03, 0.03), 0.0, 0.1, 1.5, 0.0, 0.0)
+            .with_thin_film(600.0, 1.45)
+            .with_texture(TextureChannel::FilmThickness, oil),
+        // 2: heat tinted titanium
+        Material::new_pbr(Color::new(0.6, 0.58, 0.55), 1.0, 0.25, 1.5, 0.0, 0.0).with_thin_film(250.0, 2.4),
+        // 3: lens with an anti reflection coating
+        Material::new_refractive(Color::white(), 1.5).with_thin_film(110.0, 1.38),
+        // 4: backdrop to see reflections in
+        Material::new_phong(Color::white(), 0.0, 1.0).with_texture(TextureChannel::BaseColor, wall),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 1);
+    let back = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1600.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(back), 4);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-300.0, -60.0, -800.0), 140.0)), 2);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, 0.0, -700.0), 150.0)), 0);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(300.0, -60.0, -800.0), 140.0)), 3);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.2));
+    scene.add_light(Light::new_point(Vector::new(0.0, 600.0, -200.0), Color::new(1.5, 1.5, 1.5), (1.0, 0.0001, 0.000001)));
+
+    (scene, materials)
+}
+
+pub fn subsurface_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+
+    let materials = vec![
+        // 0: the same wax without scattering, for comparison
+        Material::new_pbr(Color::new(0.9, 0.75, 0.5), 0.0, 0.4, 1.45, 0.0, 0.0),
+        // 1: wax, light goes deep before it scatters
+        Material::new_pbr(Color::new(0.9, 0.75, 0.5), 0.0, 0.4, 1.45, 0.0, 0.0).with_subsurface_scattering(Color::new(1.0, 0.8, 0.6), 30.0),
+        // 2: skin, red light travels further than blue
+        Material::new_pbr(Color::new(0.85, 0.55, 0.45), 0.0, 0.5, 1.4, 0.0, 0.0).with_subsurface_scattering(Color::new(1.0, 0.35, 0.2), 25.0),
+        // 3: milk
+        Material::new_pbr(Color::new(0.95, 0.95, 0.93), 0.0, 0.2, 1.35, 0.0, 0.0).with_subsurface_scattering(Color::new(0.9, 1.0, 1.0), 15.0),
+        // 4: marble
+        Material::new_pbr(Color::new(0.9, 0.9, 0.88), 0.0, 0.15, 1.5, 0.0, 0.0).with_subsurface_scattering(Color::new(0.8, 0.8, 0.9), 10.0),
+        // 5: floor
+        Material::new_pbr(Color::new(0.3, 0.3, 0.3), 0.0, 0.8, 1.5, 0.0, 0.0),
+    ];
+
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 5);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-360.0, -100.0, -800.0), 100.0)), 0);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-120.0, -100.0, -800.0), 100.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(120.0, -100.0, -800.0), 100.0)), 2);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(360.0, -100.0, -800.0), 100.0)), 3);
+    scene.add_primitive(Box::new(AxisAlignedBox::from_center_size(Vector::new(0.0, 80.0, -1000.0), Vector::new(300.0, 60.0, 60.0))), 4);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.05));
+    // from behind, so the light shining through the edges shows
+    scene.add_light(Light::new_point(Vector::new(0.0, 300.0, -1300.0), Color::new(1.2, 1.2, 1.2), (1.0, 0.0001, 0.000001)));
+    scene.add_light(Light::new_point(Vector::new(-400.0, 300.0, -200.0), Color::new(0.6, 0.6, 0.6), (1.0, 0.0001, 0.000001)));
+
+    (scene, materials)
+}
+
+pub fn daylight_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    // afternoon sun from behind the camera on the left, a little haze
+    scene.set_sky(Sky::new(as_radians(35.0), as_radians(140.0), 3.0));
+
+    let materials = vec![
+        Material::new_pbr(Color::new(0.5, 0.45, 0.4), 0.0, 0.9, 1.5, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.8, 0.1, 0.1), 0.0, 0.3, 1.5, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.95, 0.95, 0.95), 1.0, 0.05, 1.5, 0.0, 0.0),
+        Material::new_phong(Color::new(0.2, 0.6, 0.2), 0.3, 20.0),
+    ];
+
+    let ground = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(ground), 0);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(-300.0, -60.0, -800.0), 140.0)), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, -60.0, -900.0), 140.0)), 2);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(300.0, -60.0, -800.0), 140.0)), 3);
+
+    (scene, materials)
+}
+
+// a row of downlights close to a wall, each throwing the scallop of its ies profile
+pub fn ies_scene() -> (Scene, Vec<Material>) {
+    let mut scene = Scene::new();
+    let profile = Arc::new(IesProfile::load("res/downlight.ies").unwrap());
+
+    let materials = vec![
+        Material::new_phong(Color::new(0.9, 0.88, 0.85), 0.0, 1.0),
+        Material::new_pbr(Color::new(0.4, 0.3, 0.2), 0.0, 0.4, 1.5, 0.0, 0.0),
+        Material::new_pbr(Color::new(0.8, 0.8, 0.8), 0.0, 0.5, 1.5, 0.0, 0.0),
+    ];
+
+    let wall = Surface::new_vw(
+        Vector::new(0.0, 0.0, -1100.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+        None,
+        None,
+        Vector::new(0.0, 0.0, 1.0)
+    );
+    scene.add_primitive(Box::new(wall), 0);
+    let floor = Surface::new_vw(
+        Vector::new(0.0, -200.0, -800.0),
+        Vector::new(1.0, 0.0, 0.0),
+        Vector::new(0.0, 0.0, 1.0),
+        None,
+        None,
+        Vector::new(0.0, 1.0, 0.0)
+    );
+    scene.add_primitive(Box::new(floor), 1);
+    scene.add_primitive(Box::new(Sphere::new(Vector::new(120.0, -140.0, -900.0), 60.0)), 2);
+
+    scene.add_light(Light::new_ambient(Color::white(), 0.02));
+    // 100 units are a meter, the lights hang 30 cm in front of the wall
+    for x in [-450.0, -150.0, 150.0, 450.0] {
+        let light = Light::new_point(Vector::new(x, 190.0, -1070.0), Color::new(0.008, 0.0075, 0.0065), (1.0, 0.0, 0.0));
+        scene.add_light(light.with_ies_profile(profile.clone(), 100.0));
+    }
+
+    (scene, materials)
+}
@@ -1,9 +1,38 @@
-use crate::{light::Light, math::intersection::IntersectionPrimitive};
+use std::borrow::Cow;
+
+use crate::{color::Color, geometry::{AxisAlignedBox, Line}, light::{Light, LightType}, light_tree::LightTree, material::Material, math::{intersection::IntersectionPrimitive, Vector}, medium::{Medium, Volume}, photon_map::CausticPhotons, sky::Sky, stats::RenderStats};
+
+// primitive with an emissive material, lights the scene like an area light
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    pub primitive: usize,
+    pub radiance: Color,
+    pub area: f32,
+}
 
 pub struct Scene {
     pub primitives: Vec<Box<dyn IntersectionPrimitive + Send + Sync>>,
     pub material_index: Vec<usize>,
     pub lights: Vec<Light>,
+    // medium filling all space outside of the volumes
+    pub global_medium: Option<Medium>,
+    pub volumes: Vec<Volume>,
+    // step length of the ray march through media
+    pub march_step: f32,
+    pub emitters: Vec<Emitter>,
+    // points sampled on every emitter for each shaded point
+    pub emitter_samples: usize,
+    // background and sunlight of outdoor scenes, replaces the sky color of the camera
+    pub sky: Option<Sky>,
+    // built before rendering, picks lights by their estimated contribution
+    pub light_tree: Option<LightTree>,
+    // lights sampled for each shaded point, scenes with fewer lights use all of them
+    pub light_samples: usize,
+    // photons focused by specular objects, traced before rendering
+    pub caustics: Option<CausticPhotons>,
+    // pbr surfaces reflect a sampled glossy direction of their surroundings. rgb radiance then
+    // stays linear until the pixel is resolved, like in spectral mode
+    pub glossy_reflections: bool,
 }
 
 impl Scene {
@@ -12,6 +41,16 @@ impl Scene {
             primitives: Vec::new(),
             material_index: Vec::new(),
             lights: Vec::new(),
+            global_medium: None,
+            volumes: Vec::new(),
+            march_step: 10.0,
+            emitters: Vec::new(),
+            emitter_samples: 4,
+            sky: None,
+            light_tree: None,
+            light_samples: 8,
+            caustics: None,
+            glossy_reflections: false,
         }
     }
 
@@ -29,4 +68,206 @@ impl Scene {
             self.lights.push(light);
         }
     }
-}
\ No newline at end of file
+
+    pub fn set_global_medium(&mut self, medium: Medium) {
+        self.global_medium = Some(medium);
+    }
+
+    pub fn add_volume(&mut self, bounds: AxisAlignedBox, medium: Medium) {
+        self.volumes.push(Volume::new(bounds, medium));
+    }
+
+    // for volumes with density or emission grids
+    pub fn add_volume_object(&mut self, volume: Volume) {
+        self.volumes.push(volume);
+    }
+
+    // a closed primitive filled with the medium of the volume, the medium follows its surface
+    // (smoke in a glass sphere). the bounds of the volume should contain the primitive, they
+    // place the grids
+    pub fn add_primitive_with_volume(&mut self, primitive: Box<dyn IntersectionPrimitive + Send + Sync>, material_idx: usize, mut volume: Volume) {
+        volume.shape = Some(self.primitives.len());
+        self.add_primitive(primitive, material_idx);
+        self.volumes.push(volume);
+    }
+
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.sky = Some(sky);
+    }
+
+    pub fn set_caustics(&mut self, caustics: CausticPhotons) {
+        self.caustics = Some(caustics);
+    }
+
+    pub fn has_media(&self) -> bool {
+        self.global_medium.is_some() || !self.volumes.is_empty()
+    }
+
+    // the first volume containing the point wins, outside of all volumes it's the global medium
+    pub fn medium_at(&self, point: &Vector) -> Option<&Medium> {
+        match self.volume_at(point) {
+            Some(volume) => Some(&volume.medium),
+            None => self.global_medium.as_ref(),
+        }
+    }
+
+    pub fn volume_at(&self, point: &Vector) -> Option<&Volume> {
+        self.volumes.iter().find(|volume| match volume.shape {
+            // inside if a ray from the point is inside the shape right at its start
+            Some(_) => {
+                let ray = Line::new(*point, Vector::new(0.0, 1.0, 0.0));
+                self.volume_intervals(volume, &ray).iter().any(|(near, far)| *near <= 0.0 && *far >= 0.0)
+            }
+            None => volume.contains(point),
+        })
+    }
+
+    // the parts (near, far) of the infinite ray inside the volume, sorted by t
+    pub fn volume_intervals(&self, volume: &Volume, ray: &Line) -> Vec<(f32, f32)> {
+        match volume.shape {
+            Some(primitive) => self.primitives[primitive].intervals(ray).iter().map(|interval| (interval.enter.t, interval.exit.t)).collect(),
+            None => volume.bounds.slab(ray).into_iter().collect(),
+        }
+    }
+
+    // finds the primitives with emissive materials. the camera calls this before rendering,
+    // primitives without an area (infinite planes, csg, sdfs, cylinders, cones, tori and
+    // instances stretched unevenly) can't emit
+    pub fn register_emitters(&mut self, materials: &[Material]) {
+        self.emitters.clear();
+        for (i, primitive) in self.primitives.iter().enumerate() {
+            let material = &materials[self.material_index[i]];
+            if !material.is_emissive() {
+                continue;
+            }
+            let area = primitive.area();
+            if area <= 0.0 {
+                println!("warning: primitive {} is emissive but can't be sampled, it only glows where it is seen", i);
+                continue;
+            }
+            self.emitters.push(Emitter {
+                primitive: i,
+                radiance: material.emitted_radiance(),
+                area,
+            });
+        }
+    }
+
+    // the camera calls this before rendering, like register_emitters
+    pub fn build_light_tree(&mut self) {
+        self.light_tree = Some(LightTree::new(&self.lights));
+    }
+
+    // shoots the caustic photons, the camera calls this before rendering once the emitters
+    // are registered
+    pub fn trace_caustics(&mut self, materials: &[Material], stats: &mut RenderStats) {
+        if let Some(mut caustics) = self.caustics.take() {
+            caustics.trace(self, materials, stats);
+            self.caustics = Some(caustics);
+        }
+    }
+
+    // lights for shading the point. with more lights than light_samples, that many are picked
+    // from the light tree and their color divided by how likely they were, so on average they
+    // carry the light of all of them. ambient lights are always in. the scene's lights are
+    // borrowed, only the picked ones are copied to scale their color
+    pub fn lights_at(&self, point: &Vector) -> impl Iterator<Item = Cow<'_, Light>> + '_ {
+        let tree = self.light_tree.as_ref().filter(|tree| !tree.is_empty() && self.lights.len() > self.light_samples);
+        let point = *point;
+        let all = tree.is_none().then(|| self.lights.iter()).into_iter().flatten().map(Cow::Borrowed);
+        let picked = tree.into_iter().flat_map(move |tree| {
+            let ambient = self.lights.iter().filter(|light| light.light_type == LightType::Ambient).map(Cow::Borrowed);
+            let samples = (0..self.light_samples).filter_map(move |_| {
+                let (index, probability) = tree.sample(&point)?;
+                let mut light = self.lights[index].clone();
+                light.color /= probability * self.light_samples as f32;
+                Some(Cow::Owned(light))
+            });
+            ambient.chain(samples)
+        });
+        all.chain(picked)
+    }
+
+    // random points on the emitters, uniform by area, as lights for next event estimation.
+    // together the samples of an emitter carry all of its light. the sun is sampled on its
+    // disk and the rest of the sky is an ambient light. the samples are drawn as the
+    // iterator is walked, nothing is allocated per shaded point
+    pub fn sample_emitters(&self) -> impl Iterator<Item = Light> + '_ {
+        let sky = self.sky.iter().flat_map(|sky| {
+            let sun = (sky.sun_direction.y > 0.0).then(|| sky.sample_sun());
+            sun.into_iter().chain(std::iter::once(Light::new_ambient(sky.ambient, 1.0)))
+        });
+        let emitters = self.emitters.iter().flat_map(move |emitter| {
+            let area = emitter.area / self.emitter_samples as f32;
+            (0..self.emitter_samples).filter_map(move |_| {
+                let (point, normal) = self.primitives[emitter.primitive].sample_surface(rand::random::<f32>(), rand::random::<f32>())?;
+                Some(Light::new_area_sample(point, normal, emitter.radiance, area))
+            })
+        });
+        sky.chain(emitters)
+    }
+
+    // all light for shading the point, lights_at followed by sample_emitters
+    pub fn shading_lights(&self, point: &Vector) -> impl Iterator<Item = Cow<'_, Light>> + '_ {
+        self.lights_at(point).chain(self.sample_emitters().map(Cow::Owned))
+    }
+
+    // density over solid angle of sample_emitters picking the point of the primitive seen from
+    // origin, like the pdf of its samples. none for primitives that aren't emitters
+    pub fn emitter_pdf(&self, primitive: usize, origin: &Vector, point: &Vector, normal: &Vector) -> Option<f32> {
+        let emitter = self.emitters.iter().find(|emitter| emitter.primitive == primitive)?;
+        let to_origin = *origin - *point;
+        let cos = normal.dot(&to_origin._normalize()).abs();
+        if cos <= 0.0 {
+            return None;
+        }
+        Some(to_origin.length_squared() / (cos * emitter.area / self.emitter_samples as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lights_at_test() {
+        let mut scene = Scene::new();
+        scene.lights.push(Light::new_ambient(Color::white(), 0.1));
+        for i in 0..20 {
+            scene.lights.push(Light::new_point(Vector::new(i as f32, 10.0, 0.0), Color::white(), (1.0, 0.0, 0.0)));
+        }
+        let point = Vector::new(0.0, 0.0, 0.0);
+        // without a tree every light is lent as it is
+        assert_eq!(scene.lights_at(&point).count(), 21);
+        assert!(scene.lights_at(&point).all(|light| matches!(light, Cow::Borrowed(_))));
+
+        // with one the ambient light and light_samples picked ones
+        scene.build_light_tree();
+        let lights: Vec<_> = scene.lights_at(&point).collect();
+        assert_eq!(lights.len(), 1 + scene.light_samples);
+        assert!(matches!(lights[0], Cow::Borrowed(_)));
+        assert!(lights[1..].iter().all(|light| matches!(light, Cow::Owned(_)) && light.light_type == LightType::Point));
+    }
+
+    #[test]
+    fn register_emitters_test() {
+        use std::sync::Arc;
+        use crate::geometry::{Instance, Sphere, Torus};
+        use crate::math::Quaternion;
+
+        let mut scene = Scene::new();
+        let sphere = Arc::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0));
+        scene.add_primitive(Box::new(Instance::from_trs(sphere.clone(), Vector::new(0.0, 5.0, 0.0), Quaternion::identity(), Vector::from_num(2.0)).unwrap()), 0);
+        scene.add_primitive(Box::new(Instance::from_trs(sphere, Vector::new(0.0, 0.0, 0.0), Quaternion::identity(), Vector::new(1.0, 3.0, 1.0)).unwrap()), 0);
+        scene.add_primitive(Box::new(Torus::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), 4.0, 1.0)), 0);
+        scene.add_primitive(Box::new(AxisAlignedBox::new(Vector::new(0.0, 0.0, 0.0), Vector::from_num(1.0))), 1);
+        let materials = vec![Material::new_phong(Color::white(), 0.0, 1.0).with_emission(Color::white(), 2.0), Material::new_phong(Color::white(), 0.0, 1.0)];
+        scene.register_emitters(&materials);
+        // the evenly scaled instance emits with its world space area, the stretched one and the
+        // torus can't be sampled and the box doesn't glow
+        assert_eq!(scene.emitters.len(), 1);
+        assert_eq!(scene.emitters[0].primitive, 0);
+        assert!((scene.emitters[0].area - 16.0 * std::f32::consts::PI).abs() < 1e-3);
+        assert!(scene.sample_emitters().count() > 0);
+    }
+}
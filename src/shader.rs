@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::bsdf::dielectric_fresnel;
+use crate::color::Color;
+use crate::material::TextureChannel;
+use crate::math::Vector;
+use crate::texture::{Texture, TextureInput};
+
+// a hit as the shader graph sees it. normal is the shading normal, view points to the viewer
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderInput {
+    pub texture: TextureInput,
+    pub normal: Vector,
+    pub view: Vector,
+    pub distance: f32,
+}
+
+impl ShaderInput {
+    pub fn new(texture: TextureInput, normal: Vector, view: Vector, distance: f32) -> ShaderInput {
+        ShaderInput { texture, normal, view, distance }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Minimum,
+    Maximum,
+    Power,
+}
+
+// index of a node in its graph
+pub type NodeId = usize;
+
+// every node gives three numbers, a color or a vector. single numbers are in all three and
+// nodes reading a number from a color take its brightness
+#[derive(Debug, Clone)]
+pub enum ShaderNode {
+    Value(f32),
+    Color(Color),
+    Position,
+    ObjectPosition,
+    Normal,
+    // (u, v, 0)
+    Uv,
+    HitDistance,
+    // 1 facing the viewer, 0 at grazing angles
+    FacingRatio,
+    // share of light a dielectric of this ior reflects towards the viewer
+    Fresnel { ior: f32 },
+    // evaluated in its own space
+    Texture(Texture),
+    // evaluated at the point given by another node, for warped patterns
+    TextureAt { texture: Texture, point: NodeId },
+    Math { op: MathOp, a: NodeId, b: NodeId },
+    Mix { a: NodeId, b: NodeId, factor: NodeId },
+    Clamp { input: NodeId, min: f32, max: f32 },
+    // piecewise linear between colors at sorted positions
+    ColorRamp { input: NodeId, stops: Vec<(f32, Color)> },
+    // x, y or z as a single number
+    Component { input: NodeId, index: usize },
+}
+
+// nodes driving material inputs per hit. nodes can only read nodes added before them, so the
+// graph is evaluated in the order it was built and never has cycles
+#[derive(Debug, Clone, Default)]
+pub struct ShaderGraph {
+    pub nodes: Vec<ShaderNode>,
+    pub outputs: Vec<(TextureChannel, NodeId)>,
+}
+
+impl ShaderGraph {
+    pub fn new() -> ShaderGraph {
+        ShaderGraph::default()
+    }
+
+    pub fn add(&mut self, node: ShaderNode) -> NodeId {
+        let inputs = match &node {
+            ShaderNode::TextureAt { point, .. } => vec![*point],
+            ShaderNode::Math { a, b, .. } => vec![*a, *b],
+            ShaderNode::Mix { a, b, factor } => vec![*a, *b, *factor],
+            ShaderNode::Clamp { input, .. } | ShaderNode::ColorRamp { input, .. } | ShaderNode::Component { input, .. } => vec![*input],
+            _ => Vec::new(),
+        };
+        assert!(inputs.iter().all(|input| *input < self.nodes.len()), "shader nodes can only read nodes added before them");
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn value(&mut self, value: f32) -> NodeId {
+        self.add(ShaderNode::Value(value))
+    }
+
+    pub fn color(&mut self, color: Color) -> NodeId {
+        self.add(ShaderNode::Color(color))
+    }
+
+    pub fn math(&mut self, op: MathOp, a: NodeId, b: NodeId) -> NodeId {
+        self.add(ShaderNode::Math { op, a, b })
+    }
+
+    pub fn mix(&mut self, a: NodeId, b: NodeId, factor: NodeId) -> NodeId {
+        self.add(ShaderNode::Mix { a, b, factor })
+    }
+
+    // the node drives the material input, replacing an earlier connection
+    pub fn connect(&mut self, channel: TextureChannel, node: NodeId) {
+        self.outputs.retain(|(c, _)| *c != channel);
+        self.outputs.push((channel, node));
+    }
+
+    pub fn load(path: &str) -> Result<ShaderGraph> {
+        ShaderGraph::parse(&fs::read_to_string(path)?)
+    }
+
+    // the text form of a graph, one node per line as `name = node arguments`. nodes read
+    // other nodes by their name, which has to be given on a line above. `output channel name`
+    // connects a node to a material input, # starts a comment. the nodes are
+    //   value v, color r g b, position, object_position, normal, uv, hit_distance,
+    //   facing_ratio, fresnel ior,
+    //   add a b, subtract a b, multiply a b, divide a b, minimum a b, maximum a b, power a b,
+    //   mix a b factor, clamp input min max, component input x|y|z,
+    //   ramp input followed by a position and r g b per stop,
+    //   texture pattern, texture_at point pattern
+    // with the patterns perlin, simplex, worley, voronoi, fbm octaves lacunarity gain,
+    // turbulence octaves, marble octaves distortion, wood rings distortion, gradient x y z,
+    // uv_grid cells line_width and checker r g b r g b. channels are named like
+    // TextureChannel in snake case, base_color, roughness and so on
+    pub fn parse(text: &str) -> Result<ShaderGraph> {
+        let mut graph = ShaderGraph::new();
+        let mut names: HashMap<&str, NodeId> = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("shader: line {}: {}", number + 1, message));
+            let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["output", channel, node] => {
+                    let channel = parse_channel(channel).ok_or_else(|| invalid(format!("unknown channel {}", channel)))?;
+                    let node = *names.get(node).ok_or_else(|| invalid(format!("no node named {}", node)))?;
+                    graph.connect(channel, node);
+                }
+                [name, "=", kind, arguments @ ..] => {
+                    let mut arguments = Arguments { words: arguments.iter(), names: &names };
+                    let node = arguments.node(kind).map_err(invalid)?;
+                    if let Some(word) = arguments.words.next() {
+                        return Err(invalid(format!("{} after the arguments of {}", word, kind)));
+                    }
+                    names.insert(name, graph.add(node));
+                }
+                _ => return Err(invalid(String::from("expected `name = node arguments` or `output channel name`"))),
+            }
+        }
+        Ok(graph)
+    }
+
+    // values of all connected material inputs at the hit
+    pub fn evaluate(&self, input: &ShaderInput) -> Vec<(TextureChannel, Color)> {
+        let mut values: Vec<Color> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let value = evaluate_node(node, &values, input);
+            values.push(value);
+        }
+        self.outputs.iter().map(|(channel, node)| (*channel, values[*node])).collect()
+    }
+}
+
+// the words after a node name in the text form of a graph
+struct Arguments<'a> {
+    words: std::slice::Iter<'a, &'a str>,
+    names: &'a HashMap<&'a str, NodeId>,
+}
+
+impl<'a> Arguments<'a> {
+    fn word(&mut self) -> std::result::Result<&'a str, String> {
+        self.words.next().copied().ok_or_else(|| String::from("missing argument"))
+    }
+
+    fn number(&mut self) -> std::result::Result<f32, String> {
+        let word = self.word()?;
+        word.parse().map_err(|_| format!("{} is not a number", word))
+    }
+
+    fn count(&mut self) -> std::result::Result<u32, String> {
+        let word = self.word()?;
+        word.parse().map_err(|_| format!("{} is not a count", word))
+    }
+
+    fn color(&mut self) -> std::result::Result<Color, String> {
+        Ok(Color::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn input(&mut self) -> std::result::Result<NodeId, String> {
+        let word = self.word()?;
+        self.names.get(word).copied().ok_or_else(|| format!("no node named {}", word))
+    }
+
+    fn node(&mut self, kind: &str) -> std::result::Result<ShaderNode, String> {
+        let math = |op: MathOp, arguments: &mut Arguments| Ok(ShaderNode::Math { op, a: arguments.input()?, b: arguments.input()? });
+        match kind {
+            "value" => Ok(ShaderNode::Value(self.number()?)),
+            "color" => Ok(ShaderNode::Color(self.color()?)),
+            "position" => Ok(ShaderNode::Position),
+            "object_position" => Ok(ShaderNode::ObjectPosition),
+            "normal" => Ok(ShaderNode::Normal),
+            "uv" => Ok(ShaderNode::Uv),
+            "hit_distance" => Ok(ShaderNode::HitDistance),
+            "facing_ratio" => Ok(ShaderNode::FacingRatio),
+            "fresnel" => Ok(ShaderNode::Fresnel { ior: self.number()? }),
+            "texture" => Ok(ShaderNode::Texture(self.texture()?)),
+            "texture_at" => {
+                let point = self.input()?;
+                Ok(ShaderNode::TextureAt { texture: self.texture()?, point })
+            }
+            "add" => math(MathOp::Add, self),
+            "subtract" => math(MathOp::Subtract, self),
+            "multiply" => math(MathOp::Multiply, self),
+            "divide" => math(MathOp::Divide, self),
+            "minimum" => math(MathOp::Minimum, self),
+            "maximum" => math(MathOp::Maximum, self),
+            "power" => math(MathOp::Power, self),
+            "mix" => Ok(ShaderNode::Mix { a: self.input()?, b: self.input()?, factor: self.input()? }),
+            "clamp" => Ok(ShaderNode::Clamp { input: self.input()?, min: self.number()?, max: self.number()? }),
+            "component" => {
+                let input = self.input()?;
+                let index = match self.word()? {
+                    "x" => 0,
+                    "y" => 1,
+                    "z" => 2,
+                    other => return Err(format!("{} is not x, y or z", other)),
+                };
+                Ok(ShaderNode::Component { input, index })
+            }
+            "ramp" => {
+                let input = self.input()?;
+                let mut stops = Vec::new();
+                while self.words.len() > 0 {
+                    stops.push((self.number()?, self.color()?));
+                }
+                if stops.is_empty() {
+                    return Err(String::from("ramp without stops"));
+                }
+                stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+                Ok(ShaderNode::ColorRamp { input, stops })
+            }
+            _ => Err(format!("unknown node {}", kind)),
+        }
+    }
+
+    fn texture(&mut self) -> std::result::Result<Texture, String> {
+        match self.word()? {
+            "perlin" => Ok(Texture::Perlin),
+            "simplex" => Ok(Texture::Simplex),
+            "worley" => Ok(Texture::Worley),
+            "voronoi" => Ok(Texture::Voronoi),
+            "fbm" => Ok(Texture::Fbm { octaves: self.count()?, lacunarity: self.number()?, gain: self.number()? }),
+            "turbulence" => Ok(Texture::Turbulence { octaves: self.count()? }),
+            "marble" => Ok(Texture::Marble { octaves: self.count()?, distortion: self.number()? }),
+            "wood" => Ok(Texture::Wood { rings: self.number()?, distortion: self.number()? }),
+            "gradient" => Ok(Texture::Gradient { direction: self.color()?.to_vector() }),
+            "uv_grid" => Ok(Texture::UvGrid { cells: self.number()?, line_width: self.number()? }),
+            "checker" => Ok(Texture::checker(Texture::Constant(self.color()?), Texture::Constant(self.color()?))),
+            other => Err(format!("unknown pattern {}", other)),
+        }
+    }
+}
+
+fn parse_channel(name: &str) -> Option<TextureChannel> {
+    Some(match name {
+        "base_color" => TextureChannel::BaseColor,
+        "metallic" => TextureChannel::Metallic,
+        "roughness" => TextureChannel::Roughness,
+        "specular" => TextureChannel::Specular,
+        "shininess" => TextureChannel::Shininess,
+        "emission" => TextureChannel::Emission,
+        "specular_tint" => TextureChannel::SpecularTint,
+        "sheen" => TextureChannel::Sheen,
+        "sheen_tint" => TextureChannel::SheenTint,
+        "clearcoat" => TextureChannel::Clearcoat,
+        "clearcoat_roughness" => TextureChannel::ClearcoatRoughness,
+        "transmission" => TextureChannel::Transmission,
+        "subsurface" => TextureChannel::Subsurface,
+        "anisotropy" => TextureChannel::Anisotropy,
+        "film_thickness" => TextureChannel::FilmThickness,
+        _ => return None,
+    })
+}
+
+fn evaluate_node(node: &ShaderNode, values: &[Color], input: &ShaderInput) -> Color {
+    let number = |id: NodeId| values[id].brightness();
+    match node {
+        ShaderNode::Value(value) => Color::grey(*value),
+        ShaderNode::Color(color) => *color,
+        ShaderNode::Position => Color::from(input.texture.position),
+        ShaderNode::ObjectPosition => Color::from(input.texture.object_position),
+        ShaderNode::Normal => Color::from(input.normal),
+        ShaderNode::Uv => input.texture.uv.map(|(u, v)| Color::new(u, v, 0.0)).unwrap_or(Color::black()),
+        ShaderNode::HitDistance => Color::grey(input.distance),
+        ShaderNode::FacingRatio => Color::grey(input.normal.dot(&input.view).abs()),
+        ShaderNode::Fresnel { ior } => Color::grey(dielectric_fresnel(input.normal.dot(&input.view).abs(), *ior)),
+        ShaderNode::Texture(texture) => texture.value(&input.texture),
+        ShaderNode::TextureAt { texture, point } => texture.evaluate(&values[*point].to_vector(), &input.texture),
+        ShaderNode::Math { op, a, b } => {
+            let (a, b) = (values[*a], values[*b]);
+            let apply = |x: f32, y: f32| match op {
+                MathOp::Add => x + y,
+                MathOp::Subtract => x - y,
+                MathOp::Multiply => x * y,
+                MathOp::Divide => if y == 0.0 { 0.0 } else { x / y },
+                MathOp::Minimum => x.min(y),
+                MathOp::Maximum => x.max(y),
+                MathOp::Power => x.max(0.0).powf(y),
+            };
+            Color::new(apply(a.r, b.r), apply(a.g, b.g), apply(a.b, b.b))
+        }
+        ShaderNode::Mix { a, b, factor } => {
+            let mut color = values[*a];
+            color.blend(&values[*b], number(*factor).clamp(0.0, 1.0));
+            color
+        }
+        ShaderNode::Clamp { input, min, max } => {
+            let value = values[*input];
+            Color::new(value.r.clamp(*min, *max), value.g.clamp(*min, *max), value.b.clamp(*min, *max))
+        }
+        ShaderNode::ColorRamp { input, stops } => ramp(stops, number(*input)),
+        ShaderNode::Component { input, index } => {
+            let value = values[*input];
+            Color::grey(match index {
+                0 => value.r,
+                1 => value.g,
+                _ => value.b,
+            })
+        }
+    }
+}
+
+fn ramp(stops: &[(f32, Color)], t: f32) -> Color {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Color::black(),
+    };
+    if t <= first.0 {
+        return first.1;
+    }
+    for pair in stops.windows(2) {
+        let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+        if t <= t1 {
+            let mut color = c0;
+            color.blend(&c1, if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 });
+            return color;
+        }
+    }
+    last.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_graph_test() {
+        let texture = TextureInput::new(Some((0.25, 0.5)), Vector::new(0.0, 10.0, 0.0), None);
+        let input = ShaderInput::new(texture, Vector::new(0.0, 0.0, 1.0), Vector::new(0.0, 0.6, 0.8), 42.0);
+
+        let mut graph = ShaderGraph::new();
+        // roughness 0.2 near the camera to 0.8 far away
+        let distance = graph.add(ShaderNode::HitDistance);
+        let range = graph.value(100.0);
+        let t = graph.math(MathOp::Divide, distance, range);
+        let near = graph.value(0.2);
+        let far = graph.value(0.8);
+        let roughness = graph.mix(near, far, t);
+        graph.connect(TextureChannel::Roughness, roughness);
+        // blue when facing the viewer, red at grazing angles
+        let facing = graph.add(ShaderNode::FacingRatio);
+        let color = graph.add(ShaderNode::ColorRamp { input: facing, stops: vec![(0.0, Color::new(1.0, 0.0, 0.0)), (1.0, Color::new(0.0, 0.0, 1.0))] });
+        graph.connect(TextureChannel::BaseColor, color);
+        // height from the position
+        let position = graph.add(ShaderNode::Position);
+        let height = graph.add(ShaderNode::Component { input: position, index: 1 });
+        graph.connect(TextureChannel::Metallic, height);
+
+        let outputs = graph.evaluate(&input);
+        let output = |channel: TextureChannel| outputs.iter().find(|(c, _)| *c == channel).unwrap().1;
+        assert!((output(TextureChannel::Roughness).r - (0.2 + 0.6 * 0.42)).abs() < 1e-5);
+        let color = output(TextureChannel::BaseColor);
+        assert!((color.r - 0.2).abs() < 1e-5 && (color.b - 0.8).abs() < 1e-5);
+        assert_eq!(output(TextureChannel::Metallic).g, 10.0);
+
+        // fresnel of glass head on
+        let fresnel = graph.add(ShaderNode::Fresnel { ior: 1.5 });
+        graph.connect(TextureChannel::Specular, fresnel);
+        let straight = ShaderInput::new(texture, Vector::new(0.0, 0.0, 1.0), Vector::new(0.0, 0.0, 1.0), 1.0);
+        let specular = graph.evaluate(&straight).into_iter().find(|(c, _)| *c == TextureChannel::Specular).unwrap().1;
+        assert!((specular.r - 0.04).abs() < 1e-3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn shader_graph_order_test() {
+        let mut graph = ShaderGraph::new();
+        let a = graph.value(1.0);
+        graph.math(MathOp::Add, a, a + 1);
+    }
+
+    #[test]
+    fn shader_graph_parse_test() {
+        let text = "
+            # roughness 0.2 near the camera to 0.8 far away
+            distance = hit_distance
+            range = value 100
+            t = divide distance range
+            near = value 0.2
+            far = value 0.8
+            roughness = mix near far t
+            output roughness roughness
+
+            facing = facing_ratio
+            color = ramp facing 1 0 0 1  0 1 0 0   # stops in any order
+            output base_color color
+            height = component position_y_source y
+        ";
+        // names have to be defined before they are read
+        let error = ShaderGraph::parse(text).unwrap_err().to_string();
+        assert!(error.contains("line 14") && error.contains("position_y_source"), "{}", error);
+
+        let graph = ShaderGraph::parse(&text.replace("height = component position_y_source y", "position = position\nheight = component position y\noutput metallic height")).unwrap();
+        let texture = TextureInput::new(Some((0.25, 0.5)), Vector::new(0.0, 10.0, 0.0), None);
+        let input = ShaderInput::new(texture, Vector::new(0.0, 0.0, 1.0), Vector::new(0.0, 0.6, 0.8), 42.0);
+        let outputs = graph.evaluate(&input);
+        let output = |channel: TextureChannel| outputs.iter().find(|(c, _)| *c == channel).unwrap().1;
+        assert!((output(TextureChannel::Roughness).r - (0.2 + 0.6 * 0.42)).abs() < 1e-5);
+        let color = output(TextureChannel::BaseColor);
+        assert!((color.r - 0.2).abs() < 1e-5 && (color.b - 0.8).abs() < 1e-5);
+        assert_eq!(output(TextureChannel::Metallic).g, 10.0);
+
+        let warped = ShaderGraph::parse("p = position\nwarp = texture_at p fbm 3 2 0.5\ntiles = texture checker 1 1 1 0 0 0\noutput base_color tiles").unwrap();
+        assert!(matches!(warped.nodes[1], ShaderNode::TextureAt { point: 0, texture: Texture::Fbm { octaves: 3, .. } }));
+        for broken in ["a = value", "a = value x", "a = wobble", "a = value 1 2", "a = value 1\noutput glow a", "a = texture plaid", "a value 1"] {
+            assert!(ShaderGraph::parse(broken).is_err(), "{}", broken);
+        }
+    }
+}
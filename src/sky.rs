@@ -0,0 +1,210 @@
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::light::Light;
+use crate::math::Vector;
+use crate::spectrum::xyz_to_linear_srgb;
+
+// the sun is a point light this far away, close enough to a directional light for any scene
+const SUN_DISTANCE: f32 = 1.0e7;
+// angular diameter of the sun seen from the earth, radians
+const SUN_DIAMETER: f32 = 0.0093;
+// directions the ambient light of the sky is averaged over
+const AMBIENT_STEPS: usize = 16;
+
+// coefficients a to e of the perez sky luminance distribution
+type Perez = [f32; 5];
+
+// daylight after "A Practical Analytic Model for Daylight" (Preetham, Shirley, Smits 1999).
+// y is up, the sun stands at elevation above the horizon and azimuth around y, 0 lies
+// towards -z. turbidity is the haze of the air, 2 for clear to 10 for hazy skies.
+// radiance is relative to the zenith, scaled by exposure
+#[derive(Debug, Clone, Copy)]
+pub struct Sky {
+    pub sun_direction: Vector,
+    pub turbidity: f32,
+    // radians, the disk shadows are softened by
+    pub sun_diameter: f32,
+    pub exposure: f32,
+    // color of a white surface lit by the sun straight on
+    pub sun_color: Color,
+    // light of the whole sky on a surface facing up, for ambient lighting
+    pub ambient: Color,
+    perez: [Perez; 3],
+    zenith: (f32, f32, f32),
+}
+
+impl Sky {
+    // angles in radians
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Sky {
+        let elevation = elevation.clamp(0.0, PI / 2.0);
+        let sun_direction = Vector::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        let t = turbidity.clamp(1.7, 10.0);
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let theta = PI / 2.0 - elevation;
+        let (theta2, theta3) = (theta * theta, theta * theta * theta);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_yy = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+        let mut sky = Sky {
+            sun_direction,
+            turbidity: t,
+            sun_diameter: SUN_DIAMETER,
+            exposure: 0.1,
+            sun_color: sun_transmittance(theta, t),
+            ambient: Color::black(),
+            perez,
+            zenith: (zenith_x, zenith_yy, zenith_y),
+        };
+        sky.ambient = sky.irradiance_up();
+        sky
+    }
+
+    pub fn with_sun_diameter(mut self, diameter: f32) -> Sky {
+        self.sun_diameter = diameter.max(0.0);
+        self
+    }
+
+    // brightness of the sky, the sun is not affected
+    pub fn with_exposure(mut self, exposure: f32) -> Sky {
+        self.exposure = exposure;
+        self.ambient = self.irradiance_up();
+        self
+    }
+
+    // linear rgb radiance of the sky seen in direction, without the sun. below the horizon
+    // the ground reflects a bit of the horizon
+    pub fn radiance(&self, direction: &Vector) -> Color {
+        let direction = direction._normalize();
+        let ground = direction.y < 0.0;
+        let up = Vector::new(direction.x, direction.y.abs().max(0.01), direction.z)._normalize();
+        let cos_theta = up.y;
+        let gamma = up.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_sun = self.sun_direction.y.clamp(-1.0, 1.0).acos();
+        let relative = |coefficients: &Perez| perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_sun);
+        let x = self.zenith.0 * relative(&self.perez[1]);
+        let y = self.zenith.1 * relative(&self.perez[2]);
+        // luminance relative to the zenith, the absolute values are in kcd/m^2
+        let luminance = relative(&self.perez[0]) * self.exposure;
+        if y <= 0.0 {
+            return Color::black();
+        }
+        let xyz = Vector::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_linear_srgb(&xyz);
+        let rgb = Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0));
+        if ground { rgb * 0.3 } else { rgb }
+    }
+
+    // the sky with the disk of the sun, for rays that miss the scene
+    pub fn background(&self, direction: &Vector) -> Color {
+        self.radiance(direction) + self.sun_disk(direction)
+    }
+
+    // radiance of the disk of the sun in direction, black beside it. the light of the sun is
+    // spread over the same disk sample_sun picks from, what a white surface reflects of it is
+    // sun_color. a sun without a diameter is only seen by what it lights
+    pub fn sun_disk(&self, direction: &Vector) -> Color {
+        let pdf = match self.sun_pdf() {
+            Some(pdf) if self.sun_direction.y > 0.0 => pdf,
+            _ => return Color::black(),
+        };
+        if direction._normalize().dot(&self.sun_direction) < (self.sun_diameter / 2.0).cos() {
+            return Color::black();
+        }
+        self.sun_color * (PI * pdf)
+    }
+
+    // density over solid angle of the directions sample_sun picks, none for a sun too small to
+    // be hit by anything but light sampling
+    pub fn sun_pdf(&self) -> Option<f32> {
+        let solid_angle = 2.0 * PI * (1.0 - (self.sun_diameter / 2.0).cos());
+        (solid_angle > 0.0).then(|| 1.0 / solid_angle)
+    }
+
+    // the sun as a point light at a random spot of its disk. shaded points each get their own
+    // sample, which softens the shadows
+    pub fn sample_sun(&self) -> Light {
+        let (t, b) = self.sun_direction.orthonormal_basis();
+        let radius = (self.sun_diameter / 2.0).tan() * rand::random::<f32>().sqrt();
+        let angle = 2.0 * PI * rand::random::<f32>();
+        let direction = (self.sun_direction + t * (radius * angle.cos()) + b * (radius * angle.sin()))._normalize();
+        let area = self.sun_pdf().map_or(0.0, |pdf| SUN_DISTANCE * SUN_DISTANCE / pdf);
+        Light::new_point(direction * SUN_DISTANCE, self.sun_color, (1.0, 0.0, 0.0)).with_area(area)
+    }
+
+    // light of the sky on a white surface facing up, as its reflected radiance
+    fn irradiance_up(&self) -> Color {
+        let mut sum = Color::black();
+        for i in 0..AMBIENT_STEPS {
+            for j in 0..AMBIENT_STEPS {
+                // cosine weighted directions, their average is the irradiance over pi
+                let u1 = (i as f32 + 0.5) / AMBIENT_STEPS as f32;
+                let phi = 2.0 * PI * (j as f32 + 0.5) / AMBIENT_STEPS as f32;
+                let r = u1.sqrt();
+                sum += self.radiance(&Vector::new(r * phi.cos(), (1.0 - u1).sqrt(), r * phi.sin()));
+            }
+        }
+        sum / (AMBIENT_STEPS * AMBIENT_STEPS) as f32
+    }
+}
+
+fn perez(coefficients: &Perez, cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta.max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+// share of the sunlight getting through the air at the zenith angle theta, from rayleigh
+// scattering and aerosols (appendix of the preetham paper) at a red, green and blue wavelength
+fn sun_transmittance(theta: f32, turbidity: f32) -> Color {
+    let degrees = theta.to_degrees().min(93.0);
+    let air_mass = 1.0 / (theta.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |wavelength: f32| {
+        // micrometers
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+    Color::new(transmittance(0.61), transmittance(0.55), transmittance(0.465))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_test() {
+        let sky = Sky::new(30f32.to_radians(), 0.0, 3.0);
+        assert!(sky.sun_direction.y > 0.49 && sky.sun_direction.z < 0.0);
+        // blue at the zenith, brighter around the sun than away from it
+        let zenith = sky.radiance(&Vector::new(0.0, 1.0, 0.0));
+        assert!(zenith.b > zenith.r, "{}", zenith);
+        let brightness = |c: Color| c.r + c.g + c.b;
+        let near_sun = sky.radiance(&Vector::new(0.0, 0.6, -0.8));
+        let away = sky.radiance(&Vector::new(0.0, 0.6, 0.8));
+        assert!(brightness(near_sun) > brightness(away));
+        // the sun turns red at sunset
+        let noon = Sky::new(80f32.to_radians(), 0.0, 3.0).sun_color;
+        let sunset = Sky::new(2f32.to_radians(), 0.0, 3.0).sun_color;
+        assert!(noon.b / noon.r > sunset.b / sunset.r);
+        assert!(noon.r <= 1.0 && sunset.r < noon.r);
+        // the disk is much brighter than the sky around it
+        assert!(brightness(sky.background(&sky.sun_direction)) > 100.0 * brightness(sky.radiance(&sky.sun_direction)));
+        // the disk is as big as the one the sun is sampled on, its light stays the same
+        let wide = sky.with_sun_diameter(0.1);
+        let beside = (wide.sun_direction + Vector::new(0.03, 0.0, 0.0))._normalize();
+        assert!(sky.sun_disk(&beside).is_black() && !wide.sun_disk(&beside).is_black());
+        let disk_light = |sky: &Sky| brightness(sky.sun_disk(&sky.sun_direction)) / sky.sun_pdf().unwrap();
+        assert!((disk_light(&wide) - disk_light(&sky)).abs() < 1e-3 * disk_light(&sky));
+        assert!(sky.with_sun_diameter(0.0).sun_disk(&sky.sun_direction).is_black());
+    }
+}
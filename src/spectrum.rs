@@ -0,0 +1,399 @@
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::math::Vector;
+
+// visible range used for wavelength sampling, in nanometers
+pub const MIN_WAVELENGTH: f32 = 380.0;
+pub const MAX_WAVELENGTH: f32 = 780.0;
+
+// piecewise gaussian with different widths left and right of the peak
+fn lobe(wavelength: f32, peak: f32, left_width: f32, right_width: f32) -> f32 {
+    let width = if wavelength < peak { left_width } else { right_width };
+    let x = (wavelength - peak) / width;
+    (-0.5 * x * x).exp()
+}
+
+// CIE 1931 2 degree color matching functions, multi-lobe fit from
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (Wyman, Sloan, Shirley 2013)
+pub fn cie_x(wavelength: f32) -> f32 {
+    1.056 * lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7) - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2)
+}
+
+pub fn cie_y(wavelength: f32) -> f32 {
+    0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1)
+}
+
+pub fn cie_z(wavelength: f32) -> f32 {
+    1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8)
+}
+
+pub fn wavelength_to_xyz(wavelength: f32) -> Vector {
+    Vector::new(cie_x(wavelength), cie_y(wavelength), cie_z(wavelength))
+}
+
+// XYZ to linear sRGB, D65 white point
+pub fn xyz_to_linear_srgb(xyz: &Vector) -> Color {
+    Color::new(
+        3.240_454 * xyz.x - 1.537_139 * xyz.y - 0.498_531 * xyz.z,
+        -0.969_266 * xyz.x + 1.876_011 * xyz.y + 0.041_556 * xyz.z,
+        0.055_643 * xyz.x - 0.204_026 * xyz.y + 1.057_225 * xyz.z,
+    )
+}
+
+// rgb of a single wavelength before normalization. colors outside of the srgb gamut are clipped
+fn wavelength_to_rgb_unnormalized(wavelength: f32) -> Color {
+    let rgb = xyz_to_linear_srgb(&wavelength_to_xyz(wavelength));
+    Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0))
+}
+
+// average rgb over the visible range, so that white light split into wavelengths adds up to white again
+fn rgb_normalization() -> Color {
+    static NORMALIZATION: OnceLock<Color> = OnceLock::new();
+    *NORMALIZATION.get_or_init(|| {
+        let steps = 1000;
+        let mut sum = Color::black();
+        for i in 0..steps {
+            let wavelength = MIN_WAVELENGTH + (MAX_WAVELENGTH - MIN_WAVELENGTH) * (i as f32 + 0.5) / steps as f32;
+            sum += wavelength_to_rgb_unnormalized(wavelength);
+        }
+        sum / steps as f32
+    })
+}
+
+// weight of one wavelength when a ray is split into wavelengths sampled uniformly over the
+// visible range. averaging the weights of all wavelengths gives white
+pub fn wavelength_to_rgb(wavelength: f32) -> Color {
+    let rgb = wavelength_to_rgb_unnormalized(wavelength);
+    let normalization = rgb_normalization();
+    Color::new(rgb.r / normalization.r, rgb.g / normalization.g, rgb.b / normalization.b)
+}
+
+// count wavelengths stratified over the visible range, jitter in 0..1 moves them inside their strata
+pub fn stratified_wavelengths(count: usize, jitter: f32) -> Vec<f32> {
+    let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+    (0..count)
+        .map(|i| MIN_WAVELENGTH + range * (i as f32 + jitter) / count as f32)
+        .collect()
+}
+
+// ---- spectral rendering ----
+// in spectral mode a ray carries three wavelengths, one per color channel. every color the
+// ray picks up (materials, lights, media) is turned into the values of its spectrum at those
+// wavelengths, so all of the rgb light transport code works unchanged on spectral samples.
+pub type Wavelengths = [f32; 3];
+
+// hero wavelength sampling (Wilkie et al. 2014): u picks the hero wavelength, the other two
+// are rotated by a third of the visible range each
+pub fn hero_wavelengths(u: f32) -> Wavelengths {
+    let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+    let hero = u * range;
+    [0, 1, 2].map(|i| MIN_WAVELENGTH + (hero + range * i as f32 / 3.0) % range)
+}
+
+// integral of cie_y over the visible range
+fn y_integral() -> f32 {
+    static Y_INTEGRAL: OnceLock<f32> = OnceLock::new();
+    *Y_INTEGRAL.get_or_init(|| integrate(cie_y))
+}
+
+// riemann sum over the visible range
+fn integrate(f: impl Fn(f32) -> f32) -> f32 {
+    let steps = 800;
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f32;
+    (0..steps).map(|i| f(MIN_WAVELENGTH + (i as f32 + 0.5) * step) * step).sum()
+}
+
+// XYZ of the spectral samples traced for a camera ray, scaled so that a constant spectrum of 1 has Y = 1
+pub fn spectral_to_xyz(samples: &Color, wavelengths: &Wavelengths) -> Vector {
+    let values = [samples.r, samples.g, samples.b];
+    let weight = (MAX_WAVELENGTH - MIN_WAVELENGTH) / 3.0 / y_integral();
+    let mut xyz = Vector::new(0.0, 0.0, 0.0);
+    for (value, wavelength) in values.iter().zip(wavelengths.iter()) {
+        xyz += wavelength_to_xyz(*wavelength) * (value * weight);
+    }
+    xyz
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Rec2020,
+}
+
+impl ColorSpace {
+    // XYZ to linear rgb in this color space. both use the D65 white point
+    pub fn xyz_to_rgb(&self, xyz: &Vector) -> Color {
+        match self {
+            ColorSpace::Srgb => xyz_to_linear_srgb(xyz),
+            ColorSpace::Rec2020 => Color::new(
+                1.716_651 * xyz.x - 0.355_670_8 * xyz.y - 0.253_366_3 * xyz.z,
+                -0.666_684_4 * xyz.x + 1.616_481_2 * xyz.y + 0.015_768_5 * xyz.z,
+                0.017_639_9 * xyz.x - 0.042_770_6 * xyz.y + 0.942_103_1 * xyz.z,
+            ),
+        }
+    }
+}
+
+// ---- rgb to spectrum ----
+// basis spectra of "An RGB to Spectrum Conversion for Reflectances" (Smits 1999), 10 bins from 380 to 720 nm
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+// linear interpolation between the bin centers, constant past the first and last one
+fn smits_basis(basis: &[f32; 10], wavelength: f32) -> f32 {
+    let bin_width = (720.0 - 380.0) / 10.0;
+    let x = ((wavelength - 380.0) / bin_width - 0.5).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f32;
+    basis[i] * (1.0 - t) + basis[i + 1] * t
+}
+
+// smits' uplift of a reflectance in 0..1 to the value of its spectrum at a wavelength
+pub fn rgb_to_spectrum(rgb: &Color, wavelength: f32) -> f32 {
+    let (r, g, b) = (rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0));
+    let at = |basis: &[f32; 10]| smits_basis(basis, wavelength);
+    if r <= g && r <= b {
+        let mut value = r * at(&SMITS_WHITE);
+        if g <= b {
+            value += (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE);
+        } else {
+            value += (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN);
+        }
+        value
+    } else if g <= r && g <= b {
+        let mut value = g * at(&SMITS_WHITE);
+        if r <= b {
+            value += (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE);
+        } else {
+            value += (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED);
+        }
+        value
+    } else {
+        let mut value = b * at(&SMITS_WHITE);
+        if r <= g {
+            value += (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN);
+        } else {
+            value += (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED);
+        }
+        value
+    }
+}
+
+// reflectances (albedo, absorption colors) as seen by a ray with these wavelengths. unchanged outside of spectral mode
+pub fn reflectance_for(rgb: &Color, spectral: Option<&Wavelengths>) -> Color {
+    match spectral {
+        Some(wavelengths) => Color::new(
+            rgb_to_spectrum(rgb, wavelengths[0]),
+            rgb_to_spectrum(rgb, wavelengths[1]),
+            rgb_to_spectrum(rgb, wavelengths[2]),
+        ),
+        None => *rgb,
+    }
+}
+
+// like reflectance_for but for values above 1 too (media coefficients), the brightest channel is scaled to 1 first
+pub fn unbounded_for(rgb: &Color, spectral: Option<&Wavelengths>) -> Color {
+    let max = rgb.r.max(rgb.g).max(rgb.b);
+    if spectral.is_none() || max <= 0.0 {
+        return *rgb;
+    }
+    reflectance_for(&(*rgb / max), spectral) * max
+}
+
+// rgb light colors as seen by a ray. in spectral mode white light is D65, the white point of srgb
+pub fn emission_for(rgb: &Color, spectral: Option<&Wavelengths>) -> Color {
+    match spectral {
+        Some(_) => unbounded_for(rgb, spectral) * EmissionSpectrum::d65().for_ray(spectral),
+        None => *rgb,
+    }
+}
+
+// ---- light spectra ----
+// CIE standard illuminant D65, 380 to 780 nm in 10 nm steps
+const D65: [f32; 41] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.790, 107.689, 104.405, 104.046, 100.000, 96.3342,
+    95.788, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778,
+    78.2842, 69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054,
+    63.3828,
+];
+
+// CIE F2, cool white fluorescent, 380 to 780 nm in 5 nm steps
+const F2: [f32; 81] = [
+    1.18, 1.48, 1.84, 2.15, 3.44, 15.69, 3.85, 3.74, 4.19, 4.62,
+    5.06, 34.98, 11.81, 6.27, 6.63, 6.93, 7.19, 7.40, 7.54, 7.62,
+    7.65, 7.62, 7.62, 7.45, 7.28, 7.15, 7.05, 7.04, 7.16, 7.47,
+    8.04, 8.88, 10.01, 24.88, 16.64, 14.59, 16.16, 17.56, 18.62, 21.47,
+    22.79, 19.29, 18.66, 17.73, 16.54, 15.21, 13.80, 12.36, 10.95, 9.65,
+    8.40, 7.32, 6.31, 5.43, 4.68, 4.02, 3.45, 2.96, 2.55, 2.19,
+    1.89, 1.64, 1.53, 1.27, 1.10, 0.99, 0.88, 0.76, 0.68, 0.61,
+    0.56, 0.54, 0.51, 0.47, 0.47, 0.43, 0.46, 0.47, 0.40, 0.33,
+    0.27,
+];
+
+// CIE F11, narrow band tri-phosphor fluorescent, 380 to 780 nm in 5 nm steps
+const F11: [f32; 81] = [
+    0.91, 0.63, 0.46, 0.37, 1.29, 12.68, 1.59, 1.79, 2.46, 3.33,
+    4.49, 33.94, 12.13, 6.95, 7.19, 7.12, 6.72, 6.13, 5.46, 4.79,
+    5.66, 14.29, 14.96, 8.97, 4.72, 2.33, 1.47, 1.10, 0.89, 0.83,
+    1.18, 4.90, 39.59, 72.84, 32.61, 7.52, 2.83, 1.96, 1.67, 4.43,
+    11.28, 14.76, 12.73, 9.74, 7.33, 9.72, 55.27, 42.58, 13.18, 13.16,
+    12.26, 5.11, 2.07, 2.34, 3.58, 3.01, 2.48, 2.14, 1.54, 1.33,
+    1.46, 1.94, 2.00, 1.20, 1.35, 4.10, 5.58, 2.51, 0.57, 0.27,
+    0.23, 0.21, 0.24, 0.24, 0.20, 0.24, 0.32, 0.26, 0.16, 0.12,
+    0.09,
+];
+
+// linear interpolation in a table starting at MIN_WAVELENGTH
+fn sample_table(table: &[f32], step: f32, wavelength: f32) -> f32 {
+    let x = ((wavelength - MIN_WAVELENGTH) / step).clamp(0.0, (table.len() - 1) as f32);
+    let i = (x as usize).min(table.len() - 2);
+    let t = x - i as f32;
+    table[i] * (1.0 - t) + table[i + 1] * t
+}
+
+// planck's law, relative spectral radiance of a black body
+pub fn blackbody(wavelength: f32, kelvin: f32) -> f32 {
+    let h = 6.626_070_15e-34_f64;
+    let c = 2.997_924_58e8_f64;
+    let k = 1.380_649e-23_f64;
+    let l = wavelength as f64 * 1e-9;
+    let radiance = 2.0 * h * c * c / (l.powi(5) * ((h * c / (l * k * kelvin as f64)).exp() - 1.0));
+    // scaled down to keep it in f32 range
+    (radiance * 1e-9) as f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Illuminant {
+    // color temperature in kelvin
+    Blackbody(f32),
+    D65,
+    // incandescent tungsten, a black body at 2856 K
+    A,
+    F2,
+    F11,
+}
+
+impl Illuminant {
+    pub fn raw_value(&self, wavelength: f32) -> f32 {
+        match self {
+            Illuminant::Blackbody(kelvin) => blackbody(wavelength, *kelvin),
+            Illuminant::D65 => sample_table(&D65, 10.0, wavelength),
+            Illuminant::A => blackbody(wavelength, 2856.0),
+            Illuminant::F2 => sample_table(&F2, 5.0, wavelength),
+            Illuminant::F11 => sample_table(&F11, 5.0, wavelength),
+        }
+    }
+}
+
+// an illuminant scaled to a luminance of 1, with its rgb color for rendering without spectra
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmissionSpectrum {
+    pub illuminant: Illuminant,
+    scale: f32,
+    rgb: Color,
+}
+
+impl EmissionSpectrum {
+    pub fn new(illuminant: Illuminant) -> EmissionSpectrum {
+        let luminance = integrate(|l| illuminant.raw_value(l) * cie_y(l)) / y_integral();
+        let scale = 1.0 / luminance;
+        let x = integrate(|l| illuminant.raw_value(l) * cie_x(l)) * scale / y_integral();
+        let z = integrate(|l| illuminant.raw_value(l) * cie_z(l)) * scale / y_integral();
+        let rgb = xyz_to_linear_srgb(&Vector::new(x, 1.0, z));
+        EmissionSpectrum {
+            illuminant,
+            scale,
+            rgb: Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0)),
+        }
+    }
+
+    pub fn d65() -> EmissionSpectrum {
+        static D65_SPECTRUM: OnceLock<EmissionSpectrum> = OnceLock::new();
+        *D65_SPECTRUM.get_or_init(|| EmissionSpectrum::new(Illuminant::D65))
+    }
+
+    pub fn value(&self, wavelength: f32) -> f32 {
+        self.illuminant.raw_value(wavelength) * self.scale
+    }
+
+    // linear srgb color of the light, luminance 1
+    pub fn rgb(&self) -> Color {
+        self.rgb
+    }
+
+    // the spectrum at the ray's wavelengths, or its rgb color outside of spectral mode
+    pub fn for_ray(&self, ray_spectral: Option<&Wavelengths>) -> Color {
+        match ray_spectral {
+            Some(w) => Color::new(self.value(w[0]), self.value(w[1]), self.value(w[2])),
+            None => self.rgb,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_functions_test() {
+        // peaks of the real matching functions
+        assert!((cie_y(555.0) - 1.0).abs() < 0.02);
+        assert!((cie_x(600.0) - 1.06).abs() < 0.02);
+        assert!((cie_z(445.0) - 1.78).abs() < 0.05);
+        assert!(cie_y(380.0) < 0.01 && cie_y(780.0) < 0.01);
+    }
+
+    #[test]
+    fn illuminant_test() {
+        // D65 is the white point of srgb
+        let d65 = EmissionSpectrum::d65().rgb();
+        assert!((d65.r - 1.0).abs() < 0.03 && (d65.g - 1.0).abs() < 0.03 && (d65.b - 1.0).abs() < 0.03);
+        // incandescent light is orange, fluorescents a little warmer than daylight
+        let a = EmissionSpectrum::new(Illuminant::A).rgb();
+        assert!(a.r > a.g && a.g > a.b);
+        let f2 = EmissionSpectrum::new(Illuminant::F2).rgb();
+        assert!(f2.r > f2.b);
+        let hot = EmissionSpectrum::new(Illuminant::Blackbody(10000.0)).rgb();
+        assert!(hot.b > hot.r);
+    }
+
+    #[test]
+    fn uplift_test() {
+        assert!((rgb_to_spectrum(&Color::white(), 550.0) - 1.0).abs() < 0.01);
+        let red = Color::new(0.8, 0.1, 0.1);
+        assert!(rgb_to_spectrum(&red, 650.0) > 0.7);
+        assert!(rgb_to_spectrum(&red, 450.0) < 0.2);
+
+        // a white surface under white light comes out white
+        let mut sum = Vector::new(0.0, 0.0, 0.0);
+        let steps = 300;
+        for i in 0..steps {
+            let wavelengths = hero_wavelengths((i as f32 + 0.5) / steps as f32);
+            let d65 = EmissionSpectrum::d65().for_ray(Some(&wavelengths));
+            sum += spectral_to_xyz(&d65, &wavelengths);
+        }
+        let rgb = xyz_to_linear_srgb(&(sum / steps as f32));
+        assert!((rgb.r - 1.0).abs() < 0.03 && (rgb.g - 1.0).abs() < 0.03 && (rgb.b - 1.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn white_test() {
+        let wavelengths = stratified_wavelengths(400, 0.5);
+        let mut sum = Color::black();
+        for wavelength in wavelengths.iter() {
+            sum += wavelength_to_rgb(*wavelength);
+        }
+        let white = sum / wavelengths.len() as f32;
+        assert!((white.r - 1.0).abs() < 0.01 && (white.g - 1.0).abs() < 0.01 && (white.b - 1.0).abs() < 0.01);
+        // red light is red
+        let red = wavelength_to_rgb(650.0);
+        assert!(red.r > red.g && red.r > red.b);
+    }
+}
@@ -0,0 +1,286 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// counters gathered by a single render thread. every thread keeps its own copy
+// so nothing has to be synchronized while tracing, they get merged at the end.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    pub camera_rays: u64,
+    pub shadow_rays: u64,
+    pub secondary_rays: u64,
+    pub intersection_tests: u64,
+    pub nan_samples: u64,
+    // bounces reached by the paths of all camera rays, each path counts its deepest ray
+    pub bounce_depth_sum: u64,
+    // fewest bounces left on a ray of the path being traced, none before its first ray
+    lowest_remaining: Option<i32>,
+}
+
+impl RenderStats {
+    pub fn new() -> RenderStats {
+        RenderStats::default()
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.camera_rays + self.shadow_rays + self.secondary_rays
+    }
+
+    // called for every ray of a path with the bounces it has left
+    pub fn record_bounce(&mut self, remaining_bounces: i32) {
+        self.lowest_remaining = Some(self.lowest_remaining.map_or(remaining_bounces, |lowest| lowest.min(remaining_bounces)));
+    }
+
+    // adds how deep the path of a camera ray went, started with max_bounces
+    pub fn finish_path(&mut self, max_bounces: i32) {
+        if let Some(lowest) = self.lowest_remaining.take() {
+            self.bounce_depth_sum += (max_bounces - lowest).max(0) as u64;
+        }
+    }
+
+    // average number of bounces on the deepest ray of each camera ray's path
+    pub fn average_bounce_depth(&self) -> f32 {
+        if self.camera_rays == 0 {
+            return 0.0;
+        }
+        self.bounce_depth_sum as f32 / self.camera_rays as f32
+    }
+
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.camera_rays += other.camera_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.intersection_tests += other.intersection_tests;
+        self.nan_samples += other.nan_samples;
+        self.bounce_depth_sum += other.bounce_depth_sum;
+    }
+}
+
+// snapshot passed to the progress callback while the render is running
+#[derive(Debug, Clone, Copy)]
+pub struct RenderProgress {
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    pub rays_traced: u64,
+    pub elapsed: Duration,
+}
+
+impl RenderProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.tiles_total == 0 {
+            return 1.0;
+        }
+        self.tiles_done as f32 / self.tiles_total as f32
+    }
+
+    // estimated time left, assuming the remaining tiles take as long as the finished ones
+    pub fn eta(&self) -> Option<Duration> {
+        if self.tiles_done == 0 {
+            return None;
+        }
+        let per_tile = self.elapsed.as_secs_f64() / self.tiles_done as f64;
+        let left = (self.tiles_total - self.tiles_done) as f64 * per_tile;
+        Some(Duration::from_secs_f64(left))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return 0.0;
+        }
+        self.rays_traced as f64 / secs
+    }
+}
+
+pub type ProgressCallback = Box<dyn Fn(&RenderProgress)>;
+
+// default progress reporter, overwrites a single terminal line
+pub fn print_progress(progress: &RenderProgress) {
+    let eta = match progress.eta() {
+        Some(eta) => format!("{:.1}s", eta.as_secs_f32()),
+        None => String::from("?"),
+    };
+    print!(
+        "\r[{:>3.0}%] {}/{} tiles, {:.2} Mrays/s, ETA {}    ",
+        progress.fraction() * 100.0,
+        progress.tiles_done,
+        progress.tiles_total,
+        progress.rays_per_second() / 1_000_000.0,
+        eta,
+    );
+    std::io::stdout().flush().unwrap();
+}
+
+// shared between the render threads and the thread reporting progress.
+// threads grab tiles through next_tile and bump the other counters after each tile.
+#[derive(Debug, Default)]
+pub struct ProgressCounters {
+    pub next_tile: AtomicUsize,
+    pub tiles_done: AtomicUsize,
+    pub rays_traced: AtomicU64,
+}
+
+impl ProgressCounters {
+    pub fn new() -> ProgressCounters {
+        ProgressCounters::default()
+    }
+
+    pub fn snapshot(&self, tiles_total: usize, start: Instant) -> RenderProgress {
+        RenderProgress {
+            tiles_done: self.tiles_done.load(Ordering::Relaxed),
+            tiles_total,
+            rays_traced: self.rays_traced.load(Ordering::Relaxed),
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+// final statistics of a render, printed to the terminal and saved as json
+#[derive(Debug, Clone, Default)]
+pub struct RenderReport {
+    pub stats: RenderStats,
+    pub threads: usize,
+    pub tiles: usize,
+    // photons stored for caustics before rendering
+    pub caustic_photons: usize,
+    pub phases: Vec<(String, Duration)>,
+}
+
+impl RenderReport {
+    pub fn new(threads: usize, tiles: usize) -> RenderReport {
+        RenderReport {
+            threads,
+            tiles,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_phase(&mut self, name: &str, duration: Duration) {
+        self.phases.push((name.to_string(), duration));
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.phases.iter().map(|(_, d)| *d).sum()
+    }
+
+    pub fn print(&self) {
+        let s = &self.stats;
+        println!("---- render statistics ----");
+        println!("threads:              {}", self.threads);
+        println!("tiles:                {}", self.tiles);
+        println!("camera rays:          {}", s.camera_rays);
+        println!("shadow rays:          {}", s.shadow_rays);
+        println!("secondary rays:       {}", s.secondary_rays);
+        println!("intersection tests:   {}", s.intersection_tests);
+        println!("bounce depth sum:     {}", s.bounce_depth_sum);
+        println!("average bounce depth: {:.3}", s.average_bounce_depth());
+        println!("NaN samples dropped:  {}", s.nan_samples);
+        println!("caustic photons:      {}", self.caustic_photons);
+        for (name, duration) in self.phases.iter() {
+            println!("{:<22}{}ms", format!("{}:", name), duration.as_millis());
+        }
+        println!("{:<22}{}ms", "total:", self.total_time().as_millis());
+    }
+
+    pub fn to_json(&self) -> String {
+        let s = &self.stats;
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|(name, duration)| format!("    \"{}\": {:.3}", json_escape(name), duration.as_secs_f64() * 1000.0))
+            .collect();
+        format!(
+            "{{\n  \"threads\": {},\n  \"tiles\": {},\n  \"camera_rays\": {},\n  \"shadow_rays\": {},\n  \"secondary_rays\": {},\n  \"intersection_tests\": {},\n  \"bounce_depth_sum\": {},\n  \"average_bounce_depth\": {:.6},\n  \"nan_samples_discarded\": {},\n  \"caustic_photons\": {},\n  \"phases_ms\": {{\n{}\n  }},\n  \"total_ms\": {:.3}\n}}\n",
+            self.threads,
+            self.tiles,
+            s.camera_rays,
+            s.shadow_rays,
+            s.secondary_rays,
+            s.intersection_tests,
+            s.bounce_depth_sum,
+            s.average_bounce_depth(),
+            s.nan_samples,
+            self.caustic_photons,
+            phases.join(",\n"),
+            self.total_time().as_secs_f64() * 1000.0,
+        )
+    }
+
+    pub fn save_json(&self, path: &str) {
+        let mut file = File::create(path).unwrap();
+        file.write_all(self.to_json().as_bytes()).unwrap();
+    }
+}
+
+// quotes, backslashes and control characters in a json string
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_test() {
+        let mut a = RenderStats { camera_rays: 10, shadow_rays: 5, secondary_rays: 20, intersection_tests: 100, nan_samples: 1, bounce_depth_sum: 15, ..Default::default() };
+        let b = RenderStats { camera_rays: 10, shadow_rays: 1, secondary_rays: 0, intersection_tests: 50, nan_samples: 0, bounce_depth_sum: 5, ..Default::default() };
+        a.merge(&b);
+        assert_eq!(a.camera_rays, 20);
+        assert_eq!(a.total_rays(), 46);
+        assert_eq!(a.average_bounce_depth(), 1.0);
+    }
+
+    #[test]
+    fn bounce_depth_test() {
+        // branching rays at the same depth don't make a path deeper
+        let mut stats = RenderStats::new();
+        stats.camera_rays = 2;
+        for remaining in [4, 3, 3, 3, 2] {
+            stats.record_bounce(remaining);
+        }
+        stats.finish_path(4);
+        stats.record_bounce(4);
+        stats.finish_path(4);
+        assert_eq!(stats.bounce_depth_sum, 2);
+        assert_eq!(stats.average_bounce_depth(), 1.0);
+        // a camera ray that hit nothing adds nothing
+        stats.finish_path(4);
+        assert_eq!(stats.bounce_depth_sum, 2);
+    }
+
+    #[test]
+    fn eta_test() {
+        let progress = RenderProgress { tiles_done: 5, tiles_total: 20, rays_traced: 1000, elapsed: Duration::from_secs(10) };
+        assert_eq!(progress.eta(), Some(Duration::from_secs(30)));
+        assert_eq!(progress.rays_per_second(), 100.0);
+    }
+
+    #[test]
+    fn json_test() {
+        let mut report = RenderReport::new(4, 8);
+        report.stats.camera_rays = 3;
+        report.caustic_photons = 40;
+        report.add_phase("render", Duration::from_millis(2));
+        let json = report.to_json();
+        assert!(json.contains("\"camera_rays\": 3"));
+        assert!(json.contains("\"caustic_photons\": 40"));
+        assert!(json.contains("\"render\": 2.000"));
+
+        report.add_phase("a \"quoted\"\\phase\n", Duration::from_millis(1));
+        assert!(report.to_json().contains("\"a \\\"quoted\\\"\\\\phase\\n\": 1.000"));
+    }
+}
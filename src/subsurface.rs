@@ -0,0 +1,175 @@
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::geometry::Line;
+use crate::math::{RayCastHit, Vector};
+use crate::scene::Scene;
+use crate::spectrum::{unbounded_for, Wavelengths};
+use crate::stats::RenderStats;
+
+// scattering events before the light of a walk counts as absorbed
+const MAX_WALK_STEPS: usize = 64;
+
+// light entering the surface and scattering around inside of it before leaving it somewhere
+// else, like in skin, wax, marble or milk. the mean free path is how far light travels between
+// scattering events per channel, in units of scale. the primitives have to be closed solids
+#[derive(Debug, Clone, Copy)]
+pub struct SubsurfaceScattering {
+    pub mean_free_path: Color,
+    pub scale: f32,
+}
+
+// where a random walk came out of the surface. the normal points outwards, throughput is the
+// share of the light that made it from there to the entry point
+pub struct WalkExit {
+    pub point: Vector,
+    pub normal: Vector,
+    pub throughput: Color,
+}
+
+impl SubsurfaceScattering {
+    // single scattering albedo and extinction of the medium inside, chosen so that the
+    // surface as a whole reflects albedo
+    pub fn coefficients(&self, albedo: &Color, spectral: Option<&Wavelengths>) -> (Color, Color) {
+        let mean_free_path = unbounded_for(&self.mean_free_path, spectral) * self.scale;
+        let extinction = Color::new(
+            1.0 / mean_free_path.r.max(1e-4),
+            1.0 / mean_free_path.g.max(1e-4),
+            1.0 / mean_free_path.b.max(1e-4),
+        );
+        let scattering = Color::new(scattering_albedo(albedo.r), scattering_albedo(albedo.g), scattering_albedo(albedo.b));
+        (scattering, extinction)
+    }
+
+    // volumetric random walk from the point where light enters the surface with the outward
+    // normal. every step picks a channel to sample its distance, the throughput is divided by
+    // the pdf averaged over all channels so the others stay unbiased. none when the walk is
+    // absorbed or leaks out of an open surface
+    pub fn random_walk(&self, point: &Vector, normal: &Vector, albedo: &Color, scene: &Scene, spectral: Option<&Wavelengths>, stats: &mut RenderStats) -> Option<WalkExit> {
+        let (scattering, extinction) = self.coefficients(albedo, spectral);
+        let channels = [extinction.r, extinction.g, extinction.b];
+        let mut point = *point;
+        let mut direction = cosine_direction(&-*normal);
+        let mut throughput = Color::white();
+        for _ in 0..MAX_WALK_STEPS {
+            let sigma = channels[((rand::random::<f32>() * 3.0) as usize).min(2)];
+            let distance = -(1.0 - rand::random::<f32>()).ln() / sigma;
+            let ray = Line::new(point + direction * 0.01, direction);
+            let hit = closest_hit(&ray, scene, stats)?;
+            let hit_point = hit.unwrap().0;
+            let hit_distance = ray.point.distance(&hit_point);
+
+            if hit_distance <= distance {
+                // left the surface, with the probability of getting this far
+                let transmittance = beer_lambert(&extinction, hit_distance);
+                throughput = throughput * transmittance / transmittance.brightness();
+                let mut normal = hit.normal?;
+                if normal.dot(&direction) < 0.0 {
+                    normal = -normal;
+                }
+                return Some(WalkExit { point: hit_point, normal, throughput });
+            }
+            let transmittance = beer_lambert(&extinction, distance);
+            let pdf = (extinction * transmittance).brightness();
+            throughput = throughput * scattering * extinction * transmittance / pdf;
+            point = ray.point + direction * distance;
+            direction = sphere_direction();
+        }
+        None
+    }
+}
+
+// single scattering albedo of a medium whose multiple scattering adds up to albedo, from
+// "Practical and Controllable Subsurface Scattering for Production Path Tracing" (Chiang,
+// Kutz, Burley 2016)
+pub fn scattering_albedo(albedo: f32) -> f32 {
+    let a = albedo.clamp(0.0, 0.999);
+    1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+}
+
+fn closest_hit(ray: &Line, scene: &Scene, stats: &mut RenderStats) -> Option<RayCastHit> {
+    stats.intersection_tests += scene.primitives.len() as u64;
+    let mut closest: Option<(RayCastHit, f32)> = None;
+    for primitive in scene.primitives.iter() {
+        let hit = primitive.intersect(ray);
+        if hit.is_none() {
+            continue;
+        }
+        let to_hit = hit.unwrap().0 - ray.point;
+        if to_hit.dot(&ray.direction) < 0.0 {
+            continue;
+        }
+        let distance = to_hit.length();
+        if closest.as_ref().is_none_or(|(_, closest_distance)| distance < *closest_distance) {
+            closest = Some((hit, distance));
+        }
+    }
+    closest.map(|(hit, _)| hit)
+}
+
+fn beer_lambert(extinction: &Color, distance: f32) -> Color {
+    Color::new((-extinction.r * distance).exp(), (-extinction.g * distance).exp(), (-extinction.b * distance).exp())
+}
+
+// cosine weighted around n
+fn cosine_direction(n: &Vector) -> Vector {
+    let (t, b) = n.orthonormal_basis();
+    let (u1, u2) = (rand::random::<f32>(), rand::random::<f32>());
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    (t * (r * phi.cos()) + b * (r * phi.sin()) + *n * (1.0 - u1).sqrt())._normalize()
+}
+
+// uniform over the sphere, the phase function of isotropic scattering
+fn sphere_direction() -> Vector {
+    let z = 1.0 - 2.0 * rand::random::<f32>();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rand::random::<f32>();
+    Vector::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Sphere;
+
+    #[test]
+    fn scattering_albedo_test() {
+        assert!(scattering_albedo(0.0).abs() < 1e-4);
+        assert!(scattering_albedo(1.0) > 0.999);
+        // multiple scattering needs a higher single scattering albedo for the same brightness
+        for albedo in [0.2, 0.5, 0.8] {
+            assert!(scattering_albedo(albedo) > albedo);
+        }
+    }
+
+    #[test]
+    fn random_walk_test() {
+        let mut scene = Scene::new();
+        scene.add_primitive(Box::new(Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0)), 0);
+        let scattering = SubsurfaceScattering { mean_free_path: Color::new(1.0, 0.5, 0.25), scale: 0.1 };
+        let (entry, normal) = (Vector::new(0.0, 0.0, 1.0), Vector::new(0.0, 0.0, 1.0));
+        let mut stats = RenderStats::new();
+        // white, nothing is absorbed: the walks come out again with all of their light, unless
+        // they run out of steps
+        let mut exits = 0;
+        for _ in 0..1000 {
+            if let Some(exit) = scattering.random_walk(&entry, &normal, &Color::white(), &scene, None, &mut stats) {
+                assert!((exit.point.length() - 1.0).abs() < 1e-3);
+                assert!(exit.normal.dot(&exit.point) > 0.0);
+                exits += 1;
+            }
+        }
+        assert!(exits > 750, "{}", exits);
+        // darker albedos lose light on the way
+        let mut light = 0.0;
+        for _ in 0..500 {
+            if let Some(exit) = scattering.random_walk(&entry, &normal, &Color::new(0.5, 0.5, 0.5), &scene, None, &mut stats) {
+                light += exit.throughput.brightness() / 500.0;
+            }
+        }
+        assert!(light > 0.2 && light < 0.8, "{}", light);
+        // every step of a walk tests the sphere
+        assert!(stats.intersection_tests >= 1500);
+    }
+}
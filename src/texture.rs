@@ -0,0 +1,246 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use image::ImageBuffer;
+
+use crate::color::Color;
+use crate::math::noise::{fbm, perlin, simplex, turbulence, worley};
+use crate::math::Vector;
+use crate::mipmap::{Footprint, MipMap, TextureFilter};
+
+// coordinates a texture is evaluated at. uv is (u, v, 0), object space is the hit before
+// the transform of an Instance
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureSpace {
+    Uv,
+    World,
+    Object,
+}
+
+// the hit a texture is evaluated for. the footprints are how much uv and position change
+// towards the neighbouring pixels, known for rays with differentials
+#[derive(Debug, Clone, Copy)]
+pub struct TextureInput {
+    pub uv: Option<(f32, f32)>,
+    pub position: Vector,
+    pub object_position: Vector,
+    pub footprint: Option<Footprint>,
+    pub position_footprint: Option<(Vector, Vector)>,
+}
+
+impl TextureInput {
+    pub fn new(uv: Option<(f32, f32)>, position: Vector, object_position: Option<Vector>) -> TextureInput {
+        TextureInput {
+            uv,
+            position,
+            object_position: object_position.unwrap_or(position),
+            footprint: None,
+            position_footprint: None,
+        }
+    }
+
+    pub fn with_footprints(mut self, footprint: Option<Footprint>, position_footprint: Option<(Vector, Vector)>) -> TextureInput {
+        self.footprint = footprint;
+        self.position_footprint = position_footprint;
+        self
+    }
+
+    // footprint in the coordinates of a space, object space uses the world footprint
+    fn derivatives(&self, space: TextureSpace) -> Option<(Vector, Vector)> {
+        match space {
+            TextureSpace::Uv => self.footprint.map(|f| (Vector::new(f.dx.0, f.dx.1, 0.0), Vector::new(f.dy.0, f.dy.1, 0.0))),
+            TextureSpace::World | TextureSpace::Object => self.position_footprint,
+        }
+    }
+
+    // primitives without uv are at the origin of uv space
+    pub fn point(&self, space: TextureSpace) -> Vector {
+        match space {
+            TextureSpace::Uv => self.uv.map(|(u, v)| Vector::new(u, v, 0.0)).unwrap_or(Vector::new(0.0, 0.0, 0.0)),
+            TextureSpace::World => self.position,
+            TextureSpace::Object => self.object_position,
+        }
+    }
+}
+
+// procedural or image texture, built as a tree of patterns and operations like Sdf.
+// patterns repeat once per unit, scale them to the size they should have. textures are
+// looked up in uv space unless they are wrapped in a Space, which has to be the outermost
+// operation as it replaces the point. scalar patterns are grey
+#[derive(Debug, Clone)]
+pub enum Texture {
+    Constant(Color),
+    // repeats outside of 0..1, filtered over the footprint of the ray
+    Image(Arc<MipMap>),
+    // unit cubes alternating between the two textures
+    Checker { even: Box<Texture>, odd: Box<Texture> },
+    Perlin,
+    Simplex,
+    Fbm { octaves: u32, lacunarity: f32, gain: f32 },
+    Turbulence { octaves: u32 },
+    // distance to the closest feature point
+    Worley,
+    // a random color for every cell
+    Voronoi,
+    // bands along x, bent by turbulence
+    Marble { octaves: u32, distortion: f32 },
+    // rings around the y axis, wobbled by noise
+    Wood { rings: f32, distortion: f32 },
+    // 0 at the origin to 1 at direction, the length of direction sets the width
+    Gradient { direction: Vector },
+    // cells colored by their coordinates with white lines, to check uv layouts
+    UvGrid { cells: f32, line_width: f32 },
+    Space { space: TextureSpace, texture: Box<Texture> },
+    // the point is scaled first, then offset
+    Transform { scale: Vector, offset: Vector, texture: Box<Texture> },
+    // from a to b by the brightness of factor
+    Mix { a: Box<Texture>, b: Box<Texture>, factor: Box<Texture> },
+}
+
+impl Texture {
+    pub fn image(image: &ImageBuffer<image::Rgb<u8>, Vec<u8>>, filter: TextureFilter) -> Texture {
+        Texture::Image(Arc::new(MipMap::new(image).with_filter(filter).with_repeat(true)))
+    }
+
+    pub fn checker(even: Texture, odd: Texture) -> Texture {
+        Texture::Checker { even: Box::new(even), odd: Box::new(odd) }
+    }
+
+    pub fn mix(a: Texture, b: Texture, factor: Texture) -> Texture {
+        Texture::Mix { a: Box::new(a), b: Box::new(b), factor: Box::new(factor) }
+    }
+
+    pub fn in_space(self, space: TextureSpace) -> Texture {
+        Texture::Space { space, texture: Box::new(self) }
+    }
+
+    // pattern gets `scale` times smaller
+    pub fn scaled(self, scale: f32) -> Texture {
+        Texture::Transform { scale: Vector::new(scale, scale, scale), offset: Vector::new(0.0, 0.0, 0.0), texture: Box::new(self) }
+    }
+
+    pub fn offset(self, offset: Vector) -> Texture {
+        Texture::Transform { scale: Vector::new(1.0, 1.0, 1.0), offset, texture: Box::new(self) }
+    }
+
+    // colors a scalar pattern, from at 0 to to at 1
+    pub fn ramp(self, from: Color, to: Color) -> Texture {
+        Texture::mix(Texture::Constant(from), Texture::Constant(to), self)
+    }
+
+    pub fn value(&self, input: &TextureInput) -> Color {
+        self.evaluate_filtered(&input.point(TextureSpace::Uv), input.derivatives(TextureSpace::Uv), input)
+    }
+
+    // brightness, for textures driving a single number
+    pub fn scalar(&self, input: &TextureInput) -> f32 {
+        self.value(input).brightness()
+    }
+
+    // the texture at point p of its space, shader nodes use it to move textures around.
+    // images are not filtered, the footprint of p is unknown
+    pub fn evaluate(&self, p: &Vector, input: &TextureInput) -> Color {
+        self.evaluate_filtered(p, None, input)
+    }
+
+    // derivatives are the footprint of the ray in the space of p
+    fn evaluate_filtered(&self, p: &Vector, derivatives: Option<(Vector, Vector)>, input: &TextureInput) -> Color {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Image(image) => {
+                let footprint = derivatives.map(|(dx, dy)| Footprint { dx: (dx.x, dx.y), dy: (dy.x, dy.y) });
+                image.sample(p.x, p.y, footprint.as_ref())
+            }
+            Texture::Checker { even, odd } => {
+                let parity = p.x.floor() as i64 + p.y.floor() as i64 + p.z.floor() as i64;
+                if parity.rem_euclid(2) == 0 { even.evaluate_filtered(p, derivatives, input) } else { odd.evaluate_filtered(p, derivatives, input) }
+            }
+            Texture::Perlin => pattern(0.5 + 0.5 * perlin(p)),
+            Texture::Simplex => pattern(0.5 + 0.5 * simplex(p)),
+            Texture::Fbm { octaves, lacunarity, gain } => pattern(0.5 + 0.5 * fbm(p, *octaves, *lacunarity, *gain)),
+            Texture::Turbulence { octaves } => pattern(turbulence(p, *octaves)),
+            Texture::Worley => pattern(worley(p).0),
+            Texture::Voronoi => {
+                let cell = worley(p).1;
+                Color::new((cell & 0x3ff) as f32 / 1023.0, ((cell >> 10) & 0x3ff) as f32 / 1023.0, ((cell >> 20) & 0x3ff) as f32 / 1023.0)
+            }
+            Texture::Marble { octaves, distortion } => pattern(0.5 + 0.5 * ((p.x + distortion * turbulence(p, *octaves)) * PI).sin()),
+            Texture::Wood { rings, distortion } => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                pattern((radius * rings + distortion * perlin(p)).rem_euclid(1.0))
+            }
+            Texture::Gradient { direction } => pattern(p.dot(direction) / direction.length_squared()),
+            Texture::UvGrid { cells, line_width } => {
+                let (x, y) = (p.x * cells, p.y * cells);
+                let on_line = |c: f32| c.rem_euclid(1.0) < *line_width || c.rem_euclid(1.0) > 1.0 - line_width;
+                if on_line(x) || on_line(y) {
+                    Color::white()
+                } else {
+                    Color::new(x.floor().rem_euclid(*cells) / cells, y.floor().rem_euclid(*cells) / cells, 0.5)
+                }
+            }
+            Texture::Space { space, texture } => texture.evaluate_filtered(&input.point(*space), input.derivatives(*space), input),
+            Texture::Transform { scale, offset, texture } => {
+                let stretch = |v: &Vector| Vector::new(v.x * scale.x, v.y * scale.y, v.z * scale.z);
+                texture.evaluate_filtered(&(stretch(p) + *offset), derivatives.map(|(dx, dy)| (stretch(&dx), stretch(&dy))), input)
+            }
+            Texture::Mix { a, b, factor } => {
+                let t = factor.evaluate_filtered(p, derivatives, input).brightness().clamp(0.0, 1.0);
+                let mut color = a.evaluate_filtered(p, derivatives, input);
+                color.blend(&b.evaluate_filtered(p, derivatives, input), t);
+                color
+            }
+        }
+    }
+}
+
+// scalar patterns are grey and stay in 0..1
+fn pattern(value: f32) -> Color {
+    Color::grey(value.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_test() {
+        let input = TextureInput::new(Some((0.25, 0.75)), Vector::new(1.5, 0.5, 0.5), None);
+        let checker = Texture::checker(Texture::Constant(Color::black()), Texture::Constant(Color::white()));
+        // uv (0.25, 0.75) is in the first cell, scaled by 2 it's in (0, 1)
+        assert!(checker.value(&input).is_black());
+        assert_eq!(checker.clone().scaled(2.0).scalar(&input), 1.0);
+        // world (1.5, 0.5, 0.5) is in cell (1, 0, 0)
+        assert_eq!(checker.clone().in_space(TextureSpace::World).scalar(&input), 1.0);
+        assert!(checker.offset(Vector::new(1.0, 0.0, 0.0)).in_space(TextureSpace::World).value(&input).is_black());
+
+        let gradient = Texture::Gradient { direction: Vector::new(2.0, 0.0, 0.0) }.in_space(TextureSpace::World);
+        assert!((gradient.scalar(&input) - 0.75).abs() < 1e-5);
+        let ramp = Texture::Gradient { direction: Vector::new(1.0, 0.0, 0.0) }.ramp(Color::new(0.0, 0.0, 1.0), Color::new(1.0, 0.0, 0.0));
+        let color = ramp.value(&input);
+        assert!((color.r - 0.25).abs() < 1e-5 && (color.b - 0.75).abs() < 1e-5);
+
+        // every pattern stays in 0..1
+        let patterns = [
+            Texture::Perlin,
+            Texture::Simplex,
+            Texture::Fbm { octaves: 4, lacunarity: 2.0, gain: 0.5 },
+            Texture::Turbulence { octaves: 4 },
+            Texture::Worley,
+            Texture::Voronoi,
+            Texture::Marble { octaves: 4, distortion: 5.0 },
+            Texture::Wood { rings: 8.0, distortion: 0.5 },
+            Texture::UvGrid { cells: 8.0, line_width: 0.05 },
+        ];
+        for pattern in patterns {
+            let pattern = pattern.scaled(7.3).in_space(TextureSpace::World);
+            for i in 0..100 {
+                let input = TextureInput::new(None, Vector::new(i as f32 * 0.37, i as f32 * -0.21, i as f32 * 0.05), None);
+                let color = pattern.value(&input);
+                for channel in [color.r, color.g, color.b] {
+                    assert!((0.0..=1.0).contains(&channel), "{:?}: {}", pattern, color);
+                }
+            }
+        }
+    }
+}